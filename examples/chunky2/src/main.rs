@@ -7,9 +7,9 @@
 
 extern crate monotron_app;
 
+use monotron_app::line_editor;
 use monotron_app::Host;
 
-use core::fmt::Write;
 use heapless::consts::*;
 
 const SCROLL_ROW: u8 = 10;
@@ -104,7 +104,8 @@ pub extern "C" fn monotron_main() -> i32 {
                     return 0;
                 }
                 b'e' | b'E' => {
-                    edit_string(&mut buf);
+                    Host::puts(b"\x1BW\x1Bb\x1BZ");
+                    let _ = line_editor::edit_string(&mut buf, None);
                     Host::puts(b"\x1BW\x1Bk\x1BZ");
                     msg_start = 47;
                 }
@@ -116,41 +117,6 @@ pub extern "C" fn monotron_main() -> i32 {
     }
 }
 
-fn edit_string<T>(buf: &mut heapless::String<T>)
-where
-    T: heapless::ArrayLength<u8>,
-{
-    Host::set_cursor_visible(true);
-    loop {
-        Host::puts(b"\x1BW\x1Bb\x1BZ");
-        write!(Host, "{}", buf).unwrap();
-        while !Host::kbhit() {
-            Host::wfvbi();
-        }
-        match Host::readc() {
-            27 => {
-                return;
-            }
-            b'\r' => {
-                return;
-            }
-            b'\n' => {
-                return;
-            }
-            8 => {
-                let _ = buf.pop();
-            }
-            ch if ch < 127 => {
-                let _ = buf.push(ch as char);
-            }
-            _ => {
-                Host::puts(b"Ignoring char");
-                // Ignore
-            }
-        }
-    }
-}
-
 struct Fire {
     seed: u32,
     buffer: [u8; Fire::FLAME_BUFFER_LEN],
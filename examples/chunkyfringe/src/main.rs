@@ -8,6 +8,7 @@
 extern crate monotron_app;
 
 use core::fmt::Write;
+use monotron_app::canvas::{Canvas, TextStyle};
 use monotron_app::*;
 
 #[cfg(not(target_os = "none"))]
@@ -18,31 +19,6 @@ pub fn main() {
     std::process::exit(r);
 }
 
-struct ChunkyTextWriter {
-    x: u8,
-    y: u8,
-}
-
-impl core::fmt::Write for ChunkyTextWriter {
-    fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        for b in s.chars().map(|c| if c.is_ascii() { c as u8 } else { b'?' }) {
-            let char_lines = &BIG_FONT[b as usize];
-            for (y, line) in char_lines.iter().enumerate() {
-                for x in (1..8).rev() {
-                    Host::move_cursor(Row(y as u8), Col(x));
-                    if (line & (1 << x)) != 0 {
-                        set_pixel(self.x + x as u8, self.y + y as u8);
-                    } else {
-                        clear_pixel(self.x + x as u8, self.y + y as u8);
-                    }
-                }
-            }
-            self.x += 6;
-        }
-        Ok(())
-    }
-}
-
 #[no_mangle]
 pub extern "C" fn monotron_main() -> i32 {
     Host::set_cursor_visible(false);
@@ -59,7 +35,11 @@ pub extern "C" fn monotron_main() -> i32 {
         for _ in 0..(48 * 6) {
             write!(Host, " ").unwrap();
         }
-        write!(ChunkyTextWriter { x: 0, y: 11 }, "Chunky Fringe").unwrap();
+        let mut canvas = Canvas::new();
+        // The font's glyphs are in bits 1 to 7, and overlap the one before
+        let style = TextStyle::new(6).with_columns(0xFE).opaque();
+        canvas.text(0, 11, b"Chunky Fringe", style, |ch| &BIG_FONT[ch as usize][..]);
+        canvas.flush();
 
         Host::move_cursor(Row(9), Col(0));
         write!(Host, "\u{001B}k\u{001B}G").unwrap();
@@ -152,53 +132,6 @@ fn top_line() {
     .unwrap();
 }
 
-fn set_pixel(x: u8, y: u8) {
-    draw_pixel(x, y, true)
-}
-
-fn clear_pixel(x: u8, y: u8) {
-    draw_pixel(x, y, false)
-}
-
-fn draw_pixel(x: u8, y: u8, is_set: bool) {
-    // find the cell this pixel is in
-    let col = Col(x / 2);
-    let row = Row(y / 3);
-    let sub_col = x % 2;
-    let sub_row = y % 3;
-    let sub_cell = (sub_row * 2) + sub_col;
-    let (ch, _attr) = Host::read_char_at(row, col);
-    let bits = decode_char(ch);
-    let new_bits = if is_set {
-        bits | (1 << sub_cell)
-    } else {
-        bits & !(1 << sub_cell)
-    };
-    let new_ch = encode_char(new_bits);
-    Host::move_cursor(row, col);
-    Host::putchar(new_ch);
-}
-
-fn decode_char(ch: u8) -> u8 {
-    // 128 .. 159
-    // 192 .. 223
-    if ch >= 128 && ch <= 159 {
-        ch - 128
-    } else if ch >= 192 && ch <= 223 {
-        (ch - 192) + 32
-    } else {
-        0
-    }
-}
-
-fn encode_char(bits: u8) -> u8 {
-    if bits < 32 {
-        bits + 128
-    } else {
-        (bits - 32) + 192
-    }
-}
-
 static BIG_FONT: [[u8; 12]; 256] = [
     [
         0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
@@ -7,13 +7,23 @@
 
 extern crate monotron_app;
 
-use monotron_app::Host;
+use monotron_app::line_editor;
+use monotron_app::text_buffer::TextBuffer;
+use monotron_app::{Attr, Colour, DoubleHeight, Host};
 
-use core::fmt::Write;
 use heapless::consts::*;
 
 const SCROLL_ROW: u8 = 10;
 
+/// The title, as the red part and the blue part of each row.
+const LOGO: [(&[u8], &[u8]); 5] = [
+    (b" \xB2\xB2\xB2  \xB2   \xB2 \xB2 \xB2\xB2\xB2  \xB2 \xB2\xB2\xB2\xB2 \xB2\xB2\xB2\xB2\xB2 ", b"\xB2\xB2\xB2\xB2 \xB2\xB2\xB2\xB2 \xB2 \xB2\xB2\xB2\xB2"),
+    (b"\xB2   \xB2  \xB2 \xB2  \xB2 \xB2  \xB2 \xB2    \xB2 \xB2     ", b"   \xB2 \xB2  \xB2 \xB2 \xB2  \xB2"),
+    (b"\xB2   \xB2   \xB2   \xB2 \xB2  \xB2 \xB2   \xB2  \xB2\xB2\xB2\xB2\xB2 ", b"\xB2\xB2\xB2\xB2 \xB2  \xB2 \xB2 \xB2\xB2\xB2\xB2"),
+    (b"\xB2   \xB2  \xB2 \xB2  \xB2 \xB2  \xB2 \xB2  \xB2   \xB2     ", b"\xB2    \xB2  \xB2 \xB2    \xB2"),
+    (b" \xB2\xB2\xB2  \xB2   \xB2 \xB2 \xB2\xB2\xB2  \xB2 \xB2\xB2\xB2\xB2 \xB2\xB2\xB2\xB2\xB2 ", b"\xB2\xB2\xB2\xB2 \xB2\xB2\xB2\xB2 \xB2 \xB2\xB2\xB2\xB2"),
+];
+
 #[cfg(not(target_os = "none"))]
 pub fn main() {
     monotron_app::Host::init();
@@ -29,18 +39,19 @@ pub extern "C" fn monotron_main() -> i32 {
     let mut f = Fire::new();
     let mut msg_start = 47isize;
     let mut frames = [0, 0, 0, 0, 1].iter().cycle();
-    Host::puts(b"\x1BW\x1Bk\x1BZ");
+    let codepage = Host::codepage();
+    let mut screen = start_screen();
     loop {
         Host::wfvbi();
-        Host::set_cursor_visible(false);
-        Host::move_cursor(monotron_app::Row(0), monotron_app::Col(0));
-        Host::puts(b"\x1BR \xB2\xB2\xB2  \xB2   \xB2 \xB2 \xB2\xB2\xB2  \xB2 \xB2\xB2\xB2\xB2 \xB2\xB2\xB2\xB2\xB2 \x1BB\xB2\xB2\xB2\xB2 \xB2\xB2\xB2\xB2 \xB2 \xB2\xB2\xB2\xB2");
-        Host::puts(b"\x1BR\xB2   \xB2  \xB2 \xB2  \xB2 \xB2  \xB2 \xB2    \xB2 \xB2     \x1BB   \xB2 \xB2  \xB2 \xB2 \xB2  \xB2");
-        Host::puts(b"\x1BR\xB2   \xB2   \xB2   \xB2 \xB2  \xB2 \xB2   \xB2  \xB2\xB2\xB2\xB2\xB2 \x1BB\xB2\xB2\xB2\xB2 \xB2  \xB2 \xB2 \xB2\xB2\xB2\xB2");
-        Host::puts(b"\x1BR\xB2   \xB2  \xB2 \xB2  \xB2 \xB2  \xB2 \xB2  \xB2   \xB2     \x1BB\xB2    \xB2  \xB2 \xB2    \xB2");
-        Host::puts(b"\x1BR \xB2\xB2\xB2  \xB2   \xB2 \xB2 \xB2\xB2\xB2  \xB2 \xB2\xB2\xB2\xB2 \xB2\xB2\xB2\xB2\xB2 \x1BB\xB2\xB2\xB2\xB2 \xB2\xB2\xB2\xB2 \xB2 \xB2\xB2\xB2\xB2");
+        screen.move_cursor(monotron_app::Row(0), monotron_app::Col(0));
+        for (red, blue) in LOGO.iter() {
+            screen.set_foreground(Colour::Red);
+            screen.puts(red);
+            screen.set_foreground(Colour::Blue);
+            screen.puts(blue);
+        }
 
-        Host::puts(b"\x1BY");
+        screen.set_foreground(Colour::Yellow);
 
         // If we're over on the right somewhere, move across to the correct place
         let col_start = if msg_start > 0 {
@@ -48,59 +59,50 @@ pub extern "C" fn monotron_main() -> i32 {
         } else {
             0
         };
-        Host::move_cursor(monotron_app::Row(SCROLL_ROW), monotron_app::Col(col_start));
-        Host::puts(b"\x1B^");
-        // Number of bytes to skip at start of scroll-text
+        // Number of chars to skip at start of scroll-text
         let skip = if msg_start < 0 {
             msg_start.abs() as usize
         } else {
             0
         };
-        let mut col = col_start;
-        for b in buf.bytes().skip(skip) {
-            Host::putchar(b);
-            col += 1;
-            if col == 48 {
-                // Stop when screen is full
-                break;
+        // The same text on both rows, as they show the top and bottom halves
+        // of it
+        for row in SCROLL_ROW..=SCROLL_ROW + 1 {
+            screen.move_cursor(monotron_app::Row(row), monotron_app::Col(col_start));
+            let mut col = col_start;
+            for ch in buf.chars().skip(skip) {
+                screen.putchar(codepage.encode(ch));
+                col += 1;
+                if col == 48 {
+                    // Stop when screen is full
+                    break;
+                }
             }
-        }
-        while col < 48 {
-            Host::putchar(b' ');
-            col += 1;
-        }
-        Host::move_cursor(monotron_app::Row(SCROLL_ROW + 1), monotron_app::Col(col_start));
-        Host::puts(b"\x1Bv");
-        col = col_start;
-        for b in buf.bytes().skip(skip) {
-            Host::putchar(b);
-            col += 1;
-            if col == 48 {
-                // Stop when screen is full
-                break;
+            while col < 48 {
+                screen.putchar(b' ');
+                col += 1;
             }
         }
-        while col < 48 {
-            Host::putchar(b' ');
-            col += 1;
-        }
         // Shift left one
         let sub = frames.next().unwrap();
         msg_start -= sub;
         // Re-start scrolling next time around
-        if msg_start < -(buf.len() as isize) {
+        if msg_start < -(buf.chars().count() as isize) {
             msg_start = 47;
         }
 
-        f.draw_fire();
+        f.draw_fire(&mut screen);
+        screen.flush();
         if Host::kbhit() {
             match Host::readc() {
                 b'q' | b'Q' => {
                     return 0;
                 }
                 b'e' | b'E' => {
-                    edit_string(&mut buf);
+                    Host::puts(b"\x1BW\x1BB\x1BZ");
+                    let _ = line_editor::edit_string(&mut buf, None);
                     msg_start = 47;
+                    screen = start_screen();
                 }
                 _ => {
                     // Ignore
@@ -110,36 +112,16 @@ pub extern "C" fn monotron_main() -> i32 {
     }
 }
 
-fn edit_string<T>(buf: &mut heapless::String<T>) where T: heapless::ArrayLength<u8> {
-    Host::set_cursor_visible(true);
-    loop {
-        Host::puts(b"\x1BW\x1BB\x1BZ");
-        write!(Host, "{}", buf).unwrap();
-        while !Host::kbhit() {
-            Host::wfvbi();
-        }
-        match Host::readc() {
-            27 => {
-                return;
-            }
-            b'\r' => {
-                return;
-            }
-            b'\n' => {
-                return;
-            }
-            8 => {
-                let _ = buf.pop();
-            }
-            ch if ch < 127 => {
-                let _ = buf.push(ch as char);
-            }
-            _ => {
-                Host::puts(b"Ignoring char");
-                // Ignore
-            }
-        }
-    }
+/// Clear the screen, and get it ready for the scrolling text. Returns a
+/// buffer which matches the blank screen.
+fn start_screen() -> TextBuffer {
+    Host::puts(b"\x1BW\x1Bk\x1BZ");
+    Host::set_cursor_visible(false);
+    Host::move_cursor(monotron_app::Row(SCROLL_ROW), monotron_app::Col(0));
+    Host::set_double_height(DoubleHeight::Top);
+    Host::move_cursor(monotron_app::Row(SCROLL_ROW + 1), monotron_app::Col(0));
+    Host::set_double_height(DoubleHeight::Bottom);
+    TextBuffer::new(Attr::new(Colour::White, Colour::Black))
 }
 
 struct Fire {
@@ -162,7 +144,7 @@ impl Fire {
 
     /// Draws a flame effect.
     /// Based on https://gist.github.com/msimpson/1096950.
-    fn draw_fire(&mut self) {
+    fn draw_fire(&mut self, screen: &mut TextBuffer) {
         const CHARS: [u8; 10] = [
             b' ',
             b'.',
@@ -175,7 +157,7 @@ impl Fire {
             b'#',
             b'$',
         ];
-        Host::move_cursor(monotron_app::Row(16), monotron_app::Col(0));
+        screen.move_cursor(monotron_app::Row(16), monotron_app::Col(0));
         // Seed the fire on the last line
         for _i in 0..5 {
             let idx = (Self::WIDTH*(Self::HEIGHT-1)) + self.random_up_to(Self::WIDTH as u32) as usize;
@@ -185,23 +167,22 @@ impl Fire {
         for i in 0..Self::SIZE {
             self.buffer[i] = (self.buffer[i] + self.buffer[i+1] + self.buffer[i+Self::WIDTH] + self.buffer[i+Self::WIDTH+1]) / 4;
             if self.buffer[i] > 15 {
-                Host::puts(b"\x1BB");
+                screen.set_foreground(Colour::Blue);
             } else if self.buffer[i] > 9 {
-                Host::puts(b"\x1BR");
+                screen.set_foreground(Colour::Red);
             } else if self.buffer[i] > 4 {
-                Host::puts(b"\x1BY");
+                screen.set_foreground(Colour::Yellow);
             } else {
-                Host::puts(b"\x1BW");
+                screen.set_foreground(Colour::White);
             }
             let glyph = if self.buffer[i] > 9 {
                 CHARS[9]
             } else {
                 CHARS[self.buffer[i] as usize]
             };
-            // Don't print last glyph - makes page scroll!
-            if i < Self::SIZE - 1 {
-                Host::putchar(glyph);
-            }
+            // The screen doesn't send the last glyph, as it makes the page
+            // scroll
+            screen.putchar(glyph);
         }
     }
 
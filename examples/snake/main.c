@@ -58,99 +58,67 @@ struct {
     int j;
 } snake_tail;
 
-typedef struct music_event_t {
-    uint32_t frame;
-    uint32_t frequency_centihz;
-    uint8_t volume;
-    waveform_t waveform;
-} music_event_t;
-
-typedef struct track_t {
-    channel_t channel;
-    const music_event_t* p_events;
-    size_t num_events;
-    size_t current_event;
-    uint32_t wrap_frame_at;
-    uint32_t current_frame;
-} track_t;
-
 static const music_event_t TRACK0_EVENTS[] = {
-    { 0, Note_C2, 100, WAVEFORM_SAWTOOTH },
-    { 7, Note_E2, 100, WAVEFORM_SAWTOOTH },
-    { 15, Note_G2, 100, WAVEFORM_SAWTOOTH },
-    { 22, Note_C3, 100, WAVEFORM_SAWTOOTH },
-};
-
-static track_t TRACK0 = {
-    .channel = CHANNEL_0,
-    .p_events = TRACK0_EVENTS,
-    .num_events = ELEMOF(TRACK0_EVENTS),
-    .current_event = 0,
-    .wrap_frame_at = 30,
-    .current_frame = 0,
+    { Note_C2, LENGTH_EIGHTH },
+    { Note_E2, LENGTH_EIGHTH },
+    { Note_G2, LENGTH_EIGHTH },
+    { Note_C3, LENGTH_EIGHTH },
 };
 
 static const music_event_t TRACK1_EVENTS[] = {
-    { 30, Note_C1, 255, WAVEFORM_NOISE },
-    { 32, 0, 0, WAVEFORM_NOISE },
-    { 75, Note_C1, 255, WAVEFORM_NOISE },
-    { 77, 0, 0, WAVEFORM_NOISE },
-    { 90, Note_C1, 255, WAVEFORM_NOISE },
-    { 92, 0, 0, WAVEFORM_NOISE },
-};
-
-static track_t TRACK1 = {
-    .channel = CHANNEL_1,
-    .p_events = TRACK1_EVENTS,
-    .num_events = ELEMOF(TRACK1_EVENTS),
-    .current_event = 0,
-    .wrap_frame_at = 120,
-    .current_frame = 0,
+    { 0, LENGTH_HALF },
+    { Note_C1, LENGTH_THIRTY_SECOND },
+    { 0, LENGTH_QUARTER * 3 - LENGTH_THIRTY_SECOND },
+    { Note_C1, LENGTH_THIRTY_SECOND },
+    { 0, LENGTH_QUARTER - LENGTH_THIRTY_SECOND },
+    { Note_C1, LENGTH_THIRTY_SECOND },
+    { 0, LENGTH_HALF - LENGTH_THIRTY_SECOND },
 };
 
 static const music_event_t TRACK2_EVENTS[] = {
-    { 0, Note_C5, 96, WAVEFORM_SQUARE },
-    { 0 + 40, 0, 0, WAVEFORM_SQUARE },
-    { 45, Note_A4, 96, WAVEFORM_SQUARE },
-    { 45 + 10, 0, 0, WAVEFORM_SQUARE },
-    { 60, Note_G4, 96, WAVEFORM_SQUARE },
-    { 60 + 15, 0, 0, WAVEFORM_SQUARE },
-    { 90, Note_F4, 96, WAVEFORM_SQUARE },
-    { 90 + 15, 0, 0, WAVEFORM_SQUARE },
-    { 120, Note_G4, 96, WAVEFORM_SQUARE },
-    { 120 + 15, 0, 0, WAVEFORM_SQUARE },
-    { 150, Note_F4, 96, WAVEFORM_SQUARE },
-    { 150 + 15, 0, 0, WAVEFORM_SQUARE },
-    { 180, Note_G4, 96, WAVEFORM_SQUARE },
-    { 180 + 15, 0, 0, WAVEFORM_SQUARE },
-    { 210, Note_B4, 96, WAVEFORM_SQUARE },
-    { 210 + 15, 0, 0, WAVEFORM_SQUARE },
-    { 240, Note_C5, 96, WAVEFORM_SQUARE },
-    { 240 + 40, 0, 0, WAVEFORM_SQUARE },
-    { 285, Note_A4, 96, WAVEFORM_SQUARE },
-    { 285 + 10, 0, 0, WAVEFORM_SQUARE },
-    { 300, Note_G4, 96, WAVEFORM_SQUARE },
-    { 300 + 15, 0, 0, WAVEFORM_SQUARE },
-    { 330, Note_F4, 96, WAVEFORM_SQUARE },
-    { 330 + 15, 0, 0, WAVEFORM_SQUARE },
-    { 360, Note_G4, 96, WAVEFORM_SQUARE },
-    { 360 + 15, 0, 0, WAVEFORM_SQUARE },
-    { 390, Note_B4, 96, WAVEFORM_SQUARE },
-    { 390 + 15, 0, 0, WAVEFORM_SQUARE },
-    { 420, Note_C5, 96, WAVEFORM_SQUARE },
-    { 420 + 45, 0, 0, WAVEFORM_SQUARE },
+    { Note_C5, LENGTH_HALF + LENGTH_TRIPLET(LENGTH_QUARTER) },
+    { 0, LENGTH_TRIPLET(LENGTH_EIGHTH) },
+    { Note_A4, LENGTH_TRIPLET(LENGTH_QUARTER) },
+    { 0, LENGTH_TRIPLET(LENGTH_EIGHTH) },
+    { Note_G4, LENGTH_QUARTER },
+    { 0, LENGTH_QUARTER },
+    { Note_F4, LENGTH_QUARTER },
+    { 0, LENGTH_QUARTER },
+    { Note_G4, LENGTH_QUARTER },
+    { 0, LENGTH_QUARTER },
+    { Note_F4, LENGTH_QUARTER },
+    { 0, LENGTH_QUARTER },
+    { Note_G4, LENGTH_QUARTER },
+    { 0, LENGTH_QUARTER },
+    { Note_B4, LENGTH_QUARTER },
+    { 0, LENGTH_QUARTER },
+    { Note_C5, LENGTH_HALF + LENGTH_TRIPLET(LENGTH_QUARTER) },
+    { 0, LENGTH_TRIPLET(LENGTH_EIGHTH) },
+    { Note_A4, LENGTH_TRIPLET(LENGTH_QUARTER) },
+    { 0, LENGTH_TRIPLET(LENGTH_EIGHTH) },
+    { Note_G4, LENGTH_QUARTER },
+    { 0, LENGTH_QUARTER },
+    { Note_F4, LENGTH_QUARTER },
+    { 0, LENGTH_QUARTER },
+    { Note_G4, LENGTH_QUARTER },
+    { 0, LENGTH_QUARTER },
+    { Note_B4, LENGTH_QUARTER },
+    { 0, LENGTH_QUARTER },
+    { Note_C5, LENGTH_QUARTER * 3 },
+    { 0, LENGTH_QUARTER },
 };
 
-static track_t TRACK2 = {
-    .channel = CHANNEL_2,
-    .p_events = TRACK2_EVENTS,
-    .num_events = ELEMOF(TRACK2_EVENTS),
-    .current_event = 0,
-    .wrap_frame_at = 480,
-    .current_frame = 0,
-};
+// A quarter note is 15 frames
+#define MUSIC_BPM 240
+
+// Instruments, set up by setup_instruments()
+#define INST_BASS 0
+#define INST_DRUM 1
+#define INST_LEAD 2
+#define INST_TICK 3
+#define INST_APPLE 4
+#define INST_FALL 5
 
-static bool music_playing = false;
 static unsigned char field[FIELD_W * FIELD_H];
 static unsigned int score;
 static unsigned int hiscore;
@@ -175,7 +143,8 @@ static void update_score(unsigned int score);
 static void splash_screen(void);
 static void initialize(void);
 static int update_snake(void);
-static void beep(uint32_t frequency, uint8_t frames, uint8_t volume);
+static void setup_instruments(void);
+static void beep(int instrument, uint32_t frequency, uint8_t frames, uint8_t volume);
 static void wait_frame(void);
 static void wait_note(void);
 static void update_sound(void);
@@ -447,7 +416,7 @@ static int update_snake(void) {
         pigfx_bgcol(BG_COLOR);
         pigfx_movecursor(FIELD_H + 1, 34);
         update_score(score);
-        beep(1000, 5, MAX_VOLUME);
+        beep(INST_APPLE, 1000, 5, MAX_VOLUME);
     } else {
         if (field[head_idx] != 0) {
             return 0;
@@ -490,9 +459,31 @@ static int update_snake(void) {
     return 1;
 }
 
+// Give the music and the beeps something better than a plain square wave
+static void setup_instruments(void) {
+    static const int8_t major_chord[] = { 0, 4, 7, 12 };
+
+    instrument_set_waveform(INST_BASS, WAVEFORM_SAWTOOTH);
+    instrument_set_envelope(INST_BASS, 0, 6, 128, 3);
+
+    instrument_set_waveform(INST_DRUM, WAVEFORM_NOISE);
+    instrument_set_envelope(INST_DRUM, 0, 2, 0, 0);
+
+    instrument_set_envelope(INST_LEAD, 2, 10, 160, 6);
+    instrument_set_vibrato(INST_LEAD, 12, 10, 20);
+
+    instrument_set_envelope(INST_TICK, 0, 0, 255, 1);
+
+    instrument_set_envelope(INST_APPLE, 0, 8, 96, 4);
+    instrument_set_arpeggio(INST_APPLE, major_chord, ELEMOF(major_chord), 1);
+
+    instrument_set_envelope(INST_FALL, 0, 0, 255, 10);
+    instrument_set_slide(INST_FALL, -2);
+}
+
 // Start a beep on Channel 0 (but don't wait for it to finish)
-static void beep(uint32_t frequency, uint8_t frames, uint8_t volume) {
-    play(frequency * 100, CHANNEL_0, WAVEFORM_SQUARE, volume);
+static void beep(int instrument, uint32_t frequency, uint8_t frames, uint8_t volume) {
+    sound_note_on(CHANNEL_0, instrument, frequency * 100, volume);
     sound_frames_remaining = frames;
 }
 
@@ -510,35 +501,18 @@ static void wait_note(void) {
     }
 }
 
-static void play_note(track_t* p_track) {
-    if (p_track->current_frame == p_track->wrap_frame_at) {
-        p_track->current_frame = 0;
-        p_track->current_event = 0;
-    }
-    const music_event_t* p_event = &p_track->p_events[p_track->current_event];
-    if (p_track->current_event < p_track->num_events) {
-        if (p_track->current_frame >= p_event->frame) {
-            // Play note
-            play(p_event->frequency_centihz, p_track->channel, p_event->waveform, p_event->volume);
-            // Move to next event
-            p_track->current_event++;
-        }
-    }
-    p_track->current_frame += 1;
-}
-
 // Stop the sound when we've heard enough
 static void update_sound(void) {
-    if (music_playing) {
-        // Play music here
-        play_note(&TRACK0);
-        play_note(&TRACK1);
-        play_note(&TRACK2);
-    } else if (sound_frames_remaining > 0) {
-        sound_frames_remaining--;
-        if (sound_frames_remaining == 0) {
-            play(0, CHANNEL_0, WAVEFORM_SQUARE, 0);
+    if (music_is_playing()) {
+        music_tick();
+    } else {
+        if (sound_frames_remaining > 0) {
+            sound_frames_remaining--;
+            if (sound_frames_remaining == 0) {
+                sound_note_off(CHANNEL_0);
+            }
         }
+        sound_tick();
     }
 
 }
@@ -576,21 +550,23 @@ static char get_input(void) {
 }
 
 static void start_music(void) {
-    music_playing = true;
-    TRACK0.current_frame = 0;
-    TRACK0.current_event = 0;
-    TRACK1.current_frame = 0;
-    TRACK1.current_event = 0;
-    TRACK2.current_frame = 0;
-    TRACK2.current_event = 0;
+    music_stop();
+    music_set_tempo(MUSIC_BPM);
+    music_set_track(CHANNEL_0, TRACK0_EVENTS, ELEMOF(TRACK0_EVENTS), WAVEFORM_SAWTOOTH, 100, true);
+    music_set_track(CHANNEL_1, TRACK1_EVENTS, ELEMOF(TRACK1_EVENTS), WAVEFORM_NOISE, 255, true);
+    music_set_track(CHANNEL_2, TRACK2_EVENTS, ELEMOF(TRACK2_EVENTS), WAVEFORM_SQUARE, 96, true);
+    music_set_instrument(CHANNEL_0, INST_BASS);
+    music_set_instrument(CHANNEL_1, INST_DRUM);
+    music_set_instrument(CHANNEL_2, INST_LEAD);
+    music_play();
 }
 
 static void game_over(void) {
     pigfx_movecursor(FIELD_H / 2, (FIELD_W - 10) / 2);
     pigfx_print("GAME OVER!");
-    beep(880, 30, MAX_VOLUME);
+    beep(INST_TICK, 880, 30, MAX_VOLUME);
     wait_note();
-    beep(440, 60, MAX_VOLUME);
+    beep(INST_FALL, 440, 60, MAX_VOLUME);
     wait_note();
 
     pigfx_movecursor((FIELD_H / 2) + 1, (FIELD_W - 18) / 2);
@@ -683,10 +659,7 @@ static bool game(void) {
     }
 
     // Stop the music
-    play(0, CHANNEL_0, WAVEFORM_SQUARE, 0);
-    play(0, CHANNEL_1, WAVEFORM_SQUARE, 0);
-    play(0, CHANNEL_2, WAVEFORM_SQUARE, 0);
-    music_playing = false;
+    music_stop();
 
     // Reset the screen
     for (int scanline = 0; scanline < 576; scanline++) {
@@ -768,7 +741,7 @@ static bool game(void) {
 
         // Only bip if nothing else playing
         if (sound_frames_remaining == 0) {
-            beep(1000, 2, MAX_VOLUME / 4);
+            beep(INST_TICK, 1000, 2, MAX_VOLUME / 4);
         }
 
     }
@@ -784,6 +757,7 @@ int main(int argc, const char** argv) {
 #endif
 
 int monotron_main(void) {
+    setup_instruments();
     while (game()) {
         // Carry on
     }
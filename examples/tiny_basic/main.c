@@ -323,7 +323,6 @@ static const unsigned char sorrymsg[]          = "Sorry!";
 static const unsigned char initmsg[]           = "TinyBasic Plus for Monotron " kVersion;
 static const unsigned char memorymsg[]         = " bytes free.";
 static const unsigned char breakmsg[]          = "break!";
-static const unsigned char backspacemsg[]      = "\b \b";
 
 static void ignore_blanks(void);
 static void scantable(const unsigned char *table);
@@ -344,7 +343,6 @@ static short int expr2(void);
 static short int expression(void);
 static void line_terminator(void);
 static unsigned char breakcheck(void);
-static int inchar();
 
 /***************************************************************************/
 static void ignore_blanks(void) {
@@ -495,37 +493,22 @@ static void printmsg(const unsigned char *msg) {
 
 /***************************************************************************/
 static void getln(char prompt) {
+    int len;
+    ptrdiff_t space;
     putchar(prompt);
     txtpos = program_end+sizeof(LINENUM);
-
-    while(1) {
-        char c = inchar();
-        switch(c) {
-        case NL:
-        //break;
-        case CR:
-            line_terminator();
-            // Terminate all strings with a NL
-            txtpos[0] = NL;
-            return;
-        case CTRLH:
-            if(txtpos == program_end)
-                break;
-            txtpos--;
-
-            printmsgNoNL(backspacemsg);
-            break;
-        default:
-            // We need to leave at least one space to allow us to shuffle the line into order
-            if(txtpos == variables_begin-2)
-                putchar(BELL);
-            else {
-                txtpos[0] = c;
-                txtpos++;
-                putchar(c);
-            }
-        }
-    }
+    txtpos[0] = 0;
+
+    // We need to leave at least one space to allow us to shuffle the line into order
+    space = variables_begin-2-txtpos;
+    if(space < 0)
+        space = 0;
+    len = read_line((char *)txtpos, (size_t)space, true);
+    if(len < 0)
+        len = 0;
+    line_terminator();
+    // Terminate all strings with a NL
+    txtpos[len] = NL;
 }
 
 /***************************************************************************/
@@ -1352,10 +1335,3 @@ static unsigned char breakcheck(void) {
     }
     return 0;
 }
-/***********************************************************/
-static int inchar() {
-    int got = getchar();
-    // translation for desktop systems
-    if( got == LF ) got = CR;
-    return got;
-}
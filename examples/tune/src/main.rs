@@ -3,11 +3,26 @@
 
 extern crate monotron_app;
 
-use monotron_app::{Channel, Host, Note, Waveform};
+use monotron_app::instrument::{Envelope, Instrument, Vibrato};
+use monotron_app::music::{Event, Sequencer, Track};
+use monotron_app::notation;
+use monotron_app::{Channel, Host, Waveform};
 
-const FRAMES_PER_BAR: usize = 128;
 const MAX_VOLUME: u8 = 255;
 
+/// The tune, with a little vibrato once each note has settled
+const LEAD: Instrument = Instrument::new(Waveform::Square)
+    .with_envelope(Envelope::new(1, 10, 170, 8))
+    .with_vibrato(Vibrato::new(12, 10, 20));
+
+/// The harmony, which fades back a bit further so the tune stands out
+const HARMONY: Instrument =
+    Instrument::new(Waveform::Square).with_envelope(Envelope::new(1, 12, 120, 6));
+
+/// The bass, which is plucked
+const BASS: Instrument =
+    Instrument::new(Waveform::Sawtooth).with_envelope(Envelope::new(0, 6, 200, 3));
+
 #[cfg(not(target_os = "none"))]
 pub fn main() {
     Host::init();
@@ -23,267 +38,46 @@ pub extern "C" fn monotron_main() -> i32 {
     0
 }
 
-#[derive(Debug)]
-enum Length {
-    Whole,
-    Half,
-    Quarter,
-    DottedQuarter,
-    Eighth,
-    Sixteenth,
-}
+/// The three parts, in Music Macro Language, one per channel.
+const SONG: &str = "T112 O5 L8 \
+    E4 <B >C D E16 D16 C <B | A4 A >C E4 D C | <B4. >C D4 E4 | C4 <A4 A2 | \
+    >D4 F A4 G F | E4. C E4 D C | <B4 B >C D4 E4 | C4 <A4 A4 R4; \
+    O4 L8 \
+    B4 G+ A B R16 R16 A G+ | E4 E A >C4 <B A | G+ E G+ A B4 C4 | A4 E4 E2 | \
+    F4 A >C C16 C16 <B A | G4. E G A16 G16 F E | G+ E G+ A B G >C <G | A E E4 E4 R4; \
+    O2 L8 \
+    E >E <E >E <E >E <E >E | <A >A <A >A <A >A <A >A | \
+    <G+ >G+ <G+ >G+ <E >E <E >E | <A >A <A >A <A >A <A >A | \
+    D <D R D R D A F | C >C R C <C G R G | B >B R B R E R G+ | <A >E <A >E <A4 R4";
 
-struct Track<'a> {
-    channel: Channel,
-    waveform: Waveform,
-    volume: u8,
-    play_idx: usize,
-    play_next_at: usize,
-    notes: &'a [(Option<Note>, Length)],
-}
+static TRACK0: [Event; notation::mml_len(SONG, 0)] = notation::parse_mml(SONG, 0);
+static TRACK1: [Event; notation::mml_len(SONG, 1)] = notation::parse_mml(SONG, 1);
+static TRACK2: [Event; notation::mml_len(SONG, 2)] = notation::parse_mml(SONG, 2);
 
 fn play_tune() {
     Host::puts(b"Press Ctrl+C to exit.");
 
-    let mut track0 = Track {
-        channel: Channel::Channel0,
-        play_idx: 0,
-        play_next_at: 0,
-        waveform: Waveform::Square,
-        volume: MAX_VOLUME / 2,
-        notes: &[
-            // Bar 1
-            (Some(Note::E5), Length::Quarter),
-            (Some(Note::B4), Length::Eighth),
-            (Some(Note::C5), Length::Eighth),
-            (Some(Note::D5), Length::Eighth),
-            (Some(Note::E5), Length::Sixteenth),
-            (Some(Note::D5), Length::Sixteenth),
-            (Some(Note::C5), Length::Eighth),
-            (Some(Note::B4), Length::Eighth),
-            // Bar 2
-            (Some(Note::A4), Length::Quarter),
-            (Some(Note::A4), Length::Eighth),
-            (Some(Note::C5), Length::Eighth),
-            (Some(Note::E5), Length::Quarter),
-            (Some(Note::D5), Length::Eighth),
-            (Some(Note::C5), Length::Eighth),
-            // Bar 3
-            (Some(Note::B4), Length::DottedQuarter),
-            (Some(Note::C5), Length::Eighth),
-            (Some(Note::D5), Length::Quarter),
-            (Some(Note::E5), Length::Quarter),
-            // Bar 4
-            (Some(Note::C5), Length::Quarter),
-            (Some(Note::A4), Length::Quarter),
-            (Some(Note::A4), Length::Half),
-            // Bar 5
-            (Some(Note::D5), Length::Quarter),
-            (Some(Note::F5), Length::Eighth),
-            (Some(Note::A5), Length::Quarter),
-            (Some(Note::G5), Length::Eighth),
-            (Some(Note::F5), Length::Eighth),
-            // Bar 6
-            (Some(Note::E5), Length::DottedQuarter),
-            (Some(Note::C5), Length::Eighth),
-            (Some(Note::E5), Length::Quarter),
-            (Some(Note::D5), Length::Eighth),
-            (Some(Note::C5), Length::Eighth),
-            // Bar 7
-            (Some(Note::B4), Length::Quarter),
-            (Some(Note::B4), Length::Eighth),
-            (Some(Note::C5), Length::Eighth),
-            (Some(Note::D5), Length::Quarter),
-            (Some(Note::E5), Length::Quarter),
-            // Bar 8
-            (Some(Note::C5), Length::Quarter),
-            (Some(Note::A4), Length::Quarter),
-            (Some(Note::A4), Length::Quarter),
-            (None, Length::Quarter),
-        ],
-    };
-
-    let mut track1 = Track {
-        channel: Channel::Channel1,
-        play_idx: 0,
-        waveform: Waveform::Square,
-        volume: MAX_VOLUME / 2,
-        play_next_at: 0,
-        notes: &[
-            (Some(Note::B4), Length::Quarter),
-            (Some(Note::GsAb4), Length::Eighth),
-            (Some(Note::A4), Length::Eighth),
-            (Some(Note::B4), Length::Eighth),
-            (None, Length::Sixteenth),
-            (None, Length::Sixteenth),
-            (Some(Note::A4), Length::Eighth),
-            (Some(Note::GsAb4), Length::Eighth),
-            // Bar 2
-            (Some(Note::E4), Length::Quarter),
-            (Some(Note::E4), Length::Eighth),
-            (Some(Note::A4), Length::Eighth),
-            (Some(Note::C5), Length::Quarter),
-            (Some(Note::B4), Length::Eighth),
-            (Some(Note::A4), Length::Eighth),
-            // Bar 3
-            (Some(Note::GsAb4), Length::Eighth),
-            (Some(Note::E4), Length::Eighth),
-            (Some(Note::GsAb4), Length::Eighth),
-            (Some(Note::A4), Length::Eighth),
-            (Some(Note::B4), Length::Quarter),
-            (Some(Note::C4), Length::Quarter),
-            // Bar 4
-            (Some(Note::A4), Length::Quarter),
-            (Some(Note::E4), Length::Quarter),
-            (Some(Note::E4), Length::Half),
-            // Bar 5
-            (Some(Note::F4), Length::Quarter),
-            (Some(Note::A4), Length::Eighth),
-            (Some(Note::C5), Length::Eighth),
-            (Some(Note::C5), Length::Sixteenth),
-            (Some(Note::C5), Length::Sixteenth),
-            (Some(Note::B4), Length::Eighth),
-            (Some(Note::A4), Length::Eighth),
-            // Bar 6
-            (Some(Note::G4), Length::DottedQuarter),
-            (Some(Note::E4), Length::Eighth),
-            (Some(Note::G4), Length::Eighth),
-            (Some(Note::A4), Length::Sixteenth),
-            (Some(Note::G4), Length::Sixteenth),
-            (Some(Note::F4), Length::Eighth),
-            (Some(Note::E4), Length::Eighth),
-            // Bar 7
-            (Some(Note::GsAb4), Length::Eighth),
-            (Some(Note::E4), Length::Eighth),
-            (Some(Note::GsAb4), Length::Eighth),
-            (Some(Note::A4), Length::Eighth),
-            (Some(Note::B4), Length::Eighth),
-            (Some(Note::G4), Length::Eighth),
-            (Some(Note::C5), Length::Eighth),
-            (Some(Note::G4), Length::Eighth),
-            // Bar 8
-            (Some(Note::A4), Length::Eighth),
-            (Some(Note::E4), Length::Eighth),
-            (Some(Note::E4), Length::Quarter),
-            (Some(Note::E4), Length::Quarter),
-            (None, Length::Quarter),
-        ],
-    };
-
-    let mut track2 = Track {
-        channel: Channel::Channel2,
-        play_idx: 0,
-        waveform: Waveform::Sawtooth,
-        volume: MAX_VOLUME,
-        play_next_at: 0,
-        notes: &[
-            // Bar 1
-            (Some(Note::E2), Length::Eighth),
-            (Some(Note::E3), Length::Eighth),
-            (Some(Note::E2), Length::Eighth),
-            (Some(Note::E3), Length::Eighth),
-            (Some(Note::E2), Length::Eighth),
-            (Some(Note::E3), Length::Eighth),
-            (Some(Note::E2), Length::Eighth),
-            (Some(Note::E3), Length::Eighth),
-            // Bar 2
-            (Some(Note::A2), Length::Eighth),
-            (Some(Note::A3), Length::Eighth),
-            (Some(Note::A2), Length::Eighth),
-            (Some(Note::A3), Length::Eighth),
-            (Some(Note::A2), Length::Eighth),
-            (Some(Note::A3), Length::Eighth),
-            (Some(Note::A2), Length::Eighth),
-            (Some(Note::A3), Length::Eighth),
-            // Bar 3
-            (Some(Note::GsAb2), Length::Eighth),
-            (Some(Note::GsAb3), Length::Eighth),
-            (Some(Note::GsAb2), Length::Eighth),
-            (Some(Note::GsAb3), Length::Eighth),
-            (Some(Note::E2), Length::Eighth),
-            (Some(Note::E3), Length::Eighth),
-            (Some(Note::E2), Length::Eighth),
-            (Some(Note::E3), Length::Eighth),
-            // Bar 4
-            (Some(Note::A2), Length::Eighth),
-            (Some(Note::A3), Length::Eighth),
-            (Some(Note::A2), Length::Eighth),
-            (Some(Note::A3), Length::Eighth),
-            (Some(Note::A2), Length::Eighth),
-            (Some(Note::A3), Length::Eighth),
-            (Some(Note::A2), Length::Eighth),
-            (Some(Note::A3), Length::Eighth),
-            // Bar 5
-            (Some(Note::D3), Length::Eighth),
-            (Some(Note::D2), Length::Eighth),
-            (None, Length::Eighth),
-            (Some(Note::D2), Length::Eighth),
-            (None, Length::Eighth),
-            (Some(Note::D2), Length::Eighth),
-            (Some(Note::A2), Length::Eighth),
-            (Some(Note::F2), Length::Eighth),
-            // Bar 6
-            (Some(Note::C2), Length::Eighth),
-            (Some(Note::C3), Length::Eighth),
-            (None, Length::Eighth),
-            (Some(Note::C3), Length::Eighth),
-            (Some(Note::C2), Length::Eighth),
-            (Some(Note::G2), Length::Eighth),
-            (None, Length::Eighth),
-            (Some(Note::G2), Length::Eighth),
-            // Bar 7
-            (Some(Note::B2), Length::Eighth),
-            (Some(Note::B3), Length::Eighth),
-            (None, Length::Eighth),
-            (Some(Note::B3), Length::Eighth),
-            (None, Length::Eighth),
-            (Some(Note::E3), Length::Eighth),
-            (None, Length::Eighth),
-            (Some(Note::GsAb3), Length::Eighth),
-            // Bar 8
-            (Some(Note::A2), Length::Eighth),
-            (Some(Note::E3), Length::Eighth),
-            (Some(Note::A2), Length::Eighth),
-            (Some(Note::E3), Length::Eighth),
-            (Some(Note::A2), Length::Quarter),
-            (None, Length::Quarter),
-        ],
-    };
+    let mut sequencer = Sequencer::new(notation::mml_tempo(SONG.as_bytes()));
+    let parts: [(Channel, &[Event], Instrument, u8); 3] = [
+        (Channel::Channel0, &TRACK0, LEAD, MAX_VOLUME / 2),
+        (Channel::Channel1, &TRACK1, HARMONY, MAX_VOLUME / 2),
+        (Channel::Channel2, &TRACK2, BASS, MAX_VOLUME),
+    ];
+    for &(channel, events, instrument, volume) in &parts {
+        let mut track = Track::new(channel, events);
+        track.set_instrument(instrument);
+        track.set_volume(volume);
+        sequencer.set_track(track);
+    }
+    sequencer.play();
 
-    let mut frame_count = 0;
     loop {
-        let mut again = true;
-        while again {
-            again = false;
-            for track in &mut [&mut track0, &mut track1, &mut track2] {
-                let (note, length) = &track.notes[track.play_idx];
-                if frame_count == track.play_next_at {
-                    if let Some(pitch) = note {
-                        Host::play(*pitch, track.channel, track.waveform, track.volume);
-                    } else {
-                        Host::play(Note::C0, track.channel, track.waveform, 0);
-                    }
-                    track.play_next_at += match length {
-                        Length::Whole => FRAMES_PER_BAR,
-                        Length::Half => FRAMES_PER_BAR / 2,
-                        Length::Quarter => FRAMES_PER_BAR / 4,
-                        Length::DottedQuarter => 3 * (FRAMES_PER_BAR / 8),
-                        Length::Eighth => FRAMES_PER_BAR / 8,
-                        Length::Sixteenth => FRAMES_PER_BAR / 16,
-                    };
-                    track.play_idx += 1;
-                    if track.play_idx >= track.notes.len() {
-                        track.play_idx = 0;
-                    }
-                    again = true;
-                }
-            }
-        }
-        frame_count += 1;
         Host::wfvbi();
+        sequencer.tick();
         if Host::kbhit() {
             let c = Host::readc();
             if c == 3 {
+                sequencer.stop();
                 return;
             }
         }
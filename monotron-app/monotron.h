@@ -15,6 +15,7 @@
 
 #include <stdint.h>
 #include <stdbool.h>
+#include <stddef.h>
 
 /******************************************************************************
  *
@@ -160,6 +161,44 @@
 #define Note_AsBb8  745862
 #define Note_B8  790213
 
+// Key codes for keys which don't type a character (see key_event_t)
+#define KEY_UP 0x100
+#define KEY_DOWN 0x101
+#define KEY_LEFT 0x102
+#define KEY_RIGHT 0x103
+#define KEY_HOME 0x104
+#define KEY_END 0x105
+#define KEY_INSERT 0x106
+#define KEY_PAGE_UP 0x107
+#define KEY_PAGE_DOWN 0x108
+#define KEY_LEFT_SHIFT 0x110
+#define KEY_RIGHT_SHIFT 0x111
+#define KEY_LEFT_CTRL 0x112
+#define KEY_RIGHT_CTRL 0x113
+#define KEY_LEFT_ALT 0x114
+#define KEY_RIGHT_ALT 0x115
+#define KEY_F(n) (0x120 + (n))
+
+// Bits in mouse_state_t.buttons
+#define MOUSE_BUTTON_LEFT 0x01
+#define MOUSE_BUTTON_RIGHT 0x02
+#define MOUSE_BUTTON_MIDDLE 0x04
+
+// Bits in key_event_t.modifiers
+#define KEY_MOD_SHIFT 0x01
+#define KEY_MOD_CTRL 0x02
+#define KEY_MOD_ALT 0x04
+
+// Lengths for music_event_t, in 192ths of a whole note
+#define LENGTH_WHOLE 192
+#define LENGTH_HALF 96
+#define LENGTH_QUARTER 48
+#define LENGTH_EIGHTH 24
+#define LENGTH_SIXTEENTH 12
+#define LENGTH_THIRTY_SECOND 6
+#define LENGTH_DOTTED(l) ((l) + (l) / 2)
+#define LENGTH_TRIPLET(l) ((l) * 2 / 3)
+
 /******************************************************************************
  *
  * Public Types
@@ -185,6 +224,44 @@ typedef enum waveform_t {
 	WAVEFORM_NOISE = 3,
 } waveform_t;
 
+/**
+ * A key going down or coming back up.
+ */
+typedef struct key_event_t {
+	/** The unshifted ASCII character the key types, or one of the KEY_xxx values */
+	uint16_t code;
+	/** True if the key went down, false if it came back up */
+	bool pressed;
+	/** Any of the KEY_MOD_xxx bits */
+	uint8_t modifiers;
+} key_event_t;
+
+/**
+ * Where the mouse pointer is, and which buttons are held down.
+ */
+typedef struct mouse_state_t {
+	/** The text row the pointer is over (0..35) */
+	uint8_t row;
+	/** The text column the pointer is over (0..47) */
+	uint8_t col;
+	/** The pixel the pointer is over, across from the left of the text (0..383) */
+	uint16_t x;
+	/** The scan-line the pointer is over, down from the top of the text (0..575) */
+	uint16_t y;
+	/** Any of the MOUSE_BUTTON_xxx bits */
+	uint8_t buttons;
+} mouse_state_t;
+
+/**
+ * A note, or a rest, for music_set_track().
+ */
+typedef struct music_event_t {
+	/** What to play, in centi-hertz (e.g. Note_C4), or 0 for a rest */
+	uint32_t frequency;
+	/** How long until the next event (e.g. LENGTH_QUARTER) */
+	uint16_t length;
+} music_event_t;
+
 /******************************************************************************
  *
  * Public Function Prototypes
@@ -256,6 +333,32 @@ void set_cursor_visible(bool enabled);
  */
 int getchar(void);
 
+/**
+ * Get the next key going down or coming up. Doesn't block. These events
+ * arrive alongside the characters from getchar(), not instead of them.
+ * The Monotron firmware can't report key events yet, so on the Monotron
+ * itself this always returns false.
+ *
+ * @param p_event filled in with the event, if there is one
+ * @return true if there was an event, else false.
+ */
+bool read_key_event(key_event_t* p_event);
+
+/**
+ * Edit a line of text at the cursor, until Return or Escape is pressed. The
+ * cursor keys, Home, End, Backspace and Delete work as you'd expect.
+ *
+ * The string is edited in place, so after Escape the buffer holds whatever
+ * had been typed so far (still null-terminated), not the original string.
+ * Keep a copy if you need to put it back.
+ *
+ * @param buffer a null-terminated string to edit, with room for max_len + 1 bytes
+ * @param max_len the longest the string can get, not counting the null
+ * @param use_history if true, Up and Down step through the lines entered before
+ * @return the new length of the string, or -1 if Escape was pressed
+ */
+int read_line(char* buffer, size_t max_len, bool use_history);
+
 /**
  * Wait until the screen has been drawn and we're in the vertical blanking interval
  */
@@ -326,7 +429,8 @@ void font_teletext(void);
  * Set a custom font. Supply 4096 bytes of font data (16 bytes per char, 256
  * chars)
  *
- * @param p_font pointer to 4 KiB of font data
+ * @param p_font pointer to 4 KiB of font data, which must stay valid while
+ *        the font is in use
  */
 void font_custom(const void* p_font);
 
@@ -336,6 +440,14 @@ void font_custom(const void* p_font);
  */
 uint8_t get_joystick(void);
 
+/**
+ * Get the mouse position and buttons.
+ *
+ * @param p_state filled in with the mouse state, if there is a mouse
+ * @return true if there is a mouse, else false.
+ */
+bool get_mouse(mouse_state_t* p_state);
+
 /**
  * Check if the Joystick was pressed up when the given state was captured.
  * @return true if up was pressed, else false.
@@ -367,6 +479,201 @@ bool joystick_is_right(uint8_t state);
  */
 bool joystick_fire_pressed(uint8_t state);
 
+/**
+ * Play a list of events on one channel of the synthesiser, when the music is
+ * playing. Replaces whatever that channel was playing before.
+ *
+ * @param channel the channel to play the events on
+ * @param p_events the events, which must stay valid while the track is in use
+ * @param num_events how many events there are
+ * @param waveform the waveform to play the notes with
+ * @param volume the volume to play the notes at (0..255)
+ * @param looping if true, go back to the first event after the last one
+ */
+void music_set_track(channel_t channel, const music_event_t* p_events, size_t num_events, waveform_t waveform, uint8_t volume, bool looping);
+
+/**
+ * Stop playing anything on one channel.
+ *
+ * @param channel the channel to clear
+ */
+void music_clear_track(channel_t channel);
+
+/**
+ * Set the tempo of the music.
+ *
+ * @param bpm quarter notes per minute
+ */
+void music_set_tempo(uint16_t bpm);
+
+/**
+ * Start the music, or carry on from where music_pause() left off.
+ */
+void music_play(void);
+
+/**
+ * Stop the music, but remember where it had got to.
+ */
+void music_pause(void);
+
+/**
+ * Stop the music, and go back to the start.
+ */
+void music_stop(void);
+
+/**
+ * Check if the music is playing.
+ *
+ * @return true if music_play() has been called, and the music hasn't been
+ *         paused or stopped since.
+ */
+bool music_is_playing(void);
+
+/**
+ * Check if every track has played all its events (and isn't looping).
+ *
+ * @return true if there's nothing left to play.
+ */
+bool music_is_finished(void);
+
+/**
+ * Move the music on by one frame. Call this once after every wfvbi().
+ */
+void music_tick(void);
+
+/**
+ * Reset an instrument to a plain square wave, with no envelope or effects.
+ * There are eight instruments, numbered 0 to 7, and they all start like
+ * this.
+ *
+ * @param instrument the instrument to reset
+ */
+void instrument_reset(int instrument);
+
+/**
+ * Set the waveform an instrument plays.
+ *
+ * @param instrument the instrument to change
+ * @param waveform the waveform to play
+ */
+void instrument_set_waveform(int instrument, waveform_t waveform);
+
+/**
+ * Set how the volume of an instrument's notes changes over time.
+ *
+ * @param instrument the instrument to change
+ * @param attack frames to go from silent to full volume
+ * @param decay frames to go from full volume to the sustain level
+ * @param sustain the level (0..255) held until the note is released
+ * @param release frames to go from the sustain level to silent
+ */
+void instrument_set_envelope(int instrument, uint8_t attack, uint8_t decay, uint8_t sustain, uint8_t release);
+
+/**
+ * Make an instrument's notes wobble in pitch.
+ *
+ * @param instrument the instrument to change
+ * @param depth how far the pitch moves each way, in 64ths of a semitone (0 for no vibrato)
+ * @param speed how many frames one wobble takes
+ * @param delay how many frames into each note the wobbling starts
+ */
+void instrument_set_vibrato(int instrument, uint8_t depth, uint8_t speed, uint8_t delay);
+
+/**
+ * Make an instrument step through the notes of a chord, over and over.
+ *
+ * @param instrument the instrument to change
+ * @param p_offsets semitones from the note being played (e.g. 0, 4, 7 for a major chord)
+ * @param num_offsets how many offsets there are (up to 4, or 0 for no arpeggio)
+ * @param speed how many frames to spend on each note
+ */
+void instrument_set_arpeggio(int instrument, const int8_t* p_offsets, size_t num_offsets, uint8_t speed);
+
+/**
+ * Make an instrument's notes bend in pitch as they play.
+ *
+ * @param instrument the instrument to change
+ * @param per_frame how far to bend each frame, in 64ths of a semitone (negative is down)
+ */
+void instrument_set_slide(int instrument, int16_t per_frame);
+
+/**
+ * Play the track on a channel with a copy of an instrument (including its
+ * waveform). Call this after music_set_track().
+ *
+ * @param channel the channel whose track to change
+ * @param instrument the instrument to play it with
+ */
+void music_set_instrument(channel_t channel, int instrument);
+
+/**
+ * Start a sound effect on a channel, with a copy of an instrument. Don't
+ * use a channel the music is playing on.
+ *
+ * @param channel the channel to play on
+ * @param instrument the instrument to play with
+ * @param frequency the frequency in centi-hertz (e.g. Note_C4)
+ * @param volume the volume (0..255), before the envelope
+ */
+void sound_note_on(channel_t channel, int instrument, uint32_t frequency, uint8_t volume);
+
+/**
+ * Release the sound effect on a channel, so it fades out.
+ *
+ * @param channel the channel to release
+ */
+void sound_note_off(channel_t channel);
+
+/**
+ * Move the sound effects on by one frame. Call this once after every
+ * wfvbi().
+ */
+void sound_tick(void);
+
+/**
+ * Open a Standard MIDI File (format 0 or 1) on the SD card, ready to play
+ * from the start. Closes any file which was already open. The file is read
+ * as it plays, using one file handle per track.
+ *
+ * @param filename the file to open
+ * @return 0 on success, or -1 if the file can't be opened or played
+ */
+int midi_open(const char* filename);
+
+/**
+ * Stop playing the MIDI file, and close it.
+ */
+void midi_close(void);
+
+/**
+ * Start playing the MIDI file, or carry on from where midi_pause() left off.
+ */
+void midi_play(void);
+
+/**
+ * Stop playing the MIDI file, but remember where it had got to.
+ */
+void midi_pause(void);
+
+/**
+ * Set the overall volume of the MIDI file.
+ *
+ * @param volume the volume (0..255), which is 255 to start with
+ */
+void midi_set_volume(uint8_t volume);
+
+/**
+ * Check if the MIDI file has played to the end.
+ *
+ * @return true if there's nothing left to play, or no file is open.
+ */
+bool midi_is_finished(void);
+
+/**
+ * Move the MIDI file on by one frame. Call this once after every wfvbi().
+ */
+void midi_tick(void);
+
 /******************************************************************************
  *
  * End of File
@@ -0,0 +1,527 @@
+//! Chunky graphics using the Teletext font's sixels.
+//!
+//! Each of the 48x36 text cells holds a sixel - two pixels across and three
+//! down - so the screen becomes a 96x108 pixel canvas. A `Canvas` keeps the
+//! pixels in RAM, remembers which cells have changed, and only sends those
+//! cells to the screen when you call `flush`.
+//!
+//! Select `Font::Teletext` before flushing, or you'll get accented letters
+//! instead of blocks. Cells are drawn in whatever colours are current at the
+//! time, unless you give them a colour with `set_colour_at`.
+
+use super::codepage;
+use super::{Col, Colour, Host, Row};
+
+/// The width of the canvas, in pixels.
+pub const WIDTH: i16 = 96;
+
+/// The height of the canvas, in pixels.
+pub const HEIGHT: i16 = 108;
+
+const NUM_COLS: usize = 48;
+const NUM_ROWS: usize = 36;
+
+/// How each sixel is drawn.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SixelStyle {
+    /// The blocks touch, so shapes look solid
+    Connected,
+    /// The blocks have gaps between them
+    Separated,
+}
+
+/// How `Canvas::text` draws each glyph.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TextStyle {
+    /// How many pixels each glyph starts to the right of the last one
+    pub advance: i16,
+    /// Which columns of each glyph to draw, with bit 0 the leftmost
+    pub columns: u8,
+    /// If true, clear bits in the font turn pixels off. If false, they're
+    /// left alone.
+    pub opaque: bool,
+}
+
+impl TextStyle {
+    /// Draw all eight columns of each glyph, only turning pixels on, with
+    /// each glyph `advance` pixels after the last.
+    pub const fn new(advance: i16) -> TextStyle {
+        TextStyle {
+            advance,
+            columns: 0xFF,
+            opaque: false,
+        }
+    }
+
+    /// Only draw the columns whose bits are set in `columns`.
+    pub const fn with_columns(self, columns: u8) -> TextStyle {
+        TextStyle { columns, ..self }
+    }
+
+    /// Turn pixels off where the font has clear bits, so the text covers up
+    /// whatever was underneath it.
+    pub const fn opaque(self) -> TextStyle {
+        TextStyle {
+            opaque: true,
+            ..self
+        }
+    }
+}
+
+/// A 96x108 pixel canvas, drawn with Teletext sixels.
+pub struct Canvas {
+    /// The sixel in each cell, with bit 0 the top left pixel and bit 5 the
+    /// bottom right.
+    cells: [[u8; NUM_COLS]; NUM_ROWS],
+    /// One bit per column, set if that cell needs sending to the screen.
+    dirty: [u64; NUM_ROWS],
+    /// The foreground colour for each cell, if it has one.
+    colours: [[Option<Colour>; NUM_COLS]; NUM_ROWS],
+    style: SixelStyle,
+}
+
+impl Default for Canvas {
+    fn default() -> Canvas {
+        Canvas::new()
+    }
+}
+
+impl Canvas {
+    /// Make a blank canvas. Nothing is sent to the screen until you draw
+    /// something.
+    pub fn new() -> Canvas {
+        Canvas {
+            cells: [[0; NUM_COLS]; NUM_ROWS],
+            dirty: [0; NUM_ROWS],
+            colours: [[None; NUM_COLS]; NUM_ROWS],
+            style: SixelStyle::Connected,
+        }
+    }
+
+    /// Make a canvas from whatever sixels are on screen already. Any cell
+    /// without a sixel in it is taken to be blank.
+    pub fn from_screen() -> Canvas {
+        let mut canvas = Canvas::new();
+        for (row, cells) in canvas.cells.iter_mut().enumerate() {
+            for (col, cell) in cells.iter_mut().enumerate() {
+                let (glyph, _attr) = Host::read_char_at(Row(row as u8), Col(col as u8));
+                *cell = codepage::sixel_bits(glyph).unwrap_or(0);
+            }
+        }
+        canvas
+    }
+
+    /// Choose connected or separated sixels. The whole canvas is redrawn on
+    /// the next `flush`.
+    pub fn set_style(&mut self, style: SixelStyle) {
+        if style != self.style {
+            self.style = style;
+            self.mark_all_dirty();
+        }
+    }
+
+    /// Send every cell on the next `flush`, not just the ones which have
+    /// changed (e.g. because something else has drawn over the screen).
+    pub fn mark_all_dirty(&mut self) {
+        for dirty in self.dirty.iter_mut() {
+            *dirty = (1 << NUM_COLS) - 1;
+        }
+    }
+
+    /// Send the cells which have changed to the screen.
+    ///
+    /// Cells with a colour change the foreground colour, and it's left that
+    /// way afterwards. Cells without one are drawn in whichever colour was
+    /// used last.
+    pub fn flush(&mut self) {
+        let mut current_colour = None;
+        for (row, dirty) in self.dirty.iter_mut().enumerate() {
+            let mut cursor_ok = false;
+            for col in 0..NUM_COLS {
+                if *dirty & (1 << col) == 0 {
+                    // We'll need to skip over this one
+                    cursor_ok = false;
+                    continue;
+                }
+                if !cursor_ok {
+                    Host::move_cursor(Row(row as u8), Col(col as u8));
+                    cursor_ok = true;
+                }
+                if let Some(colour) = self.colours[row][col] {
+                    if current_colour != Some(colour) {
+                        Host::set_foreground(colour);
+                        current_colour = Some(colour);
+                    }
+                }
+                let sixel = self.cells[row][col];
+                Host::putchar(match self.style {
+                    SixelStyle::Connected => codepage::connected_sixel(sixel),
+                    SixelStyle::Separated => codepage::separated_sixel(sixel),
+                });
+            }
+            *dirty = 0;
+        }
+    }
+
+    /// Set every pixel on, or off.
+    pub fn clear(&mut self, set: bool) {
+        let sixel = if set { 0b111111 } else { 0 };
+        for row in 0..NUM_ROWS {
+            for col in 0..NUM_COLS {
+                self.set_cell(row, col, sixel);
+            }
+        }
+    }
+
+    /// True if the given pixel is on. Pixels off the canvas are always off.
+    pub fn pixel(&self, x: i16, y: i16) -> bool {
+        match Canvas::locate(x, y) {
+            Some((row, col, bit)) => self.cells[row][col] & bit != 0,
+            None => false,
+        }
+    }
+
+    /// Turn a pixel on or off. Pixels off the canvas are ignored, so shapes
+    /// can hang over the edges.
+    pub fn set_pixel(&mut self, x: i16, y: i16, set: bool) {
+        if let Some((row, col, bit)) = Canvas::locate(x, y) {
+            let old = self.cells[row][col];
+            let new = if set { old | bit } else { old & !bit };
+            self.set_cell(row, col, new);
+        }
+    }
+
+    /// Set the colour of the cell a pixel is in. All six pixels in a cell
+    /// share one colour. `None` means whatever colour is current on `flush`.
+    pub fn set_colour_at(&mut self, x: i16, y: i16, colour: Option<Colour>) {
+        if let Some((row, col, _bit)) = Canvas::locate(x, y) {
+            if self.colours[row][col] != colour {
+                self.colours[row][col] = colour;
+                self.dirty[row] |= 1 << col;
+            }
+        }
+    }
+
+    /// Draw a line between two points (inclusive).
+    pub fn line(&mut self, x0: i16, y0: i16, x1: i16, y1: i16, set: bool) {
+        // Bresenham's algorithm, for all eight octants. It's worked out in
+        // i32s, so points far off the canvas can't overflow.
+        let (x0, y0) = (i32::from(x0), i32::from(y0));
+        let (x1, y1) = (i32::from(x1), i32::from(y1));
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let step_x = if x0 < x1 { 1 } else { -1 };
+        let step_y = if y0 < y1 { 1 } else { -1 };
+        let mut error = dx + dy;
+        let (mut x, mut y) = (x0, y0);
+        loop {
+            self.plot(x, y, set);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let error2 = 2 * error;
+            if error2 >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if error2 <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Draw the outline of a rectangle, `width` pixels across and `height`
+    /// pixels down from (`x`, `y`).
+    pub fn rect(&mut self, x: i16, y: i16, width: i16, height: i16, set: bool) {
+        if width <= 0 || height <= 0 {
+            return;
+        }
+        let (right, bottom) = (x.saturating_add(width - 1), y.saturating_add(height - 1));
+        self.line(x, y, right, y, set);
+        self.line(x, bottom, right, bottom, set);
+        self.line(x, y, x, bottom, set);
+        self.line(right, y, right, bottom, set);
+    }
+
+    /// Fill in a rectangle, `width` pixels across and `height` pixels down
+    /// from (`x`, `y`).
+    pub fn fill_rect(&mut self, x: i16, y: i16, width: i16, height: i16, set: bool) {
+        for py in y.max(0)..y.saturating_add(height).min(HEIGHT) {
+            for px in x.max(0)..x.saturating_add(width).min(WIDTH) {
+                self.set_pixel(px, py, set);
+            }
+        }
+    }
+
+    /// Draw the outline of a circle.
+    pub fn circle(&mut self, centre_x: i16, centre_y: i16, radius: i16, set: bool) {
+        let (centre_x, centre_y) = (i32::from(centre_x), i32::from(centre_y));
+        self.for_each_octant(radius, |canvas, x, y| {
+            for &(dx, dy) in &[(x, y), (y, x), (-y, x), (-x, y)] {
+                canvas.plot(centre_x + dx, centre_y + dy, set);
+                canvas.plot(centre_x - dx, centre_y - dy, set);
+            }
+        });
+    }
+
+    /// Fill in a circle.
+    pub fn fill_circle(&mut self, centre_x: i16, centre_y: i16, radius: i16, set: bool) {
+        let (centre_x, centre_y) = (i32::from(centre_x), i32::from(centre_y));
+        self.for_each_octant(radius, |canvas, x, y| {
+            for &(dx, dy) in &[(x, y), (y, x)] {
+                canvas.hline(centre_x - dx, centre_x + dx, centre_y + dy, set);
+                canvas.hline(centre_x - dx, centre_x + dx, centre_y - dy, set);
+            }
+        });
+    }
+
+    /// Copy a 1-bit image onto the canvas. Each row of the image is
+    /// `(width + 7) / 8` bytes, with the leftmost pixel in the top bit of the
+    /// first byte. Set bits turn pixels on, and clear bits are left alone
+    /// unless `opaque` is true, in which case they turn pixels off.
+    pub fn blit(&mut self, x: i16, y: i16, width: i16, image: &[u8], opaque: bool) {
+        if width <= 0 {
+            return;
+        }
+        let stride = (width as usize).div_ceil(8);
+        for (row, line) in image.chunks(stride).enumerate() {
+            for col in 0..width {
+                let set = line
+                    .get(col as usize / 8)
+                    .map(|byte| byte & (0x80 >> (col % 8)) != 0)
+                    .unwrap_or(false);
+                if set || opaque {
+                    self.plot(
+                        i32::from(x) + i32::from(col),
+                        i32::from(y) + row as i32,
+                        set,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Draw some text with a bitmap font. `font` gives the rows for a glyph,
+    /// top first, with the leftmost pixel in bit 0 and up to eight pixels
+    /// across. `style` says how far apart the glyphs are and which pixels
+    /// of each glyph are drawn. Returns the x position after the last glyph.
+    pub fn text<'f, F>(&mut self, x: i16, y: i16, text: &[u8], style: TextStyle, font: F) -> i16
+    where
+        F: Fn(u8) -> &'f [u8],
+    {
+        let mut x = x;
+        for &ch in text {
+            for (row, line) in font(ch).iter().enumerate() {
+                for col in 0..8 {
+                    if style.columns & (1 << col) == 0 {
+                        continue;
+                    }
+                    let set = line & (1 << col) != 0;
+                    if set || style.opaque {
+                        self.plot(i32::from(x) + col, i32::from(y) + row as i32, set);
+                    }
+                }
+            }
+            x = x.saturating_add(style.advance);
+        }
+        x
+    }
+
+    /// Draw a horizontal line from `x0` to `x1` (inclusive).
+    fn hline(&mut self, x0: i32, x1: i32, y: i32, set: bool) {
+        if y < 0 || y >= i32::from(HEIGHT) {
+            return;
+        }
+        for x in x0.max(0)..=x1.min(i32::from(WIDTH) - 1) {
+            self.plot(x, y, set);
+        }
+    }
+
+    /// Like `set_pixel`, but for points which might not even fit in an i16.
+    fn plot(&mut self, x: i32, y: i32, set: bool) {
+        if x >= 0 && y >= 0 && x < i32::from(WIDTH) && y < i32::from(HEIGHT) {
+            self.set_pixel(x as i16, y as i16, set);
+        }
+    }
+
+    /// Walk one octant of a circle with the midpoint algorithm, calling `f`
+    /// with each point relative to the centre. It's worked out in i32s, as
+    /// the error term outgrows an i16 for big circles.
+    fn for_each_octant<F>(&mut self, radius: i16, mut f: F)
+    where
+        F: FnMut(&mut Canvas, i32, i32),
+    {
+        if radius < 0 {
+            return;
+        }
+        let radius = i32::from(radius);
+        let (mut x, mut y) = (radius, 0);
+        let mut error = 1 - radius;
+        while x >= y {
+            f(self, x, y);
+            y += 1;
+            if error < 0 {
+                error += 2 * y + 1;
+            } else {
+                x -= 1;
+                error += 2 * (y - x) + 1;
+            }
+        }
+    }
+
+    /// Change a cell, marking it dirty if it's different.
+    fn set_cell(&mut self, row: usize, col: usize, sixel: u8) {
+        if self.cells[row][col] != sixel {
+            self.cells[row][col] = sixel;
+            self.dirty[row] |= 1 << col;
+        }
+    }
+
+    /// Find the cell a pixel is in, and its bit within that cell.
+    fn locate(x: i16, y: i16) -> Option<(usize, usize, u8)> {
+        if x < 0 || y < 0 || x >= WIDTH || y >= HEIGHT {
+            return None;
+        }
+        let (x, y) = (x as usize, y as usize);
+        let bit = 1 << ((y % 3) * 2 + (x % 2));
+        Some((y / 3, x / 2, bit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every pixel on the canvas which is on, as (x, y).
+    fn lit(canvas: &Canvas) -> Vec<(i16, i16)> {
+        let mut pixels = Vec::new();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                if canvas.pixel(x, y) {
+                    pixels.push((x, y));
+                }
+            }
+        }
+        pixels
+    }
+
+    #[test]
+    fn sixel_layout() {
+        // Cell (row 7, col 5) holds x = 10..12 and y = 21..24
+        for dy in 0..3 {
+            for dx in 0..2 {
+                let mut canvas = Canvas::new();
+                canvas.set_pixel(10 + dx, 21 + dy, true);
+                assert_eq!(canvas.cells[7][5], 1 << (dy * 2 + dx));
+                assert_eq!(canvas.dirty[7], 1 << 5);
+                assert_eq!(lit(&canvas), vec![(10 + dx, 21 + dy)]);
+            }
+        }
+        let mut canvas = Canvas::new();
+        canvas.set_pixel(WIDTH - 1, HEIGHT - 1, true);
+        assert_eq!(canvas.cells[NUM_ROWS - 1][NUM_COLS - 1], 0b100000);
+        assert_eq!(canvas.dirty[NUM_ROWS - 1], 1 << (NUM_COLS - 1));
+        canvas.set_pixel(WIDTH - 1, HEIGHT - 1, false);
+        assert_eq!(canvas.cells[NUM_ROWS - 1][NUM_COLS - 1], 0);
+    }
+
+    #[test]
+    fn off_canvas_pixels() {
+        let mut canvas = Canvas::new();
+        for &(x, y) in &[
+            (-1, 0),
+            (0, -1),
+            (WIDTH, 0),
+            (0, HEIGHT),
+            (i16::MIN, i16::MAX),
+        ] {
+            canvas.set_pixel(x, y, true);
+            canvas.set_colour_at(x, y, Some(Colour::Red));
+            assert!(!canvas.pixel(x, y));
+        }
+        assert!(lit(&canvas).is_empty());
+        assert_eq!(canvas.dirty, [0; NUM_ROWS]);
+    }
+
+    #[test]
+    fn lines_at_the_extremes() {
+        let mut canvas = Canvas::new();
+        canvas.line(i16::MIN, i16::MIN, i16::MAX, i16::MAX, true);
+        let diagonal: Vec<(i16, i16)> = (0..WIDTH).map(|i| (i, i)).collect();
+        assert_eq!(lit(&canvas), diagonal);
+
+        let mut canvas = Canvas::new();
+        canvas.line(i16::MAX, 0, i16::MIN, 0, true);
+        let top: Vec<(i16, i16)> = (0..WIDTH).map(|x| (x, 0)).collect();
+        assert_eq!(lit(&canvas), top);
+
+        let mut canvas = Canvas::new();
+        canvas.line(i16::MIN, i16::MAX, i16::MAX, i16::MIN, true);
+        canvas.line(i16::MIN, i16::MIN, i16::MIN, i16::MAX, true);
+        canvas.rect(i16::MIN, i16::MIN, i16::MAX, i16::MAX, true);
+        canvas.rect(i16::MAX, i16::MAX, i16::MAX, i16::MAX, true);
+        canvas.fill_rect(i16::MAX, i16::MAX, i16::MAX, i16::MAX, true);
+        assert!(lit(&canvas).is_empty());
+    }
+
+    #[test]
+    fn circles_at_the_extremes() {
+        let mut canvas = Canvas::new();
+        canvas.circle(48, 54, 10, true);
+        for &(x, y) in &[(58, 54), (38, 54), (48, 44), (48, 64)] {
+            assert!(canvas.pixel(x, y), "({}, {})", x, y);
+        }
+        assert!(!canvas.pixel(48, 54));
+
+        let mut canvas = Canvas::new();
+        canvas.circle(0, 0, i16::MAX, true);
+        canvas.circle(i16::MAX, i16::MIN, i16::MAX, true);
+        canvas.circle(i16::MIN, i16::MIN, 10, true);
+        canvas.fill_circle(i16::MAX, i16::MAX, i16::MAX, true);
+        assert!(lit(&canvas).is_empty());
+
+        canvas.fill_circle(i16::MIN, i16::MAX, i16::MAX, true);
+        canvas.fill_circle(0, 0, i16::MAX, true);
+        assert_eq!(lit(&canvas).len(), WIDTH as usize * HEIGHT as usize);
+    }
+
+    #[test]
+    fn text_and_images_at_the_extremes() {
+        let mut canvas = Canvas::new();
+        canvas.blit(i16::MAX, i16::MAX, 16, &[0xFF; 32], true);
+        let end = canvas.text(i16::MAX - 1, 0, b"ab", TextStyle::new(6).opaque(), |_| {
+            &[0xFF; 8]
+        });
+        assert_eq!(end, i16::MAX);
+        assert!(lit(&canvas).is_empty());
+    }
+
+    #[test]
+    fn dirty_tracking() {
+        let mut canvas = Canvas::new();
+        canvas.set_pixel(3, 4, true);
+        canvas.set_pixel(95, 4, true);
+        assert_eq!(canvas.dirty[1], 1 << 1 | 1 << 47);
+        canvas.flush();
+        assert_eq!(canvas.dirty, [0; NUM_ROWS]);
+
+        // Nothing changes, so nothing needs sending
+        canvas.set_pixel(3, 4, true);
+        canvas.set_pixel(2, 5, false);
+        canvas.set_colour_at(3, 4, None);
+        assert_eq!(canvas.dirty, [0; NUM_ROWS]);
+
+        canvas.set_colour_at(3, 4, Some(Colour::Green));
+        assert_eq!(canvas.dirty[1], 1 << 1);
+        canvas.flush();
+
+        canvas.set_style(SixelStyle::Connected);
+        assert_eq!(canvas.dirty, [0; NUM_ROWS]);
+        canvas.set_style(SixelStyle::Separated);
+        assert_eq!(canvas.dirty, [(1 << NUM_COLS) - 1; NUM_ROWS]);
+        canvas.flush();
+        assert_eq!(canvas.dirty, [0; NUM_ROWS]);
+        assert_eq!(lit(&canvas), vec![(3, 4), (95, 4)]);
+    }
+}
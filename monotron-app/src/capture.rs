@@ -0,0 +1,154 @@
+//! Saves frames from the emulator as images and videos.
+
+use super::sdl_window::{HEIGHT, WIDTH};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Save an RGB24 frame as a PPM file if the name ends in `.ppm`, or as a PNG
+/// file otherwise.
+pub fn save_screenshot(pixels: &[u8], path: &Path) -> io::Result<()> {
+    let is_ppm = path
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("ppm"))
+        .unwrap_or(false);
+    let mut file = BufWriter::new(File::create(path)?);
+    if is_ppm {
+        write!(file, "P6\n{} {}\n255\n", WIDTH, HEIGHT)?;
+        file.write_all(pixels)?;
+    } else {
+        let mut encoder = png::Encoder::new(file, WIDTH as u32, HEIGHT as u32);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder
+            .write_header()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        writer
+            .write_image_data(pixels)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        return Ok(());
+    }
+    file.flush()
+}
+
+/// Find a name for the next screenshot or recording, like
+/// `monotron-003.png`, which won't overwrite an earlier one.
+pub fn next_free_path(extension: &str) -> PathBuf {
+    let mut n = 0;
+    loop {
+        let path = PathBuf::from(format!("monotron-{:03}.{}", n, extension));
+        if !path.exists() {
+            return path;
+        }
+        n += 1;
+    }
+}
+
+/// The eight colours the Monotron can show, indexed by their red, green and
+/// blue bits (in that order).
+const PALETTE: [u8; 24] = [
+    0x00, 0x00, 0x00, // Black
+    0x00, 0x00, 0xFF, // Blue
+    0x00, 0xFF, 0x00, // Green
+    0x00, 0xFF, 0xFF, // Cyan
+    0xFF, 0x00, 0x00, // Red
+    0xFF, 0x00, 0xFF, // Magenta
+    0xFF, 0xFF, 0x00, // Yellow
+    0xFF, 0xFF, 0xFF, // White
+];
+
+enum Format {
+    Gif(gif::Encoder<BufWriter<File>>),
+    Y4m(BufWriter<File>),
+}
+
+/// Records one frame per call to `wfvbi`, so the video plays back at a steady
+/// rate however fast (or slowly) the emulator ran. That's exactly 60 fps for
+/// `.y4m` files, but 50 fps for GIFs (see `add_frame`).
+pub struct Recorder {
+    path: PathBuf,
+    format: Format,
+    frames: u64,
+}
+
+impl Recorder {
+    /// Start recording to a `.y4m` (YUV4MPEG2) file if the name ends in
+    /// `.y4m`, or to an animated GIF otherwise.
+    pub fn start(path: &Path) -> io::Result<Recorder> {
+        let is_y4m = path
+            .extension()
+            .map(|ext| ext.eq_ignore_ascii_case("y4m"))
+            .unwrap_or(false);
+        let mut file = BufWriter::new(File::create(path)?);
+        let format = if is_y4m {
+            // 4:4:4 so we don't smudge the colours
+            writeln!(file, "YUV4MPEG2 W{} H{} F60:1 Ip A1:1 C444", WIDTH, HEIGHT)?;
+            Format::Y4m(file)
+        } else {
+            use gif::SetParameter;
+            let mut encoder = gif::Encoder::new(file, WIDTH as u16, HEIGHT as u16, &PALETTE)?;
+            encoder.set(gif::Repeat::Infinite)?;
+            Format::Gif(encoder)
+        };
+        Ok(Recorder {
+            path: path.to_owned(),
+            format,
+            frames: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Add an RGB24 frame to the recording.
+    pub fn add_frame(&mut self, pixels: &[u8]) -> io::Result<()> {
+        match self.format {
+            Format::Gif(ref mut encoder) => {
+                // Each channel is either 0x00 or 0xFF, so masking gives us
+                // the palette index
+                let indices: Vec<u8> = pixels
+                    .chunks(3)
+                    .map(|rgb| (rgb[0] & 4) | (rgb[1] & 2) | (rgb[2] & 1))
+                    .collect();
+                // GIF delays are in 1/100ths of a second, and most viewers
+                // treat a delay of 1 as if it were 10, so we can't get 60
+                // fps. 50 fps is the closest that plays back evenly.
+                let frame = gif::Frame {
+                    width: WIDTH as u16,
+                    height: HEIGHT as u16,
+                    delay: 2,
+                    buffer: std::borrow::Cow::Owned(indices),
+                    ..gif::Frame::default()
+                };
+                encoder.write_frame(&frame)?;
+            }
+            Format::Y4m(ref mut file) => {
+                // BT.601, studio range, which is what most players expect
+                let mut planes = vec![0u8; WIDTH * HEIGHT * 3];
+                let (y_plane, chroma) = planes.split_at_mut(WIDTH * HEIGHT);
+                let (u_plane, v_plane) = chroma.split_at_mut(WIDTH * HEIGHT);
+                for (idx, rgb) in pixels.chunks(3).enumerate() {
+                    let (r, g, b) = (rgb[0] as i32, rgb[1] as i32, rgb[2] as i32);
+                    y_plane[idx] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+                    u_plane[idx] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+                    v_plane[idx] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+                }
+                file.write_all(b"FRAME\n")?;
+                file.write_all(&planes)?;
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Finish off the file.
+    pub fn finish(self) -> io::Result<u64> {
+        match self.format {
+            // The encoder writes the GIF trailer when it's dropped
+            Format::Gif(encoder) => drop(encoder),
+            Format::Y4m(mut file) => file.flush()?,
+        }
+        Ok(self.frames)
+    }
+}
@@ -0,0 +1,131 @@
+//! A fake calendar clock for the emulator, so that anything showing the time
+//! looks the same on every run.
+//!
+//! Set `MONOTRON_CLOCK` to a time like `2019-05-15 09:30:00` (in the years
+//! 1970 to 2225, which is what `Timestamp` can hold) to pin the clock
+//! there. Set `MONOTRON_CLOCK_STEP` as well to move the clock on that many
+//! seconds per call to `wfvbi` - either as a decimal (`0.5`) or as a fraction
+//! (`1/60`, which runs the clock at real-time speed). The step can't be
+//! negative, and the clock stops at the end of 2225.
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+
+pub struct FakeClock {
+    start: NaiveDateTime,
+    /// How many seconds pass per frame.
+    step: f64,
+}
+
+impl FakeClock {
+    /// Returns `None` if `MONOTRON_CLOCK` isn't set (or is no good), so we
+    /// use the real time.
+    pub fn from_env() -> Option<FakeClock> {
+        let start = std::env::var("MONOTRON_CLOCK").ok()?;
+        let start = match NaiveDateTime::parse_from_str(&start, "%Y-%m-%d %H:%M:%S")
+            .or_else(|_| NaiveDateTime::parse_from_str(&start, "%Y-%m-%dT%H:%M:%S"))
+        {
+            // `Timestamp` counts years from 1970 in a `u8`
+            Ok(time) if (1970..=1970 + 255).contains(&time.year()) => time,
+            Ok(_) => {
+                println!(
+                    "Bad MONOTRON_CLOCK {:?} (the year must be 1970 to 2225) - using the real time",
+                    start
+                );
+                return None;
+            }
+            Err(e) => {
+                println!(
+                    "Bad MONOTRON_CLOCK {:?} ({}) - using the real time",
+                    start, e
+                );
+                return None;
+            }
+        };
+        let step = match std::env::var("MONOTRON_CLOCK_STEP") {
+            Ok(step) => parse_step(&step).unwrap_or_else(|| {
+                println!("Bad MONOTRON_CLOCK_STEP {:?} - the clock won't move", step);
+                0.0
+            }),
+            Err(_) => 0.0,
+        };
+        Some(FakeClock { start, step })
+    }
+
+    /// The time after the given number of frames. The clock stops at the
+    /// end of 2225, as `Timestamp` can't go any further.
+    pub fn time_at(&self, frame: u64) -> NaiveDateTime {
+        let last = last_time();
+        // Clamp before converting, so a huge step can't overflow the i64
+        let most_ms = (last - self.start).num_milliseconds() as f64;
+        let elapsed_ms = (frame as f64 * self.step * 1000.0).min(most_ms);
+        self.start
+            .checked_add_signed(Duration::milliseconds(elapsed_ms as i64))
+            .map_or(last, |time| time.min(last))
+    }
+}
+
+/// The last time `Timestamp` can hold.
+fn last_time() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(1970 + 255, 12, 31)
+        .and_then(|date| date.and_hms_opt(23, 59, 59))
+        .expect("valid date")
+}
+
+/// Parse a step, which must be a finite number of seconds, and not negative
+/// (the clock can't run backwards past 1970).
+fn parse_step(step: &str) -> Option<f64> {
+    let mut parts = step.splitn(2, '/');
+    let numerator: f64 = parts.next()?.trim().parse().ok()?;
+    let step = match parts.next() {
+        Some(denominator) => numerator / denominator.trim().parse::<f64>().ok()?,
+        None => numerator,
+    };
+    if step.is_finite() && step >= 0.0 {
+        Some(step)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn steps() {
+        assert_eq!(parse_step("0.5"), Some(0.5));
+        assert_eq!(parse_step(" 1 / 4 "), Some(0.25));
+        assert_eq!(parse_step("1/0"), None);
+        assert_eq!(parse_step("0/0"), None);
+        assert_eq!(parse_step("inf"), None);
+        assert_eq!(parse_step("NaN"), None);
+        assert_eq!(parse_step("-1"), None);
+        assert_eq!(parse_step("1/-2"), None);
+        assert_eq!(parse_step("soon"), None);
+    }
+
+    #[test]
+    fn stops_at_the_end_of_2225() {
+        let clock = FakeClock {
+            start: time("2019-05-15 09:30:00"),
+            step: 0.5,
+        };
+        assert_eq!(clock.time_at(0), time("2019-05-15 09:30:00"));
+        assert_eq!(clock.time_at(4), time("2019-05-15 09:30:02"));
+        let clock = FakeClock {
+            start: time("2225-12-31 23:59:00"),
+            step: 1.0,
+        };
+        assert_eq!(clock.time_at(59), time("2225-12-31 23:59:59"));
+        assert_eq!(clock.time_at(60), time("2225-12-31 23:59:59"));
+        let clock = FakeClock {
+            start: time("1970-01-01 00:00:00"),
+            step: 1e300,
+        };
+        assert_eq!(clock.time_at(u64::MAX), time("2225-12-31 23:59:59"));
+    }
+}
@@ -0,0 +1,126 @@
+//! Converts Unicode text into the 8-bit glyphs the Monotron's fonts contain.
+//!
+//! The normal font is Code Page 850. The Teletext font has ASCII in the
+//! bottom half, and the 64 sixel (or sextant) block graphics, connected and
+//! separated, in the top half. Anything the selected font can't show comes
+//! out as `FALLBACK`.
+
+/// What we show for a character the font doesn't have.
+pub const FALLBACK: u8 = b'?';
+
+/// The characters in the top half of Code Page 850, from 0x80 to 0xFF.
+static CP850_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', // 0x80
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', 'ø', '£', 'Ø', '×', 'ƒ', // 0x90
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '®', '¬', '½', '¼', '¡', '«', '»', // 0xA0
+    '░', '▒', '▓', '│', '┤', 'Á', 'Â', 'À', '©', '╣', '║', '╗', '╝', '¢', '¥', '┐', // 0xB0
+    '└', '┴', '┬', '├', '─', '┼', 'ã', 'Ã', '╚', '╔', '╩', '╦', '╠', '═', '╬', '¤', // 0xC0
+    'ð', 'Ð', 'Ê', 'Ë', 'È', 'ı', 'Í', 'Î', 'Ï', '┘', '┌', '█', '▄', '¦', 'Ì', '▀', // 0xD0
+    'Ó', 'ß', 'Ô', 'Ò', 'õ', 'Õ', 'µ', 'þ', 'Þ', 'Ú', 'Û', 'Ù', 'ý', 'Ý', '¯', '´', // 0xE0
+    '\u{AD}', '±', '‗', '¾', '¶', '§', '÷', '¸', '°', '¨', '·', '¹', '³', '²', '■', '\u{A0}', // 0xF0
+];
+
+/// The first of the Unicode "BLOCK SEXTANT" characters, U+1FB00.
+const FIRST_SEXTANT: u32 = 0x1FB00;
+
+/// Which font's glyphs we're converting to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Codepage {
+    /// Code Page 850, as used by `Font::Normal`
+    Cp850,
+    /// ASCII and sixels, as used by `Font::Teletext`
+    Teletext,
+}
+
+impl Codepage {
+    /// Convert a character, using `FALLBACK` if there's no glyph for it.
+    pub fn encode(self, ch: char) -> u8 {
+        let glyph = match self {
+            Codepage::Cp850 => to_cp850(ch),
+            Codepage::Teletext => to_teletext(ch),
+        };
+        glyph.unwrap_or(FALLBACK)
+    }
+}
+
+/// Find the Code Page 850 glyph for a character.
+pub fn to_cp850(ch: char) -> Option<u8> {
+    if ch.is_ascii() {
+        return Some(ch as u8);
+    }
+    CP850_HIGH
+        .iter()
+        .position(|&c| c == ch)
+        .map(|idx| 0x80 + idx as u8)
+}
+
+/// Find the character a Code Page 850 glyph shows.
+pub fn from_cp850(glyph: u8) -> char {
+    if glyph < 0x80 {
+        glyph as char
+    } else {
+        CP850_HIGH[glyph as usize - 0x80]
+    }
+}
+
+/// Find the Teletext font glyph for a character. Block sextants (and the
+/// full and half blocks, which are sextants too) become connected sixels.
+pub fn to_teletext(ch: char) -> Option<u8> {
+    if ch.is_ascii() {
+        return Some(ch as u8);
+    }
+    let sixel = match ch {
+        '▌' => 0b010101,
+        '▐' => 0b101010,
+        '█' => 0b111111,
+        _ => {
+            let idx = (ch as u32).checked_sub(FIRST_SEXTANT)?;
+            if idx > 59 {
+                return None;
+            }
+            // Unicode leaves out the empty and full patterns, and the two
+            // half blocks, which exist elsewhere.
+            let mut sixel = idx as u8 + 1;
+            if sixel >= 0b010101 {
+                sixel += 1;
+            }
+            if sixel >= 0b101010 {
+                sixel += 1;
+            }
+            sixel
+        }
+    };
+    Some(connected_sixel(sixel))
+}
+
+/// The Teletext font glyph for a connected sixel, where bit 0 is the top
+/// left block and bit 5 is the bottom right.
+pub fn connected_sixel(sixel: u8) -> u8 {
+    if sixel < 0x20 {
+        sixel + 0x80
+    } else {
+        sixel + 0xC0 - 0x20
+    }
+}
+
+/// The Teletext font glyph for a separated sixel, where bit 0 is the top
+/// left block and bit 5 is the bottom right.
+pub fn separated_sixel(sixel: u8) -> u8 {
+    if sixel < 0x20 {
+        sixel + 0xA0
+    } else {
+        sixel + 0xE0 - 0x20
+    }
+}
+
+/// The sixel a Teletext font glyph shows (connected or separated), or `None`
+/// if it isn't a sixel.
+pub fn sixel_bits(glyph: u8) -> Option<u8> {
+    match glyph {
+        0x80..=0x9F => Some(glyph - 0x80),
+        0xA0..=0xBF => Some(glyph - 0xA0),
+        0xC0..=0xDF => Some(glyph - 0xC0 + 0x20),
+        0xE0..=0xFF => Some(glyph - 0xE0 + 0x20),
+        _ => None,
+    }
+}
@@ -0,0 +1,71 @@
+//! Lets the embedded-graphics crate draw on a sixel `Canvas`.
+//!
+//! Pixels are `Colour`s. Black turns a pixel off, and any other colour turns
+//! it on and sets the colour of its cell - so if two pixels in the same cell
+//! are given different colours, the last one wins. `BinaryColor` and
+//! `Rgb888` images can be drawn with `color_converted()`.
+
+use super::canvas::{self, Canvas};
+use super::Colour;
+use embedded_graphics_core::draw_target::DrawTarget;
+use embedded_graphics_core::geometry::{OriginDimensions, Size};
+use embedded_graphics_core::pixelcolor::PixelColor;
+use embedded_graphics_core::pixelcolor::{BinaryColor, Rgb888, RgbColor};
+use embedded_graphics_core::Pixel;
+
+impl PixelColor for Colour {
+    type Raw = ();
+}
+
+impl From<BinaryColor> for Colour {
+    fn from(colour: BinaryColor) -> Colour {
+        match colour {
+            BinaryColor::Off => Colour::Black,
+            BinaryColor::On => Colour::White,
+        }
+    }
+}
+
+impl From<Rgb888> for Colour {
+    /// Each channel is either on or off, so round to the nearest.
+    fn from(colour: Rgb888) -> Colour {
+        let red = (colour.r() >= 0x80) as u8;
+        let green = (colour.g() >= 0x80) as u8;
+        let blue = (colour.b() >= 0x80) as u8;
+        Colour::from_bits((red << 2) | (green << 1) | blue)
+    }
+}
+
+impl OriginDimensions for Canvas {
+    fn size(&self) -> Size {
+        Size::new(canvas::WIDTH as u32, canvas::HEIGHT as u32)
+    }
+}
+
+impl DrawTarget for Canvas {
+    type Color = Colour;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, colour) in pixels {
+            if point.x < 0
+                || point.y < 0
+                || point.x >= canvas::WIDTH as i32
+                || point.y >= canvas::HEIGHT as i32
+            {
+                continue;
+            }
+            let (x, y) = (point.x as i16, point.y as i16);
+            if colour == Colour::Black {
+                self.set_pixel(x, y, false);
+            } else {
+                self.set_pixel(x, y, true);
+                self.set_colour_at(x, y, Some(colour));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+//! Emulates the Monotron's SD card using a directory on the host.
+//!
+//! The directory is taken from `MONOTRON_SDCARD`, or is `./sdcard` if that
+//! isn't set. Applications can't see anything outside of it.
+
+use lazy_static::lazy_static;
+use monotron_api::{EmptyResult, Error, Handle, HandleResult, OpenMode, SizeResult};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+/// Handles 0, 1 and 2 look like stdin, stdout and stderr, so we don't hand
+/// those out for files.
+const FIRST_HANDLE: u16 = 3;
+
+lazy_static! {
+    static ref OPEN_FILES: Mutex<Vec<Option<File>>> = Mutex::new(Vec::new());
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Where the SD card is for the test running on this thread, so tests
+    /// don't have to change `MONOTRON_SDCARD` for everyone else.
+    pub static TEST_SDCARD: std::cell::RefCell<Option<PathBuf>> = std::cell::RefCell::new(None);
+}
+
+/// Work out where a Monotron filename lives on the host, refusing anything
+/// which would escape the SD card directory.
+fn host_path(filename: &str) -> Option<PathBuf> {
+    let relative = Path::new(filename);
+    let mut components = 0;
+    for component in relative.components() {
+        match component {
+            Component::Normal(_) => components += 1,
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    if components == 0 {
+        return None;
+    }
+    #[cfg(test)]
+    {
+        if let Some(root) = TEST_SDCARD.with(|root| root.borrow().clone()) {
+            return Some(root.join(relative));
+        }
+    }
+    let root = std::env::var_os("MONOTRON_SDCARD").unwrap_or_else(|| "sdcard".into());
+    Some(Path::new(&root).join(relative))
+}
+
+fn map_io_error(error: &std::io::Error) -> Error {
+    match error.kind() {
+        std::io::ErrorKind::NotFound => Error::FileNotFound,
+        _ => Error::IOError,
+    }
+}
+
+/// Open/create a file in the SD card directory.
+pub fn open(filename: &str, mode: OpenMode) -> HandleResult {
+    let path = match host_path(filename) {
+        Some(path) => path,
+        None => return HandleResult::Error(Error::FileNotFound),
+    };
+    let mut options = OpenOptions::new();
+    match mode {
+        OpenMode::ReadOnly { .. } => {
+            options.read(true);
+        }
+        OpenMode::WriteOnly {
+            append,
+            create,
+            exclusive,
+            truncate,
+            ..
+        } => {
+            options
+                .write(true)
+                .append(append)
+                .create(create)
+                .create_new(exclusive)
+                .truncate(truncate);
+        }
+        OpenMode::ReadWrite {
+            append,
+            create,
+            exclusive,
+            truncate,
+            ..
+        } => {
+            options
+                .read(true)
+                .write(true)
+                .append(append)
+                .create(create)
+                .create_new(exclusive)
+                .truncate(truncate);
+        }
+    }
+    let file = match options.open(&path) {
+        Ok(file) => file,
+        Err(e) => return HandleResult::Error(map_io_error(&e)),
+    };
+    let mut open_files = OPEN_FILES.lock().unwrap();
+    // Re-use the first free slot, so handle numbers stay small
+    let slot = match open_files.iter().position(Option::is_none) {
+        Some(slot) => slot,
+        None => {
+            open_files.push(None);
+            open_files.len() - 1
+        }
+    };
+    open_files[slot] = Some(file);
+    HandleResult::Ok(Handle(FIRST_HANDLE + slot as u16))
+}
+
+/// Run `f` on the file a handle refers to.
+fn with_file<T, F>(handle: Handle, f: F) -> Result<T, Error>
+where
+    F: FnOnce(&mut File) -> std::io::Result<T>,
+{
+    let mut open_files = OPEN_FILES.lock().unwrap();
+    let file = handle
+        .0
+        .checked_sub(FIRST_HANDLE)
+        .and_then(|slot| open_files.get_mut(slot as usize))
+        .and_then(Option::as_mut)
+        .ok_or(Error::BadFileHandle)?;
+    f(file).map_err(|e| map_io_error(&e))
+}
+
+/// Close a previously opened handle.
+pub fn close(handle: Handle) -> EmptyResult {
+    let mut open_files = OPEN_FILES.lock().unwrap();
+    let slot = handle
+        .0
+        .checked_sub(FIRST_HANDLE)
+        .and_then(|slot| open_files.get_mut(slot as usize));
+    match slot {
+        Some(slot) if slot.is_some() => {
+            *slot = None;
+            EmptyResult::Ok(())
+        }
+        _ => EmptyResult::Error(Error::BadFileHandle),
+    }
+}
+
+/// Read from a file handle into the given buffer.
+pub fn read(handle: Handle, buffer: &mut [u8]) -> SizeResult {
+    match with_file(handle, |file| file.read(buffer)) {
+        Ok(n) => SizeResult::Ok(n),
+        Err(e) => SizeResult::Error(e),
+    }
+}
+
+/// Write the contents of the given buffer to a file handle.
+pub fn write(handle: Handle, buffer: &[u8]) -> SizeResult {
+    match with_file(handle, |file| file.write(buffer)) {
+        Ok(n) => SizeResult::Ok(n),
+        Err(e) => SizeResult::Error(e),
+    }
+}
@@ -0,0 +1,563 @@
+//! Makes notes sound like instruments, by changing their volume and pitch
+//! every frame.
+//!
+//! `Host::play` only makes a steady tone. A `Voice` plays notes on one
+//! channel with an `Instrument`, which can have:
+//!
+//! * an `Envelope`, so notes fade in (attack), drop back (decay) to a level
+//!   they hold (sustain), and fade out when released (release),
+//! * `Vibrato`, which wobbles the pitch,
+//! * an `Arpeggio`, which steps quickly through the notes of a chord,
+//! * a slide, which bends the pitch up or down.
+//!
+//! Call `Voice::tick` once per frame (i.e. after every `Host::wfvbi`). The
+//! tracks in a `music::Sequencer` have a voice each, so you can give them
+//! instruments with `Track::set_instrument`.
+//!
+//! ```rust,ignore
+//! const PLUCK: Instrument = Instrument::new(Waveform::Square)
+//!     .with_envelope(Envelope::new(0, 8, 128, 6))
+//!     .with_vibrato(Vibrato::new(16, 10, 20));
+//!
+//! let mut voice = Voice::new(Channel::Channel0, PLUCK);
+//! voice.note_on(Note::C4, 255);
+//! for _ in 0..30 {
+//!     Host::wfvbi();
+//!     voice.tick();
+//! }
+//! voice.note_off();
+//! ```
+//!
+//! Pitches are changed in 64ths of a semitone.
+
+use super::{Channel, Frequency, Host, Note, Waveform};
+
+/// How many steps of pitch there are in a semitone.
+pub const STEPS_PER_SEMITONE: i32 = 64;
+
+const STEPS_PER_OCTAVE: i32 = STEPS_PER_SEMITONE * 12;
+
+/// How far a slide can bend a note, either way.
+const MAX_BEND: i32 = STEPS_PER_OCTAVE * 4;
+
+/// 2^(n/12) for each semitone in an octave, in 1/65536ths.
+static SEMITONE_RATIOS: [u32; 13] = [
+    65536, 69433, 73562, 77936, 82570, 87480, 92682, 98193, 104032, 110218, 116772, 123715, 131072,
+];
+
+/// How a note's volume changes over time. The times are in frames, and the
+/// sustain level is a fraction of the note's volume (255 is all of it).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    /// Frames to go from silent to full volume
+    pub attack: u8,
+    /// Frames to go from full volume to the sustain level
+    pub decay: u8,
+    /// The level the note holds until it's released
+    pub sustain: u8,
+    /// Frames to go from the sustain level to silent, once released
+    pub release: u8,
+}
+
+impl Envelope {
+    /// Full volume straight away, until the note is released.
+    pub const NONE: Envelope = Envelope::new(0, 0, 255, 0);
+
+    /// Make an envelope.
+    pub const fn new(attack: u8, decay: u8, sustain: u8, release: u8) -> Envelope {
+        Envelope {
+            attack,
+            decay,
+            sustain,
+            release,
+        }
+    }
+}
+
+/// Wobbles the pitch up and down.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Vibrato {
+    /// How far the pitch moves each way, in 64ths of a semitone
+    pub depth: u8,
+    /// How many frames one wobble takes
+    pub speed: u8,
+    /// How many frames into the note the wobbling starts
+    pub delay: u8,
+}
+
+impl Vibrato {
+    /// Make a vibrato.
+    pub const fn new(depth: u8, speed: u8, delay: u8) -> Vibrato {
+        Vibrato {
+            depth,
+            speed,
+            delay,
+        }
+    }
+
+    /// The pitch change `age` frames into a note. It's a triangle wave,
+    /// starting in the middle.
+    fn offset(&self, age: u16) -> i32 {
+        if age < u16::from(self.delay) || self.speed == 0 {
+            return 0;
+        }
+        let speed = i32::from(self.speed);
+        let depth = i32::from(self.depth);
+        let phase = i32::from(age - u16::from(self.delay)) % speed;
+        let pos = phase * 4 * depth / speed;
+        if pos < depth {
+            pos
+        } else if pos < 3 * depth {
+            2 * depth - pos
+        } else {
+            pos - 4 * depth
+        }
+    }
+}
+
+/// The most notes an `Arpeggio` can step through.
+pub const MAX_ARPEGGIO: usize = 4;
+
+/// Steps through a few notes, over and over, faster than you could play
+/// them - the old way of playing a chord on one channel.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Arpeggio {
+    /// Semitones above (or below) the note being played
+    offsets: [i8; MAX_ARPEGGIO],
+    count: u8,
+    /// Frames per note
+    speed: u8,
+}
+
+impl Arpeggio {
+    /// Step through `offsets` (semitones from the note being played),
+    /// spending `speed` frames on each. Only the first `MAX_ARPEGGIO` are
+    /// used. A major chord is `&[0, 4, 7]`.
+    pub const fn new(offsets: &[i8], speed: u8) -> Arpeggio {
+        let mut copy = [0; MAX_ARPEGGIO];
+        let mut count = 0;
+        while count < offsets.len() && count < MAX_ARPEGGIO {
+            copy[count] = offsets[count];
+            count += 1;
+        }
+        Arpeggio {
+            offsets: copy,
+            count: count as u8,
+            speed,
+        }
+    }
+
+    /// The notes we step through.
+    pub fn offsets(&self) -> &[i8] {
+        &self.offsets[..self.count as usize]
+    }
+
+    fn offset(&self, age: u16) -> i32 {
+        if self.count == 0 {
+            return 0;
+        }
+        let step = (age / u16::from(self.speed.max(1))) % u16::from(self.count);
+        i32::from(self.offsets[step as usize]) * STEPS_PER_SEMITONE
+    }
+}
+
+/// What a `Voice` does to the notes it plays.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Instrument {
+    /// The waveform to play
+    pub waveform: Waveform,
+    /// How the volume changes
+    pub envelope: Envelope,
+    /// How the pitch wobbles, if it does
+    pub vibrato: Option<Vibrato>,
+    /// The chord to step through, if any
+    pub arpeggio: Option<Arpeggio>,
+    /// How much the pitch bends each frame, in 64ths of a semitone
+    pub slide: i16,
+}
+
+impl Instrument {
+    /// A plain instrument, which plays notes exactly as they are.
+    pub const fn new(waveform: Waveform) -> Instrument {
+        Instrument {
+            waveform,
+            envelope: Envelope::NONE,
+            vibrato: None,
+            arpeggio: None,
+            slide: 0,
+        }
+    }
+
+    /// The same instrument with a different envelope.
+    pub const fn with_envelope(self, envelope: Envelope) -> Instrument {
+        Instrument { envelope, ..self }
+    }
+
+    /// The same instrument with vibrato.
+    pub const fn with_vibrato(self, vibrato: Vibrato) -> Instrument {
+        Instrument {
+            vibrato: Some(vibrato),
+            ..self
+        }
+    }
+
+    /// The same instrument with an arpeggio.
+    pub const fn with_arpeggio(self, arpeggio: Arpeggio) -> Instrument {
+        Instrument {
+            arpeggio: Some(arpeggio),
+            ..self
+        }
+    }
+
+    /// The same instrument with a pitch slide, in 64ths of a semitone per
+    /// frame. Negative numbers slide down.
+    pub const fn with_slide(self, slide: i16) -> Instrument {
+        Instrument { slide, ..self }
+    }
+}
+
+/// Where a note is in its envelope.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Off,
+}
+
+/// Plays notes on one channel with an instrument.
+#[derive(Debug, Clone)]
+pub struct Voice {
+    channel: Channel,
+    instrument: Instrument,
+    /// The note being played, before any effects.
+    frequency: Frequency,
+    volume: u8,
+    stage: Stage,
+    /// Frames since this stage started.
+    frames: u16,
+    /// Frames since the note started.
+    age: u16,
+    /// The level the release started from.
+    release_from: u8,
+    /// How far the slide has bent the note.
+    bend: i32,
+    /// What we last told the synthesiser, so `tick` only tells it about
+    /// changes. Anything else might share the channel, so the other calls
+    /// clear this to make sure they're heard.
+    playing: Option<(Frequency, Waveform, u8)>,
+}
+
+impl Voice {
+    /// Make a voice which plays on `channel`.
+    pub const fn new(channel: Channel, instrument: Instrument) -> Voice {
+        Voice {
+            channel,
+            instrument,
+            frequency: Frequency::from_centi_hz(0),
+            volume: 0,
+            stage: Stage::Off,
+            frames: 0,
+            age: 0,
+            release_from: 0,
+            bend: 0,
+            playing: None,
+        }
+    }
+
+    /// The channel we play on.
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    /// The instrument we play with.
+    pub fn instrument(&self) -> &Instrument {
+        &self.instrument
+    }
+
+    /// Change the instrument. A note which is playing carries on with the
+    /// new one.
+    pub fn set_instrument(&mut self, instrument: Instrument) {
+        self.instrument = instrument;
+    }
+
+    /// Change the instrument's waveform.
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        self.instrument.waveform = waveform;
+    }
+
+    /// Start a note, at `volume` (before the envelope). It's heard straight
+    /// away.
+    pub fn note_on<F>(&mut self, frequency: F, volume: u8)
+    where
+        F: Into<Frequency>,
+    {
+        self.frequency = frequency.into();
+        self.volume = volume;
+        self.stage = Stage::Attack;
+        self.frames = 0;
+        self.age = 0;
+        self.bend = 0;
+        self.next_stage();
+        self.playing = None;
+        self.output();
+    }
+
+    /// Let go of the note, so it fades out over the release time.
+    pub fn note_off(&mut self) {
+        match self.stage {
+            Stage::Release | Stage::Off => {}
+            _ => {
+                self.release_from = self.level();
+                self.stage = Stage::Release;
+                self.frames = 0;
+                self.next_stage();
+                self.playing = None;
+                self.output();
+            }
+        }
+    }
+
+    /// Stop the note straight away.
+    pub fn silence(&mut self) {
+        self.stage = Stage::Off;
+        self.playing = None;
+        self.output();
+    }
+
+    /// True if a note is playing, or fading out.
+    pub fn is_sounding(&self) -> bool {
+        self.stage != Stage::Off
+    }
+
+    /// Move the note on by one frame. Call this once per `Host::wfvbi`.
+    pub fn tick(&mut self) {
+        if self.stage == Stage::Off {
+            return;
+        }
+        self.frames = self.frames.saturating_add(1);
+        self.age = self.age.saturating_add(1);
+        self.bend = (self.bend + i32::from(self.instrument.slide)).clamp(-MAX_BEND, MAX_BEND);
+        self.next_stage();
+        self.output();
+    }
+
+    /// Move past any stages which have finished (or have no length).
+    fn next_stage(&mut self) {
+        let envelope = self.instrument.envelope;
+        loop {
+            let (length, next) = match self.stage {
+                Stage::Attack => (envelope.attack, Stage::Decay),
+                Stage::Decay => (envelope.decay, Stage::Sustain),
+                Stage::Release => (envelope.release, Stage::Off),
+                Stage::Sustain | Stage::Off => return,
+            };
+            if self.frames < u16::from(length) {
+                return;
+            }
+            self.stage = next;
+            self.frames = 0;
+        }
+    }
+
+    /// How loud the envelope says we are, from 0 to 255.
+    fn level(&self) -> u8 {
+        let envelope = self.instrument.envelope;
+        match self.stage {
+            Stage::Attack => ramp(0, 255, self.frames, envelope.attack),
+            Stage::Decay => ramp(255, envelope.sustain, self.frames, envelope.decay),
+            Stage::Sustain => envelope.sustain,
+            Stage::Release => ramp(self.release_from, 0, self.frames, envelope.release),
+            Stage::Off => 0,
+        }
+    }
+
+    /// Tell the synthesiser what we sound like now, if that's changed.
+    fn output(&mut self) {
+        let waveform = self.instrument.waveform;
+        let sound = if self.stage == Stage::Off {
+            (Note::Rest.frequency(), waveform, 0)
+        } else {
+            let mut steps = self.bend;
+            if let Some(vibrato) = self.instrument.vibrato {
+                steps += vibrato.offset(self.age);
+            }
+            if let Some(arpeggio) = self.instrument.arpeggio {
+                steps += arpeggio.offset(self.age);
+            }
+            let volume = u16::from(self.volume) * u16::from(self.level()) / 255;
+            (shift(self.frequency, steps), waveform, volume as u8)
+        };
+        if self.playing != Some(sound) {
+            self.playing = Some(sound);
+            let (frequency, waveform, volume) = sound;
+            Host::play(frequency, self.channel, waveform, volume);
+        }
+    }
+}
+
+/// Go from `from` to `to` in `length` frames, and say where we are after
+/// `frames` of them.
+fn ramp(from: u8, to: u8, frames: u16, length: u8) -> u8 {
+    if frames >= u16::from(length) {
+        return to;
+    }
+    let from = i32::from(from);
+    let to = i32::from(to);
+    (from + (to - from) * i32::from(frames) / i32::from(length)) as u8
+}
+
+/// Move a frequency up (or down) by `steps` 64ths of a semitone.
+pub fn shift(frequency: Frequency, steps: i32) -> Frequency {
+    if steps == 0 {
+        return frequency;
+    }
+    let octaves = steps.div_euclid(STEPS_PER_OCTAVE);
+    let within = steps.rem_euclid(STEPS_PER_OCTAVE);
+    let semitone = (within / STEPS_PER_SEMITONE) as usize;
+    let fraction = u64::from((within % STEPS_PER_SEMITONE) as u32);
+    // Straight lines between the semitones are close enough
+    let low = u64::from(SEMITONE_RATIOS[semitone]);
+    let high = u64::from(SEMITONE_RATIOS[semitone + 1]);
+    let ratio = low + (high - low) * fraction / STEPS_PER_SEMITONE as u64;
+    let mut centi_hz = (u64::from(frequency.as_centi_hz()) * ratio) >> 16;
+    if octaves >= 0 {
+        centi_hz <<= octaves.min(16);
+    } else {
+        centi_hz >>= (-octaves).min(63);
+    }
+    Frequency::from_centi_hz(centi_hz.min(u64::from(u32::MAX)) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hz(centi_hz: u32) -> Frequency {
+        Frequency::from_centi_hz(centi_hz)
+    }
+
+    #[test]
+    fn shifting() {
+        let a4 = hz(44_000);
+        assert_eq!(shift(a4, 0), a4);
+        assert_eq!(shift(a4, STEPS_PER_OCTAVE), hz(88_000));
+        assert_eq!(shift(a4, -STEPS_PER_OCTAVE), hz(22_000));
+        assert_eq!(shift(a4, 2 * STEPS_PER_OCTAVE), hz(176_000));
+        let up = (44_000 * u64::from(SEMITONE_RATIOS[1])) >> 16;
+        assert_eq!(shift(a4, STEPS_PER_SEMITONE), hz(up as u32));
+        let down = (44_000 * u64::from(SEMITONE_RATIOS[11])) >> 17;
+        assert_eq!(shift(a4, -STEPS_PER_SEMITONE), hz(down as u32));
+        // Half a semitone is half way between the two
+        let half = (44_000 * (u64::from(SEMITONE_RATIOS[0] + SEMITONE_RATIOS[1]) / 2)) >> 16;
+        assert_eq!(shift(a4, STEPS_PER_SEMITONE / 2), hz(half as u32));
+        // Way out of range doesn't overflow
+        assert_eq!(shift(a4, 100 * STEPS_PER_OCTAVE), hz(44_000 << 16));
+        assert_eq!(shift(a4, -100 * STEPS_PER_OCTAVE), hz(0));
+    }
+
+    #[test]
+    fn ramps() {
+        assert_eq!(ramp(0, 255, 0, 4), 0);
+        assert_eq!(ramp(0, 255, 2, 4), 127);
+        assert_eq!(ramp(0, 255, 4, 4), 255);
+        assert_eq!(ramp(255, 55, 1, 2), 155);
+        assert_eq!(ramp(100, 0, 0, 0), 0);
+    }
+
+    /// Play a note for `held` frames and let it go, and list the stages and
+    /// levels it went through, one per frame.
+    fn envelope(envelope: Envelope, held: usize, frames: usize) -> Vec<(Stage, u8)> {
+        let instrument = Instrument::new(Waveform::Square).with_envelope(envelope);
+        let mut voice = Voice::new(Channel::Channel0, instrument);
+        voice.note_on(Note::A4, 255);
+        let mut seen = Vec::new();
+        for frame in 0..frames {
+            if frame == held {
+                voice.note_off();
+            }
+            seen.push((voice.stage, voice.level()));
+            voice.tick();
+        }
+        seen
+    }
+
+    #[test]
+    fn envelopes() {
+        use self::Stage::*;
+        assert_eq!(
+            envelope(Envelope::new(2, 2, 100, 2), 6, 10),
+            [
+                (Attack, 0),
+                (Attack, 127),
+                (Decay, 255),
+                (Decay, 178),
+                (Sustain, 100),
+                (Sustain, 100),
+                (Release, 100),
+                (Release, 50),
+                (Off, 0),
+                (Off, 0),
+            ]
+        );
+        // Stages with no length are skipped straight away
+        assert_eq!(
+            envelope(Envelope::NONE, 2, 4),
+            [(Sustain, 255), (Sustain, 255), (Off, 0), (Off, 0)]
+        );
+        assert_eq!(
+            envelope(Envelope::new(0, 2, 0, 0), 3, 4),
+            [(Decay, 255), (Decay, 128), (Sustain, 0), (Off, 0)]
+        );
+        // Letting go part way up releases from where we'd got to
+        assert_eq!(
+            envelope(Envelope::new(4, 0, 255, 2), 2, 5),
+            [
+                (Attack, 0),
+                (Attack, 63),
+                (Release, 127),
+                (Release, 64),
+                (Off, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn vibrato() {
+        let vibrato = Vibrato::new(8, 8, 2);
+        let offsets: Vec<i32> = (0..12).map(|age| vibrato.offset(age)).collect();
+        assert_eq!(offsets, [0, 0, 0, 4, 8, 4, 0, -4, -8, -4, 0, 4]);
+        assert_eq!(Vibrato::new(8, 0, 0).offset(5), 0);
+    }
+
+    #[test]
+    fn arpeggio() {
+        let arpeggio = Arpeggio::new(&[0, 4, 7], 2);
+        assert_eq!(arpeggio.offsets(), [0, 4, 7]);
+        let steps: Vec<i32> = (0..8)
+            .map(|age| arpeggio.offset(age) / STEPS_PER_SEMITONE)
+            .collect();
+        assert_eq!(steps, [0, 0, 4, 4, 7, 7, 0, 0]);
+        // Only the first few notes are kept, and a speed of 0 is 1
+        let arpeggio = Arpeggio::new(&[0, 1, 2, 3, 4, 5], 0);
+        assert_eq!(arpeggio.offsets(), [0, 1, 2, 3]);
+        assert_eq!(arpeggio.offset(5), STEPS_PER_SEMITONE);
+        assert_eq!(Arpeggio::new(&[], 2).offset(3), 0);
+    }
+
+    #[test]
+    fn slides() {
+        let instrument = Instrument::new(Waveform::Square).with_slide(STEPS_PER_SEMITONE as i16);
+        let mut voice = Voice::new(Channel::Channel0, instrument);
+        voice.note_on(Note::A4, 255);
+        for _ in 0..12 {
+            voice.tick();
+        }
+        assert_eq!(voice.bend, STEPS_PER_OCTAVE);
+        for _ in 0..100 {
+            voice.tick();
+        }
+        assert_eq!(voice.bend, MAX_BEND);
+    }
+}
@@ -51,6 +51,42 @@ extern crate lazy_static;
 #[cfg(not(target_os = "none"))]
 mod sdl_window;
 
+#[cfg(not(target_os = "none"))]
+mod filesystem;
+
+#[cfg(not(target_os = "none"))]
+mod script;
+
+#[cfg(not(target_os = "none"))]
+mod capture;
+
+#[cfg(not(target_os = "none"))]
+mod clock;
+
+#[cfg(not(target_os = "none"))]
+mod pacer;
+
+pub mod canvas;
+
+pub mod codepage;
+
+#[cfg(feature = "embedded-graphics")]
+mod draw_target;
+
+pub mod instrument;
+
+pub mod line_editor;
+
+pub mod midi;
+
+pub mod music;
+
+pub mod notation;
+
+pub mod text_buffer;
+
+pub mod ui;
+
 pub use monotron_api::*;
 
 /// Represents the Monotron we're running on. Can be passed to `write!` and
@@ -81,15 +117,214 @@ pub enum Font {
     Custom(&'static [u8]),
 }
 
+/// True if the Teletext font is selected, so `write!` knows which glyphs to
+/// use.
+static TELETEXT_SELECTED: core::sync::atomic::AtomicBool =
+    core::sync::atomic::AtomicBool::new(false);
+
+impl Host {
+    /// Remember which font was selected.
+    fn font_changed(font: &Font) {
+        let teletext = matches!(font, Font::Teletext);
+        TELETEXT_SELECTED.store(teletext, core::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Set the colour new text is drawn in.
+    pub fn set_foreground(colour: Colour) {
+        Host::puts(&[0x1B, colour.escape_letter()]);
+    }
+
+    /// Set the colour drawn behind new text.
+    pub fn set_background(colour: Colour) {
+        Host::puts(&[0x1B, colour.escape_letter().to_ascii_lowercase()]);
+    }
+
+    /// Set both colours for new text.
+    pub fn set_attr(attr: Attr) {
+        Host::set_foreground(attr.fg());
+        Host::set_background(attr.bg());
+    }
+
+    /// Fill the screen with the background colour, and move the cursor to the
+    /// top left.
+    pub fn clear_screen() {
+        Host::puts(b"\x1BZ");
+    }
+
+    /// Draw the current line as normal text, or as the top or bottom half of
+    /// double-height text. To show double-height text, write it once on a
+    /// `Top` line and again on the `Bottom` line below.
+    pub fn set_double_height(mode: DoubleHeight) {
+        let letter = match mode {
+            DoubleHeight::Off => b'-',
+            DoubleHeight::Top => b'^',
+            DoubleHeight::Bottom => b'v',
+        };
+        Host::puts(&[0x1B, letter]);
+    }
+
+    /// The code page which matches the selected font.
+    pub fn codepage() -> codepage::Codepage {
+        if TELETEXT_SELECTED.load(core::sync::atomic::Ordering::Relaxed) {
+            codepage::Codepage::Teletext
+        } else {
+            codepage::Codepage::Cp850
+        }
+    }
+
+    /// Print a UTF-8 string, converted to glyphs the same way as `write!`.
+    pub fn puts_utf8(string: &str) {
+        let _ = core::fmt::Write::write_str(&mut Host, string);
+    }
+}
+
+impl core::fmt::Write for Host {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        // Converted here, rather than by the firmware or the emulator, so
+        // both give the same result. Sent a chunk at a time, rather than a
+        // character at a time, as each call costs us.
+        let codepage = Host::codepage();
+        let mut chunk = [0u8; 32];
+        let mut len = 0;
+        for ch in s.chars() {
+            chunk[len] = codepage.encode(ch);
+            len += 1;
+            if len == chunk.len() {
+                Host::puts(&chunk);
+                len = 0;
+            }
+        }
+        if len > 0 {
+            Host::puts(&chunk[..len]);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+/// One of the eight colours the Monotron can show.
+pub enum Colour {
+    /// No red, green or blue
+    Black = 0,
+    /// Blue only
+    Blue = 1,
+    /// Green only
+    Green = 2,
+    /// Green and blue
+    Cyan = 3,
+    /// Red only
+    Red = 4,
+    /// Red and blue
+    Magenta = 5,
+    /// Red and green
+    Yellow = 6,
+    /// Red, green and blue
+    White = 7,
+}
+
+impl Colour {
+    /// Convert the bottom three bits of a number (red, green and blue, in
+    /// that order) into a Colour.
+    pub fn from_bits(bits: u8) -> Colour {
+        match bits & 0b111 {
+            0 => Colour::Black,
+            1 => Colour::Blue,
+            2 => Colour::Green,
+            3 => Colour::Cyan,
+            4 => Colour::Red,
+            5 => Colour::Magenta,
+            6 => Colour::Yellow,
+            _ => Colour::White,
+        }
+    }
+
+    /// The letter which selects this as the foreground colour, after an
+    /// Escape. The lower-case version selects it as the background colour.
+    fn escape_letter(self) -> u8 {
+        match self {
+            Colour::Black => b'K',
+            Colour::Blue => b'B',
+            Colour::Green => b'G',
+            Colour::Cyan => b'C',
+            Colour::Red => b'R',
+            Colour::Magenta => b'M',
+            Colour::Yellow => b'Y',
+            Colour::White => b'W',
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// The colours of a cell on screen, as returned by `Host::read_char_at`.
+pub struct Attr(u8);
+
+impl Attr {
+    /// Make an attribute from a foreground and a background colour.
+    pub fn new(fg: Colour, bg: Colour) -> Attr {
+        Attr(((fg as u8) << 3) | (bg as u8))
+    }
+
+    /// Wrap up the raw attribute byte for a cell.
+    pub fn from_u8(attr: u8) -> Attr {
+        Attr(attr)
+    }
+
+    /// Get the raw attribute byte.
+    pub fn as_u8(self) -> u8 {
+        self.0
+    }
+
+    /// The colour the glyph is drawn in.
+    pub fn fg(self) -> Colour {
+        Colour::from_bits(self.0 >> 3)
+    }
+
+    /// The colour behind the glyph.
+    pub fn bg(self) -> Colour {
+        Colour::from_bits(self.0)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Which half of some double-height text the current line shows. See
+/// `Host::set_double_height`.
+pub enum DoubleHeight {
+    /// Normal-height text
+    Off,
+    /// The top half of double-height text
+    Top,
+    /// The bottom half of double-height text
+    Bottom,
+}
+
 #[derive(Debug, Copy, Clone)]
 /// Represents the current state of an Atari 9-pin joystick.
 pub struct JoystickState(u8);
 
+#[cfg(not(target_os = "none"))]
 #[derive(Debug, Copy, Clone)]
+/// Input which can be fed to the emulator instead of using the keyboard. See
+/// `Host::script_input`.
+pub enum InputEvent {
+    /// A byte arrives for `readc`
+    Key(u8),
+    /// The joystick moves to this state, and stays there
+    Joystick(JoystickState),
+    /// A key goes down, for `read_key_event`
+    KeyDown(KeyCode, Modifiers),
+    /// A key comes back up, for `read_key_event`
+    KeyUp(KeyCode, Modifiers),
+    /// Print the screen as text on stdout, and exit
+    Quit,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(transparent)]
 /// A frequency we can give to the synthesiser.
 pub struct Frequency(u32);
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(u8)]
 /// A channel on the synthesiser. They all run concurrently.
 pub enum Channel {
@@ -101,7 +336,7 @@ pub enum Channel {
     Channel2 = 2,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(u8)]
 /// A waveform on the synthesiser. You can change this note by note.
 pub enum Waveform {
@@ -132,7 +367,7 @@ impl Frequency {
     }
 
     /// Convert a number of centi-Hz into a Frequency.
-    pub fn from_centi_hz(centi_hz: u32) -> Frequency {
+    pub const fn from_centi_hz(centi_hz: u32) -> Frequency {
         Frequency(centi_hz)
     }
 }
@@ -164,6 +399,237 @@ impl JoystickState {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(transparent)]
+/// Identifies a key on the keyboard. Keys which type a character use the
+/// ASCII code of that character when unshifted (so the A key is `b'a'`, and
+/// Return is `b'\r'`). Other keys use the constants below.
+pub struct KeyCode(pub u16);
+
+#[allow(missing_docs)]
+impl KeyCode {
+    pub const UP: KeyCode = KeyCode(0x100);
+    pub const DOWN: KeyCode = KeyCode(0x101);
+    pub const LEFT: KeyCode = KeyCode(0x102);
+    pub const RIGHT: KeyCode = KeyCode(0x103);
+    pub const HOME: KeyCode = KeyCode(0x104);
+    pub const END: KeyCode = KeyCode(0x105);
+    pub const INSERT: KeyCode = KeyCode(0x106);
+    pub const PAGE_UP: KeyCode = KeyCode(0x107);
+    pub const PAGE_DOWN: KeyCode = KeyCode(0x108);
+    pub const LEFT_SHIFT: KeyCode = KeyCode(0x110);
+    pub const RIGHT_SHIFT: KeyCode = KeyCode(0x111);
+    pub const LEFT_CTRL: KeyCode = KeyCode(0x112);
+    pub const RIGHT_CTRL: KeyCode = KeyCode(0x113);
+    pub const LEFT_ALT: KeyCode = KeyCode(0x114);
+    pub const RIGHT_ALT: KeyCode = KeyCode(0x115);
+    /// F2 is `KeyCode(KeyCode::F1.0 + 1)`, and so on up to F12.
+    pub const F1: KeyCode = KeyCode(0x121);
+}
+
+/// Turns the bytes from `Host::readc` back into `KeyCode`s, by undoing the
+/// escape sequences the cursor keys and function keys arrive as. Delete
+/// comes out as `KeyCode(0x7F)`, which is what `readc` gives for it anyway.
+#[derive(Debug, Default, Clone)]
+pub struct KeyDecoder {
+    state: DecodeState,
+    /// The key for a byte which came straight after an Escape on its own,
+    /// which has to wait until the Escape has been returned.
+    pending: Option<KeyCode>,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+enum DecodeState {
+    #[default]
+    Idle,
+    /// We've had an Escape
+    Escape,
+    /// We've had `ESC [` and maybe some digits
+    Csi(u8),
+    /// We've had `ESC O`
+    Ss3,
+}
+
+impl KeyDecoder {
+    /// Make a decoder, not part-way through anything.
+    pub const fn new() -> KeyDecoder {
+        KeyDecoder {
+            state: DecodeState::Idle,
+            pending: None,
+        }
+    }
+
+    /// Feed in the next byte from `readc`. Returns a key, if that byte
+    /// finished one. Sequences we don't recognise are dropped.
+    ///
+    /// If an Escape is followed by a byte which doesn't start a sequence,
+    /// the Escape comes back first and the key for that byte comes back from
+    /// the next call to `feed` or `idle`, so keys come out one behind until
+    /// `idle` catches up.
+    pub fn feed(&mut self, byte: u8) -> Option<KeyCode> {
+        let waiting = self.pending.take();
+        let key = self.decode(byte);
+        match waiting {
+            Some(waiting) => {
+                self.pending = key;
+                Some(waiting)
+            }
+            None => key,
+        }
+    }
+
+    fn decode(&mut self, byte: u8) -> Option<KeyCode> {
+        let state = self.state;
+        self.state = DecodeState::Idle;
+        match state {
+            DecodeState::Idle if byte == 0x1B => {
+                self.state = DecodeState::Escape;
+                None
+            }
+            DecodeState::Idle => Some(KeyCode(u16::from(byte))),
+            DecodeState::Escape => {
+                match byte {
+                    b'[' => self.state = DecodeState::Csi(0),
+                    b'O' => self.state = DecodeState::Ss3,
+                    // The Escape was on its own, and this one might be too
+                    0x1B => {
+                        self.state = DecodeState::Escape;
+                        return Some(KeyCode(0x1B));
+                    }
+                    // The Escape was on its own, so this byte is a key of its
+                    // own, which has to wait its turn
+                    _ => {
+                        self.pending = Some(KeyCode(u16::from(byte)));
+                        return Some(KeyCode(0x1B));
+                    }
+                }
+                None
+            }
+            DecodeState::Csi(param) => match byte {
+                b'0'..=b'9' => {
+                    let param = param.saturating_mul(10).saturating_add(byte - b'0');
+                    self.state = DecodeState::Csi(param);
+                    None
+                }
+                b'A' => Some(KeyCode::UP),
+                b'B' => Some(KeyCode::DOWN),
+                b'C' => Some(KeyCode::RIGHT),
+                b'D' => Some(KeyCode::LEFT),
+                b'H' => Some(KeyCode::HOME),
+                b'F' => Some(KeyCode::END),
+                b'~' => match param {
+                    1 => Some(KeyCode::HOME),
+                    2 => Some(KeyCode::INSERT),
+                    3 => Some(KeyCode(0x7F)),
+                    4 => Some(KeyCode::END),
+                    5 => Some(KeyCode::PAGE_UP),
+                    6 => Some(KeyCode::PAGE_DOWN),
+                    15 => Some(KeyCode(KeyCode::F1.0 + 4)),
+                    17..=21 => Some(KeyCode(KeyCode::F1.0 + u16::from(param) - 12)),
+                    23 | 24 => Some(KeyCode(KeyCode::F1.0 + u16::from(param) - 13)),
+                    _ => None,
+                },
+                _ => None,
+            },
+            DecodeState::Ss3 => match byte {
+                b'P'..=b'S' => Some(KeyCode(KeyCode::F1.0 + u16::from(byte - b'P'))),
+                b'H' => Some(KeyCode::HOME),
+                b'F' => Some(KeyCode::END),
+                _ => None,
+            },
+        }
+    }
+
+    /// Call when there are no more bytes waiting. Returns a key which
+    /// `feed` held back, if there is one, or else the Escape key if the last
+    /// byte was an Escape on its own.
+    pub fn idle(&mut self) -> Option<KeyCode> {
+        if let Some(key) = self.pending.take() {
+            Some(key)
+        } else if self.state == DecodeState::Escape {
+            self.state = DecodeState::Idle;
+            Some(KeyCode(0x1B))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(transparent)]
+/// Which of Shift, Ctrl and Alt were held down during a `KeyEvent`.
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    /// True if either Shift key was held down.
+    pub fn is_shift(&self) -> bool {
+        (self.0 & 0b001) != 0
+    }
+
+    /// True if either Ctrl key was held down.
+    pub fn is_ctrl(&self) -> bool {
+        (self.0 & 0b010) != 0
+    }
+
+    /// True if either Alt key was held down.
+    pub fn is_alt(&self) -> bool {
+        (self.0 & 0b100) != 0
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+/// A key going down or coming back up. See `Host::read_key_event`.
+pub struct KeyEvent {
+    /// Which key it was
+    pub code: KeyCode,
+    /// True if the key went down, false if it came back up
+    pub pressed: bool,
+    /// What else was held down at the time
+    pub modifiers: Modifiers,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+/// Where the mouse pointer is, and which buttons are held down. See
+/// `Host::get_mouse`.
+pub struct MouseState {
+    /// The text row the pointer is over (`0..=35`)
+    pub row: u8,
+    /// The text column the pointer is over (`0..=47`)
+    pub col: u8,
+    /// The pixel the pointer is over, across from the left of the text
+    /// (`0..=383`)
+    pub x: u16,
+    /// The scan-line the pointer is over, down from the top of the text
+    /// (`0..=575`)
+    pub y: u16,
+    /// Which buttons are held down
+    pub buttons: u8,
+}
+
+impl MouseState {
+    /// The text cell the pointer is over.
+    pub fn cell(&self) -> (Row, Col) {
+        (Row(self.row), Col(self.col))
+    }
+
+    /// True if the left button is held down.
+    pub fn is_left_pressed(&self) -> bool {
+        (self.buttons & 0b001) != 0
+    }
+
+    /// True if the right button is held down.
+    pub fn is_right_pressed(&self) -> bool {
+        (self.buttons & 0b010) != 0
+    }
+
+    /// True if the middle button is held down.
+    pub fn is_middle_pressed(&self) -> bool {
+        (self.buttons & 0b100) != 0
+    }
+}
+
 /// Notes on an piano keyboard, where A4 = 440 Hz.
 #[derive(Debug, Copy, Clone, PartialEq)]
 #[repr(u8)]
@@ -280,8 +746,9 @@ pub enum Note {
     B8,
 }
 
-impl core::convert::Into<Frequency> for Note {
-    fn into(self) -> Frequency {
+impl Note {
+    /// The frequency of the note. `Rest` is 0 Hz.
+    pub const fn frequency(self) -> Frequency {
         Frequency::from_centi_hz(match self {
             Note::Rest => 0,
             Note::C0 => 1635,
@@ -394,6 +861,24 @@ impl core::convert::Into<Frequency> for Note {
             Note::B8 => 7902_13,
         })
     }
+
+    /// The note for a MIDI note number, where 60 is middle C (C4). Notes
+    /// below C0 (12) or above B8 (119) give `None`.
+    pub const fn from_midi(key: u8) -> Option<Note> {
+        if key < 12 || key > 119 {
+            None
+        } else {
+            // The notes are numbered in order from C0 = 1 to B8, and we
+            // checked the range above.
+            Some(unsafe { core::mem::transmute::<u8, Note>(key - 11) })
+        }
+    }
+}
+
+impl core::convert::Into<Frequency> for Note {
+    fn into(self) -> Frequency {
+        self.frequency()
+    }
 }
 
 #[cfg(target_os = "none")]
@@ -437,13 +922,6 @@ pub mod target {
         }
     }
 
-    impl core::fmt::Write for Host {
-        fn write_str(&mut self, s: &str) -> core::fmt::Result {
-            Host::puts_utf8(s);
-            Ok(())
-        }
-    }
-
     impl Host {
         /// Get the (width, height) of the Monotron TTY
         pub fn getsize() -> (u16, u16) {
@@ -456,7 +934,7 @@ pub mod target {
             (tbl.putchar)(ch);
         }
 
-        /// Send a single 8-bit character to the screen.
+        /// Send some 8-bit characters to the screen.
         pub fn puts(str8bit: &[u8]) {
             let tbl = get_api();
             for &ch in str8bit {
@@ -476,6 +954,16 @@ pub mod target {
             (tbl.readc)() as u8
         }
 
+        /// Get the next key going down or coming up, if there is one. Doesn't
+        /// block.
+        ///
+        /// The Monotron firmware only gives us characters, and taking them
+        /// here would take them away from `readc`, so until it can report
+        /// real key events this always returns `None`.
+        pub fn read_key_event() -> Option<KeyEvent> {
+            None
+        }
+
         /// Wait For Vertical Blanking Interval
         pub fn wfvbi() {
             let tbl = get_api();
@@ -492,10 +980,10 @@ pub mod target {
         ///
         /// Returns the 8-bit glyph in the given cell, and the attribute for
         /// that cell.
-        pub fn read_char_at(row: Row, col: Col) -> (u8, u8) {
+        pub fn read_char_at(row: Row, col: Col) -> (u8, Attr) {
             let tbl = get_api();
             let word = (tbl.read_char_at)(row.0, col.0);
-            ((word >> 8) as u8, word as u8)
+            ((word >> 8) as u8, Attr(word as u8))
         }
 
         /// Re-map visible scanlines
@@ -540,6 +1028,7 @@ pub mod target {
                     (tbl.change_font)(2, ram.as_ptr());
                 }
             }
+            Host::font_changed(&font);
             Ok(())
         }
 
@@ -550,6 +1039,14 @@ pub mod target {
             JoystickState(b)
         }
 
+        /// Get the mouse position and buttons, or `None` if there is no mouse.
+        ///
+        /// The Monotron firmware doesn't support a PS/2 mouse yet, so for now
+        /// this always returns `None`.
+        pub fn get_mouse() -> Option<MouseState> {
+            None
+        }
+
         /// Show/hide the cursor
         pub fn set_cursor_visible(visible: bool) {
             let tbl = get_api();
@@ -563,12 +1060,6 @@ pub mod target {
             (tbl.gettime)()
         }
 
-        /// Print a UTF-8 string
-        pub fn puts_utf8(string: &str) {
-            let tbl = get_api();
-            (tbl.puts_utf8)(string.as_ptr(), string.len());
-        }
-
         /// Open/create a device/file. Returns a file handle, or an error.
         pub fn open(filename: &str, mode: OpenMode) -> HandleResult {
             let tbl = get_api();
@@ -642,18 +1133,6 @@ pub mod target {
         static ref VIDEO_CONTEXT: Mutex<Option<sdl_window::Context<'static>>> = Mutex::new(None);
     }
 
-    impl std::fmt::Write for Host {
-        fn write_str(&mut self, s: &str) -> std::fmt::Result {
-            for b in s.bytes() {
-                // Ugh - this will force UTF-8 (the Rust native string format)
-                // into ASCII CodePage 850, which will do bad things for
-                // characters that aren't in the basic ASCII set.
-                Host::putchar(b);
-            }
-            Ok(())
-        }
-    }
-
     impl Host {
         /// Get the (width, height) of the Monotron TTY
         pub fn getsize() -> (u16, u16) {
@@ -666,9 +1145,11 @@ pub mod target {
             *VIDEO_CONTEXT.lock().unwrap() = Some(sdl_window::Context::new());
         }
 
-        /// Disable ncurses
+        /// Call once at exit, to finish off any video recording
         pub fn deinit() {
-            // destroy SDL2 window
+            if let Some(ref mut ctx) = *VIDEO_CONTEXT.lock().unwrap() {
+                ctx.stop_recording();
+            }
         }
 
         /// Send a single 8-bit character to the screen.
@@ -699,18 +1180,39 @@ pub mod target {
         }
 
         /// Read an 8-bit character from the console.
+        ///
+        /// Panics in headless mode if there is no more scripted input, as
+        /// otherwise we'd wait forever.
         pub fn readc() -> u8 {
             loop {
+                let mut exhausted = false;
                 if let Some(ref mut ctx) = *VIDEO_CONTEXT.lock().unwrap() {
                     if let Some(ch) = ctx.keypresses.pop_front() {
                         break ch;
                     }
+                    exhausted = ctx.input_exhausted();
+                }
+                if exhausted {
+                    panic!("readc() called in headless mode with no more scripted input");
                 }
                 // Need to pump the event loop to get keypresses
                 wfvbi();
             }
         }
 
+        /// Get the next key going down or coming up, if there is one. Doesn't
+        /// block, so call `wfvbi` to wait for more.
+        ///
+        /// These events come alongside the characters from `readc`, not
+        /// instead of them.
+        pub fn read_key_event() -> Option<KeyEvent> {
+            if let Some(ref mut ctx) = *VIDEO_CONTEXT.lock().unwrap() {
+                ctx.key_events.pop_front()
+            } else {
+                None
+            }
+        }
+
         /// Wait For Vertical Blanking Interval
         pub fn wfvbi() {
             // redraw the screen here, as apps should call wfvbi often yes,
@@ -719,8 +1221,8 @@ pub mod target {
             if let Some(ref mut ctx) = *VIDEO_CONTEXT.lock().unwrap() {
                 ctx.draw();
                 ctx.pump();
+                ctx.wait_for_frame();
             }
-            ::std::thread::sleep(::std::time::Duration::from_micros(1_000_000 / 60));
         }
 
         /// Move the cursor on the screen.
@@ -743,19 +1245,19 @@ pub mod target {
         ///
         /// Returns the 8-bit glyph in the given cell, and the attribute for
         /// that cell.
-        pub fn read_char_at(row: Row, col: Col) -> (u8, u8) {
+        pub fn read_char_at(row: Row, col: Col) -> (u8, Attr) {
             if let Some(ref mut ctx) = *VIDEO_CONTEXT.lock().unwrap() {
                 let p = vga_framebuffer::Position::new(
                     vga_framebuffer::Row(row.0),
                     vga_framebuffer::Col(col.0),
                 );
                 if let Some((glyph, attr)) = ctx.fb.read_glyph_at(p) {
-                    (glyph as u8, attr.as_u8())
+                    (glyph as u8, Attr(attr.as_u8()))
                 } else {
-                    (0, 0)
+                    (0, Attr(0))
                 }
             } else {
-                (0, 0)
+                (0, Attr(0))
             }
         }
 
@@ -778,11 +1280,13 @@ pub mod target {
         }
 
         /// Start playing a tone. It will continue.
-        pub fn play<F>(_frequency: F, _channel: Channel, _waveform: Waveform, _volume: u8)
+        pub fn play<F>(frequency: F, channel: Channel, waveform: Waveform, volume: u8)
         where
             F: Into<Frequency>,
         {
-
+            if let Some(ref mut ctx) = *VIDEO_CONTEXT.lock().unwrap() {
+                ctx.play(frequency.into(), channel, waveform, volume);
+            }
         }
 
         /// Move the cursor on the screen.
@@ -795,8 +1299,15 @@ pub mod target {
                     Font::Teletext => ctx
                         .fb
                         .set_custom_font(Some(&vga_framebuffer::freebsd_teletext::FONT_DATA)),
-                    Font::Custom(_ram) => unimplemented!(),
+                    Font::Custom(ram) => {
+                        use std::convert::TryFrom;
+                        match <&[u8; 4096]>::try_from(ram) {
+                            Ok(font_data) => ctx.fb.set_custom_font(Some(font_data)),
+                            Err(_) => return Err("bad font length"),
+                        }
+                    }
                 }
+                Host::font_changed(&font);
                 Ok(())
             } else {
                 Err("Failed video lock")
@@ -805,7 +1316,21 @@ pub mod target {
 
         /// Get the Joystick state
         pub fn get_joystick() -> JoystickState {
-            JoystickState(0)
+            if let Some(ref mut ctx) = *VIDEO_CONTEXT.lock().unwrap() {
+                JoystickState(ctx.joystick())
+            } else {
+                JoystickState(0)
+            }
+        }
+
+        /// Get the mouse position and buttons, or `None` if there is no mouse
+        /// (e.g. in headless mode).
+        pub fn get_mouse() -> Option<MouseState> {
+            if let Some(ref ctx) = *VIDEO_CONTEXT.lock().unwrap() {
+                ctx.mouse()
+            } else {
+                None
+            }
         }
 
         /// Show/hide the cursor
@@ -817,9 +1342,16 @@ pub mod target {
 
         /// Get the current calendar time. This system does not understand
         /// time zones, or leap seconds.
+        ///
+        /// If `MONOTRON_CLOCK` is set, this is a fake time which only moves
+        /// when `wfvbi` is called (see README.md).
         pub fn gettime() -> monotron_api::Timestamp {
             use chrono::prelude::*;
-            let local: DateTime<Local> = Local::now();
+            let fake_time = match *VIDEO_CONTEXT.lock().unwrap() {
+                Some(ref ctx) => ctx.fake_time(),
+                None => None,
+            };
+            let local: NaiveDateTime = fake_time.unwrap_or_else(|| Local::now().naive_local());
             monotron_api::Timestamp {
                 /// The Gregorian calendar year, minus 1970 (so 10 is 1980, and 30 is the year 2000)
                 year_from_1970: (local.year() - 1970) as u8,
@@ -838,35 +1370,125 @@ pub mod target {
             }
         }
 
-        /// Print a UTF-8 string
-        pub fn puts_utf8(string: &str) {
-            use std::fmt::Write;
+        /// Feed input to the emulator, as if it came from the keyboard or
+        /// joystick. It arrives once `wfvbi` has been called `frame` times (see
+        /// `frame_count`). Useful for testing, especially in headless mode.
+        pub fn script_input(frame: u64, event: InputEvent) {
+            if let Some(ref mut ctx) = *VIDEO_CONTEXT.lock().unwrap() {
+                ctx.script_input(frame, event);
+            }
+        }
+
+        /// Get the number of times `wfvbi` has been called.
+        pub fn frame_count() -> u64 {
+            if let Some(ref ctx) = *VIDEO_CONTEXT.lock().unwrap() {
+                ctx.frame_count()
+            } else {
+                0
+            }
+        }
+
+        /// Get the number of frames the emulator has dropped because it
+        /// couldn't keep up with 60 Hz.
+        pub fn dropped_frames() -> u64 {
+            if let Some(ref ctx) = *VIDEO_CONTEXT.lock().unwrap() {
+                ctx.dropped_frames()
+            } else {
+                0
+            }
+        }
+
+        /// Read back the screen as text, with one line per row. Glyphs which
+        /// aren't printable ASCII come back as `?`.
+        pub fn screen_text() -> String {
             if let Some(ref mut ctx) = *VIDEO_CONTEXT.lock().unwrap() {
-                ctx.fb.write_str(string).unwrap();
+                ctx.screen_text()
+            } else {
+                String::new()
+            }
+        }
+
+        /// Read back every cell on the screen, as (glyph, attribute) pairs,
+        /// row by row.
+        pub fn screen_cells() -> Vec<(u8, u8)> {
+            if let Some(ref mut ctx) = *VIDEO_CONTEXT.lock().unwrap() {
+                ctx.screen_cells()
+            } else {
+                Vec::new()
+            }
+        }
+
+        /// Get the most recently drawn frame, as 800x600 pixels of RGB24
+        /// (i.e. three bytes per pixel). This works in headless mode too.
+        pub fn screen_pixels() -> Vec<u8> {
+            if let Some(ref mut ctx) = *VIDEO_CONTEXT.lock().unwrap() {
+                ctx.fb.borrow_hw_mut().unwrap().pixels.clone()
+            } else {
+                Vec::new()
+            }
+        }
+
+        /// Save the most recently drawn frame as an 800x600 image. It's a PPM
+        /// file if `path` ends in `.ppm`, otherwise it's a PNG file. You can
+        /// also press F12 to save `monotron-<n>.png`.
+        pub fn screenshot<P>(path: P) -> std::io::Result<()>
+        where
+            P: AsRef<std::path::Path>,
+        {
+            if let Some(ref mut ctx) = *VIDEO_CONTEXT.lock().unwrap() {
+                ctx.screenshot(path.as_ref())
+            } else {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "Failed video lock",
+                ))
+            }
+        }
+
+        /// Start recording video, one frame per call to `wfvbi`. It's a
+        /// YUV4MPEG2 file if `path` ends in `.y4m`, otherwise it's an
+        /// animated GIF. You can also press F10, or pass
+        /// `--monotron-record=<path>` on the command line.
+        pub fn start_recording<P>(path: P)
+        where
+            P: AsRef<std::path::Path>,
+        {
+            if let Some(ref mut ctx) = *VIDEO_CONTEXT.lock().unwrap() {
+                ctx.start_recording(path.as_ref());
+            }
+        }
+
+        /// Stop recording video, and finish off the file.
+        pub fn stop_recording() {
+            if let Some(ref mut ctx) = *VIDEO_CONTEXT.lock().unwrap() {
+                ctx.stop_recording();
             }
         }
 
         /// Open/create a device/file. Returns a file handle, or an error.
-        pub fn open(_filename: &str, _mode: OpenMode) -> HandleResult {
-            unimplemented!();
+        ///
+        /// Files live in the directory given by `MONOTRON_SDCARD` (default
+        /// `./sdcard`), which stands in for the SD card.
+        pub fn open(filename: &str, mode: OpenMode) -> HandleResult {
+            filesystem::open(filename, mode)
         }
 
         /// Close a previously opened handle.
-        pub fn close(_handle: Handle) -> EmptyResult {
-            unimplemented!();
+        pub fn close(handle: Handle) -> EmptyResult {
+            filesystem::close(handle)
         }
 
         /// Read from a file handle into the given buffer. Returns an error, or
         /// the number of bytes read (which may be less than `buffer_len`).
-        pub fn read(_handle: Handle, _buffer: &mut [u8]) -> SizeResult {
-            unimplemented!();
+        pub fn read(handle: Handle, buffer: &mut [u8]) -> SizeResult {
+            filesystem::read(handle, buffer)
         }
 
         /// Write the contents of the given buffer to a file handle. Returns an
         /// error, or the number of bytes written (which may be less than
         /// `buffer_len`).
-        pub fn write(_handle: Handle, _buffer: &[u8]) -> SizeResult {
-            unimplemented!();
+        pub fn write(handle: Handle, buffer: &[u8]) -> SizeResult {
+            filesystem::write(handle, buffer)
         }
     }
 }
@@ -923,6 +1545,63 @@ pub extern "C" fn getchar() -> u8 {
     Host::readc()
 }
 
+#[no_mangle]
+/// C FFI for Host::read_key_event. Returns true, and fills in `*p_event`, if
+/// there was an event.
+///
+/// # Safety
+///
+/// `p_event` must point to somewhere we can write a `KeyEvent`.
+pub unsafe extern "C" fn read_key_event(p_event: *mut KeyEvent) -> bool {
+    match Host::read_key_event() {
+        Some(event) if !p_event.is_null() => {
+            *p_event = event;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// The lines remembered by `read_line`.
+static mut READ_LINE_HISTORY: line_editor::History = line_editor::History::new();
+
+#[no_mangle]
+/// C FFI for line_editor::LineEditor::edit. Edits the null-terminated string
+/// in `buffer` at the cursor, letting it grow to `max_len` bytes (plus the
+/// null). If `use_history` is true, Up and Down step through the lines
+/// entered previously. Returns the new length, or -1 if Escape was pressed.
+/// The string is edited in place, so after Escape it holds whatever had been
+/// typed so far (still null-terminated), not the original string.
+///
+/// # Safety
+///
+/// `buffer` must point to `max_len + 1` bytes, holding a null-terminated
+/// string. Only call this from one place at a time.
+pub unsafe extern "C" fn read_line(buffer: *mut u8, max_len: usize, use_history: bool) -> i32 {
+    if buffer.is_null() {
+        return -1;
+    }
+    let buffer = core::slice::from_raw_parts_mut(buffer, max_len + 1);
+    let len = buffer[..max_len]
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(max_len);
+    let (status, len) = {
+        let mut editor = line_editor::LineEditor::new(&mut buffer[..max_len], len);
+        if use_history {
+            editor.set_history(&mut *core::ptr::addr_of_mut!(READ_LINE_HISTORY));
+        }
+        let status = editor.edit();
+        (status, editor.text().len())
+    };
+    buffer[len] = 0;
+    if status == line_editor::Status::Done {
+        len as i32
+    } else {
+        -1
+    }
+}
+
 #[no_mangle]
 /// C FFI for Host::wfvbi
 pub extern "C" fn wfvbi() {
@@ -935,27 +1614,352 @@ pub extern "C" fn move_cursor(row: u8, col: u8) {
     Host::move_cursor(Row(row), Col(col))
 }
 
-#[no_mangle]
-/// C FFI for Host::play
-pub extern "C" fn play(frequency: u32, channel: i32, waveform: i32, volume: u8) {
-    let channel = match channel {
+/// Convert a `channel_t` from C.
+fn channel_from_c(channel: i32) -> Option<Channel> {
+    match channel {
         0 => Some(Channel::Channel0),
         1 => Some(Channel::Channel1),
         2 => Some(Channel::Channel2),
         _ => None,
-    };
-    let waveform = match waveform {
+    }
+}
+
+/// Convert a `waveform_t` from C.
+fn waveform_from_c(waveform: i32) -> Option<Waveform> {
+    match waveform {
         0 => Some(Waveform::Square),
         1 => Some(Waveform::Sine),
         2 => Some(Waveform::Sawtooth),
         3 => Some(Waveform::Noise),
         _ => None,
-    };
-    if let (Some(ch), Some(wv)) = (channel, waveform) {
+    }
+}
+
+#[no_mangle]
+/// C FFI for Host::play
+pub extern "C" fn play(frequency: u32, channel: i32, waveform: i32, volume: u8) {
+    if let (Some(ch), Some(wv)) = (channel_from_c(channel), waveform_from_c(waveform)) {
         Host::play(Frequency(frequency), ch, wv, volume)
     }
 }
 
+/// The tune played by the `music_xxx` functions.
+static mut MUSIC: music::Sequencer<'static> = music::Sequencer::new(120);
+
+/// Get at the tune played by the `music_xxx` functions. C apps only have
+/// one thread, so nobody else can be using it.
+fn c_music() -> &'static mut music::Sequencer<'static> {
+    unsafe { &mut *core::ptr::addr_of_mut!(MUSIC) }
+}
+
+#[no_mangle]
+/// C FFI for music::Sequencer::set_track
+///
+/// # Safety
+///
+/// `p_events` must point to `num_events` events, which must stay valid until
+/// the track is replaced or cleared.
+pub unsafe extern "C" fn music_set_track(
+    channel: i32,
+    p_events: *const music::Event,
+    num_events: usize,
+    waveform: i32,
+    volume: u8,
+    looping: bool,
+) {
+    if p_events.is_null() {
+        return;
+    }
+    if let (Some(ch), Some(wv)) = (channel_from_c(channel), waveform_from_c(waveform)) {
+        let events = core::slice::from_raw_parts(p_events, num_events);
+        let mut track = music::Track::new(ch, events);
+        track.set_waveform(wv);
+        track.set_volume(volume);
+        track.set_looping(looping);
+        c_music().set_track(track);
+    }
+}
+
+#[no_mangle]
+/// C FFI for music::Sequencer::clear_track
+pub extern "C" fn music_clear_track(channel: i32) {
+    if let Some(ch) = channel_from_c(channel) {
+        c_music().clear_track(ch);
+    }
+}
+
+#[no_mangle]
+/// C FFI for music::Sequencer::set_tempo
+pub extern "C" fn music_set_tempo(bpm: u16) {
+    c_music().set_tempo(bpm);
+}
+
+#[no_mangle]
+/// C FFI for music::Sequencer::play
+pub extern "C" fn music_play() {
+    c_music().play();
+}
+
+#[no_mangle]
+/// C FFI for music::Sequencer::pause
+pub extern "C" fn music_pause() {
+    c_music().pause();
+}
+
+#[no_mangle]
+/// C FFI for music::Sequencer::stop
+pub extern "C" fn music_stop() {
+    c_music().stop();
+}
+
+#[no_mangle]
+/// C FFI for music::Sequencer::is_playing
+pub extern "C" fn music_is_playing() -> bool {
+    c_music().is_playing()
+}
+
+#[no_mangle]
+/// C FFI for music::Sequencer::is_finished
+pub extern "C" fn music_is_finished() -> bool {
+    c_music().is_finished()
+}
+
+#[no_mangle]
+/// C FFI for music::Sequencer::tick
+pub extern "C" fn music_tick() {
+    c_music().tick();
+}
+
+/// How many instruments the `instrument_xxx` functions can set up.
+const NUM_C_INSTRUMENTS: usize = 8;
+
+/// The instruments set up by the `instrument_xxx` functions.
+static mut INSTRUMENTS: [instrument::Instrument; NUM_C_INSTRUMENTS] =
+    [instrument::Instrument::new(Waveform::Square); NUM_C_INSTRUMENTS];
+
+/// The voices played by the `sound_xxx` functions.
+static mut SOUNDS: [instrument::Voice; 3] = [
+    instrument::Voice::new(
+        Channel::Channel0,
+        instrument::Instrument::new(Waveform::Square),
+    ),
+    instrument::Voice::new(
+        Channel::Channel1,
+        instrument::Instrument::new(Waveform::Square),
+    ),
+    instrument::Voice::new(
+        Channel::Channel2,
+        instrument::Instrument::new(Waveform::Square),
+    ),
+];
+
+/// Get at one of the instruments set up by the `instrument_xxx` functions.
+/// C apps only have one thread, so nobody else can be using it.
+fn c_instrument(instrument: i32) -> Option<&'static mut instrument::Instrument> {
+    if instrument < 0 {
+        return None;
+    }
+    unsafe { (*core::ptr::addr_of_mut!(INSTRUMENTS)).get_mut(instrument as usize) }
+}
+
+/// Get at the voice the `sound_xxx` functions play on a channel.
+fn c_sound(channel: Channel) -> &'static mut instrument::Voice {
+    unsafe { &mut (*core::ptr::addr_of_mut!(SOUNDS))[channel as usize] }
+}
+
+#[no_mangle]
+/// C FFI to make an instrument a plain square wave again.
+pub extern "C" fn instrument_reset(instrument: i32) {
+    if let Some(inst) = c_instrument(instrument) {
+        *inst = instrument::Instrument::new(Waveform::Square);
+    }
+}
+
+#[no_mangle]
+/// C FFI to set an instrument's waveform.
+pub extern "C" fn instrument_set_waveform(instrument: i32, waveform: i32) {
+    if let (Some(inst), Some(wv)) = (c_instrument(instrument), waveform_from_c(waveform)) {
+        inst.waveform = wv;
+    }
+}
+
+#[no_mangle]
+/// C FFI to set an instrument's instrument::Envelope.
+pub extern "C" fn instrument_set_envelope(
+    instrument: i32,
+    attack: u8,
+    decay: u8,
+    sustain: u8,
+    release: u8,
+) {
+    if let Some(inst) = c_instrument(instrument) {
+        inst.envelope = instrument::Envelope::new(attack, decay, sustain, release);
+    }
+}
+
+#[no_mangle]
+/// C FFI to set an instrument's instrument::Vibrato. A depth of 0 turns it
+/// off.
+pub extern "C" fn instrument_set_vibrato(instrument: i32, depth: u8, speed: u8, delay: u8) {
+    if let Some(inst) = c_instrument(instrument) {
+        inst.vibrato = if depth == 0 {
+            None
+        } else {
+            Some(instrument::Vibrato::new(depth, speed, delay))
+        };
+    }
+}
+
+#[no_mangle]
+/// C FFI to set an instrument's instrument::Arpeggio. No offsets turns it
+/// off.
+///
+/// # Safety
+///
+/// `p_offsets` must point to `num_offsets` offsets, or be null.
+pub unsafe extern "C" fn instrument_set_arpeggio(
+    instrument: i32,
+    p_offsets: *const i8,
+    num_offsets: usize,
+    speed: u8,
+) {
+    if let Some(inst) = c_instrument(instrument) {
+        inst.arpeggio = if p_offsets.is_null() || num_offsets == 0 {
+            None
+        } else {
+            let offsets = core::slice::from_raw_parts(p_offsets, num_offsets);
+            Some(instrument::Arpeggio::new(offsets, speed))
+        };
+    }
+}
+
+#[no_mangle]
+/// C FFI to set an instrument's pitch slide.
+pub extern "C" fn instrument_set_slide(instrument: i32, per_frame: i16) {
+    if let Some(inst) = c_instrument(instrument) {
+        inst.slide = per_frame;
+    }
+}
+
+#[no_mangle]
+/// C FFI for music::Track::set_instrument, on the track playing on a
+/// channel.
+pub extern "C" fn music_set_instrument(channel: i32, instrument: i32) {
+    if let (Some(ch), Some(inst)) = (channel_from_c(channel), c_instrument(instrument)) {
+        if let Some(track) = c_music().track_mut(ch) {
+            track.set_instrument(*inst);
+        }
+    }
+}
+
+#[no_mangle]
+/// C FFI for instrument::Voice::note_on
+pub extern "C" fn sound_note_on(channel: i32, instrument: i32, frequency: u32, volume: u8) {
+    if let (Some(ch), Some(inst)) = (channel_from_c(channel), c_instrument(instrument)) {
+        let voice = c_sound(ch);
+        voice.set_instrument(*inst);
+        voice.note_on(Frequency(frequency), volume);
+    }
+}
+
+#[no_mangle]
+/// C FFI for instrument::Voice::note_off
+pub extern "C" fn sound_note_off(channel: i32) {
+    if let Some(ch) = channel_from_c(channel) {
+        c_sound(ch).note_off();
+    }
+}
+
+#[no_mangle]
+/// C FFI for instrument::Voice::tick, on every channel
+pub extern "C" fn sound_tick() {
+    for channel in &[Channel::Channel0, Channel::Channel1, Channel::Channel2] {
+        c_sound(*channel).tick();
+    }
+}
+
+/// The file played by the `midi_xxx` functions.
+static mut MIDI: Option<midi::Player> = None;
+
+/// Get at the file played by the `midi_xxx` functions. C apps only have one
+/// thread, so nobody else can be using it.
+fn c_midi() -> &'static mut Option<midi::Player> {
+    unsafe { &mut *core::ptr::addr_of_mut!(MIDI) }
+}
+
+#[no_mangle]
+/// C FFI for midi::Player::open. Any file already open is closed first.
+/// Returns 0 on success, or -1 if the file can't be played.
+///
+/// # Safety
+///
+/// `filename` must point to a null-terminated UTF-8 string.
+pub unsafe extern "C" fn midi_open(filename: *const u8) -> i32 {
+    *c_midi() = None;
+    if filename.is_null() {
+        return -1;
+    }
+    let mut len = 0usize;
+    while *filename.add(len) != 0 {
+        len += 1;
+    }
+    let filename = match core::str::from_utf8(core::slice::from_raw_parts(filename, len)) {
+        Ok(filename) => filename,
+        Err(_) => return -1,
+    };
+    match midi::Player::open(filename) {
+        Ok(player) => {
+            *c_midi() = Some(player);
+            0
+        }
+        Err(_) => -1,
+    }
+}
+
+#[no_mangle]
+/// C FFI for dropping a midi::Player
+pub extern "C" fn midi_close() {
+    *c_midi() = None;
+}
+
+#[no_mangle]
+/// C FFI for midi::Player::play
+pub extern "C" fn midi_play() {
+    if let Some(player) = c_midi() {
+        player.play();
+    }
+}
+
+#[no_mangle]
+/// C FFI for midi::Player::pause
+pub extern "C" fn midi_pause() {
+    if let Some(player) = c_midi() {
+        player.pause();
+    }
+}
+
+#[no_mangle]
+/// C FFI for midi::Player::set_volume
+pub extern "C" fn midi_set_volume(volume: u8) {
+    if let Some(player) = c_midi() {
+        player.set_volume(volume);
+    }
+}
+
+#[no_mangle]
+/// C FFI for midi::Player::is_finished. True if no file is open.
+pub extern "C" fn midi_is_finished() -> bool {
+    c_midi().as_ref().map_or(true, midi::Player::is_finished)
+}
+
+#[no_mangle]
+/// C FFI for midi::Player::tick
+pub extern "C" fn midi_tick() {
+    if let Some(player) = c_midi() {
+        player.tick();
+    }
+}
+
 #[no_mangle]
 /// C FFI for Host::set_font
 pub extern "C" fn font_normal() {
@@ -968,12 +1972,42 @@ pub extern "C" fn font_teletext() {
     let _ = Host::set_font(Font::Teletext);
 }
 
+#[no_mangle]
+/// C FFI for Host::set_font
+///
+/// # Safety
+///
+/// `p_font` must point to 4096 bytes of font data which live for as long as
+/// the font is in use.
+pub unsafe extern "C" fn font_custom(p_font: *const u8) {
+    if !p_font.is_null() {
+        let _ = Host::set_font(Font::Custom(core::slice::from_raw_parts(p_font, 4096)));
+    }
+}
+
 #[no_mangle]
 /// C FFI for Host::get_joystick
 pub extern "C" fn get_joystick() -> JoystickState {
     Host::get_joystick()
 }
 
+#[no_mangle]
+/// C FFI for Host::get_mouse. Returns true, and fills in `*p_state`, if
+/// there is a mouse.
+///
+/// # Safety
+///
+/// `p_state` must point to somewhere we can write a `MouseState`.
+pub unsafe extern "C" fn get_mouse(p_state: *mut MouseState) -> bool {
+    match Host::get_mouse() {
+        Some(state) if !p_state.is_null() => {
+            *p_state = state;
+            true
+        }
+        _ => false,
+    }
+}
+
 /// True if joystick is pointing up.
 #[no_mangle]
 pub extern "C" fn joystick_is_up(state: u8) -> bool {
@@ -1009,10 +2043,8 @@ pub extern "C" fn joystick_fire_pressed(state: u8) -> bool {
 /// `sixel` is the numeric value of the sixel to write (0..63)
 #[no_mangle]
 pub extern "C" fn put_connected_sixel(sixel: u8) {
-    if sixel < 0x20 {
-        putchar(sixel + 0x80)
-    } else if sixel < 0x40 {
-        putchar(sixel + 0xC0 - 0x20)
+    if sixel < 0x40 {
+        putchar(codepage::connected_sixel(sixel))
     }
 }
 
@@ -1021,10 +2053,8 @@ pub extern "C" fn put_connected_sixel(sixel: u8) {
 /// `sixel` is the numeric value of the sixel to write (0..63)
 #[no_mangle]
 pub extern "C" fn put_separated_sixel(sixel: u8) {
-    if sixel < 0x20 {
-        putchar(sixel + 0xA0)
-    } else if sixel < 0x40 {
-        putchar(sixel + 0xE0 - 0x20)
+    if sixel < 0x40 {
+        putchar(codepage::separated_sixel(sixel))
     }
 }
 
@@ -1044,7 +2074,7 @@ pub extern "C" fn gettime() -> monotron_api::Timestamp {
 /// C FFI for Host::read_char_at
 pub extern "C" fn read_char_at(row: u8, col: u8) -> u16 {
     let (glyph, attr) = Host::read_char_at(Row(row), Col(col));
-    ((glyph as u16) << 8) + (attr as u16)
+    ((glyph as u16) << 8) + (attr.as_u8() as u16)
 }
 
 #[no_mangle]
@@ -0,0 +1,588 @@
+//! Reads a line of text from the keyboard, with a cursor you can move about.
+//!
+//! The line is edited where the screen's cursor was when the `LineEditor`
+//! was made (or wherever `LineEditor::at` says), and scrolls sideways if it
+//! gets too long for the screen. The text is 8-bit glyphs, like everything
+//! else sent to `Host::putchar`.
+//!
+//! The keys are:
+//!
+//! * Left/Right (or Ctrl+B/Ctrl+F) - move the cursor
+//! * Home/End (or Ctrl+A/Ctrl+E) - move to the start/end of the line
+//! * Backspace/Delete - remove the character before/under the cursor
+//! * Ctrl+K - remove everything from the cursor onwards
+//! * Ctrl+U - remove the whole line
+//! * Up/Down - step through the history, if there is one
+//! * Return - finish
+//! * Escape - give up
+
+use super::codepage;
+use super::{Col, Host, KeyCode, KeyDecoder, Row};
+
+/// How many lines a `History` remembers.
+pub const HISTORY_LINES: usize = 8;
+
+/// How much of each line a `History` remembers.
+pub const HISTORY_LINE_LEN: usize = 80;
+
+/// The most recent lines entered, for Up and Down to step through.
+pub struct History {
+    lines: [[u8; HISTORY_LINE_LEN]; HISTORY_LINES],
+    lens: [usize; HISTORY_LINES],
+    /// How many lines we have.
+    count: usize,
+    /// Where the next line goes.
+    next: usize,
+}
+
+impl Default for History {
+    fn default() -> History {
+        History::new()
+    }
+}
+
+impl History {
+    /// Make an empty history.
+    pub const fn new() -> History {
+        History {
+            lines: [[0; HISTORY_LINE_LEN]; HISTORY_LINES],
+            lens: [0; HISTORY_LINES],
+            count: 0,
+            next: 0,
+        }
+    }
+
+    /// Remember a line, forgetting the oldest one if we're full. Empty lines,
+    /// and repeats of the last line, aren't remembered.
+    pub fn push(&mut self, line: &[u8]) {
+        if line.is_empty() || self.get(0) == Some(line) {
+            return;
+        }
+        let len = line.len().min(HISTORY_LINE_LEN);
+        self.lines[self.next][..len].copy_from_slice(&line[..len]);
+        self.lens[self.next] = len;
+        self.next = (self.next + 1) % HISTORY_LINES;
+        self.count = (self.count + 1).min(HISTORY_LINES);
+    }
+
+    /// Get a line, where 0 is the most recent.
+    pub fn get(&self, age: usize) -> Option<&[u8]> {
+        if age >= self.count {
+            return None;
+        }
+        let idx = (self.next + HISTORY_LINES - 1 - age) % HISTORY_LINES;
+        Some(&self.lines[idx][..self.lens[idx]])
+    }
+
+    /// How many lines we have.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// True if we have no lines.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+/// What happened to the line after a key was pressed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Status {
+    /// We want more keys
+    Editing,
+    /// Return was pressed
+    Done,
+    /// Escape was pressed
+    Cancelled,
+}
+
+/// Edits a line of text in a buffer you supply.
+pub struct LineEditor<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+    max_len: usize,
+    cursor: usize,
+    history: Option<&'a mut History>,
+    /// Which history line we're showing, if any.
+    history_pos: Option<usize>,
+    /// Where the line starts on screen.
+    row: Row,
+    col: Col,
+    /// The most cells we may use on screen.
+    max_width: usize,
+    /// How many bytes of the line are off the left of the screen.
+    scroll: usize,
+    decoder: KeyDecoder,
+}
+
+impl<'a> LineEditor<'a> {
+    /// Edit the text in `buffer`, the first `len` bytes of which are the
+    /// starting text. The line can grow to fill the buffer. It is drawn
+    /// from where the screen's cursor is now.
+    pub fn new(buffer: &'a mut [u8], len: usize) -> LineEditor<'a> {
+        let (row, col) = Host::get_cursor();
+        LineEditor::at(row, col, buffer, len)
+    }
+
+    /// Like `new`, but the line is drawn from (`row`, `col`) instead.
+    pub fn at(row: Row, col: Col, buffer: &'a mut [u8], len: usize) -> LineEditor<'a> {
+        let len = len.min(buffer.len());
+        LineEditor {
+            max_len: buffer.len(),
+            buffer,
+            len,
+            cursor: len,
+            history: None,
+            history_pos: None,
+            row,
+            col,
+            max_width: usize::MAX,
+            scroll: 0,
+            decoder: KeyDecoder::new(),
+        }
+    }
+
+    /// Stop the line growing past `max_len` bytes (or the size of the
+    /// buffer, if that's smaller).
+    pub fn set_max_len(&mut self, max_len: usize) {
+        self.max_len = max_len.min(self.buffer.len());
+        if self.len > self.max_len {
+            self.len = self.max_len;
+            self.cursor = self.cursor.min(self.len);
+        }
+    }
+
+    /// Use Up and Down to step through `history`, and add the line to it
+    /// when Return is pressed.
+    pub fn set_history(&mut self, history: &'a mut History) {
+        self.history = Some(history);
+    }
+
+    /// Use no more than `width` cells on screen. By default the line uses
+    /// the rest of the row.
+    pub fn set_width(&mut self, width: usize) {
+        self.max_width = width;
+    }
+
+    /// The text so far.
+    pub fn text(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+
+    /// Draw the line, and put the screen's cursor where our cursor is.
+    pub fn draw(&mut self) {
+        let width = self.width();
+        if self.cursor < self.scroll {
+            self.scroll = self.cursor;
+        } else if self.cursor >= self.scroll + width {
+            self.scroll = self.cursor + 1 - width;
+        }
+        Host::move_cursor(self.row, self.col);
+        for idx in self.scroll..self.scroll + width {
+            Host::putchar(if idx < self.len {
+                self.buffer[idx]
+            } else {
+                b' '
+            });
+        }
+        let cursor_col = self.col.0 as usize + self.cursor - self.scroll;
+        Host::move_cursor(self.row, Col(cursor_col as u8));
+    }
+
+    /// Deal with a byte from `Host::readc`, and say what happened. Call
+    /// `draw` to show the result.
+    pub fn handle_key(&mut self, key: u8) -> Status {
+        match self.decoder.feed(key) {
+            Some(code) => self.handle_keycode(code),
+            None => Status::Editing,
+        }
+    }
+
+    /// Deal with a key which has already been decoded (e.g. by a
+    /// `KeyDecoder`), and say what happened.
+    pub fn handle_keycode(&mut self, code: KeyCode) -> Status {
+        match code {
+            KeyCode::UP => self.history_older(),
+            KeyCode::DOWN => self.history_newer(),
+            // Ctrl+B
+            KeyCode::LEFT | KeyCode(0x02) => self.cursor = self.cursor.saturating_sub(1),
+            // Ctrl+F
+            KeyCode::RIGHT | KeyCode(0x06) => self.cursor = (self.cursor + 1).min(self.len),
+            // Ctrl+A
+            KeyCode::HOME | KeyCode(0x01) => self.cursor = 0,
+            // Ctrl+E
+            KeyCode::END | KeyCode(0x05) => self.cursor = self.len,
+            KeyCode(0x1B) => return Status::Cancelled,
+            KeyCode(0x0D) | KeyCode(0x0A) => {
+                self.cursor = self.len;
+                if let Some(ref mut history) = self.history {
+                    history.push(&self.buffer[..self.len]);
+                }
+                return Status::Done;
+            }
+            // Backspace
+            KeyCode(0x08) if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.delete(self.cursor);
+            }
+            // Delete
+            KeyCode(0x7F) => self.delete(self.cursor),
+            // Ctrl+K
+            KeyCode(0x0B) => self.len = self.cursor,
+            // Ctrl+U
+            KeyCode(0x15) => {
+                self.len = 0;
+                self.cursor = 0;
+            }
+            KeyCode(glyph @ 0x20..=0xFF) => self.insert(glyph as u8),
+            _ => {}
+        }
+        Status::Editing
+    }
+
+    /// Call when no more keys have arrived after `handle_key`. This deals
+    /// with any key the `KeyDecoder` held back. If the last key was an
+    /// Escape on its own, that means we're cancelled.
+    pub fn idle(&mut self) -> Status {
+        match self.decoder.idle() {
+            Some(code) => self.handle_keycode(code),
+            None => Status::Editing,
+        }
+    }
+
+    /// Edit the line until Return or Escape is pressed, calling `wfvbi`
+    /// while we wait for keys. Turns the cursor on.
+    ///
+    /// The keys are read with `ui::read_key`, so a key typed straight after
+    /// the Escape which cancels us isn't lost.
+    pub fn edit(&mut self) -> Status {
+        Host::set_cursor_visible(true);
+        loop {
+            self.draw();
+            match self.handle_keycode(super::ui::read_key()) {
+                Status::Editing => {}
+                status => {
+                    self.draw();
+                    return status;
+                }
+            }
+        }
+    }
+
+    /// How many cells we have on screen.
+    fn width(&self) -> usize {
+        let mut width = 48 - (self.col.0 as usize).min(47);
+        // Writing to the bottom right cell makes the screen scroll
+        if self.row.0 >= 35 && width > 1 {
+            width -= 1;
+        }
+        width.min(self.max_width).max(1)
+    }
+
+    fn insert(&mut self, glyph: u8) {
+        if self.len < self.max_len {
+            self.buffer
+                .copy_within(self.cursor..self.len, self.cursor + 1);
+            self.buffer[self.cursor] = glyph;
+            self.len += 1;
+            self.cursor += 1;
+        }
+    }
+
+    fn delete(&mut self, idx: usize) {
+        if idx < self.len {
+            self.buffer.copy_within(idx + 1..self.len, idx);
+            self.len -= 1;
+        }
+    }
+
+    /// Show the previous line from the history.
+    fn history_older(&mut self) {
+        let age = self.history_pos.map(|pos| pos + 1).unwrap_or(0);
+        self.show_history(age);
+    }
+
+    /// Show the next line from the history, or an empty line if there isn't
+    /// one.
+    fn history_newer(&mut self) {
+        match self.history_pos {
+            Some(0) => {
+                self.history_pos = None;
+                self.len = 0;
+                self.cursor = 0;
+            }
+            Some(pos) => self.show_history(pos - 1),
+            None => {}
+        }
+    }
+
+    fn show_history(&mut self, age: usize) {
+        let line = match self.history.as_ref().and_then(|history| history.get(age)) {
+            Some(line) => line,
+            None => return,
+        };
+        let len = line.len().min(self.max_len);
+        self.buffer[..len].copy_from_slice(&line[..len]);
+        self.len = len;
+        self.cursor = len;
+        self.history_pos = Some(age);
+    }
+}
+
+/// Edit a string with a `LineEditor`, at the screen's cursor. Characters
+/// which aren't in Code Page 850 come back as `codepage::FALLBACK`. The
+/// string is only changed if Return is pressed; if it fills up, the end of
+/// the line is lost.
+pub fn edit_string<N>(string: &mut heapless::String<N>, history: Option<&mut History>) -> Status
+where
+    N: heapless::ArrayLength<u8>,
+{
+    let mut buffer = [0u8; 256];
+    let mut len = 0;
+    for (slot, ch) in buffer.iter_mut().zip(string.chars()) {
+        *slot = codepage::Codepage::Cp850.encode(ch);
+        len += 1;
+    }
+    let mut editor = LineEditor::new(&mut buffer, len);
+    editor.set_max_len(string.capacity());
+    if let Some(history) = history {
+        editor.set_history(history);
+    }
+    let status = editor.edit();
+    if status == Status::Done {
+        let text = editor.text();
+        string.clear();
+        for &glyph in text {
+            if string.push(codepage::from_cp850(glyph)).is_err() {
+                break;
+            }
+        }
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Type `keys` into an editor which starts with `text`, and say what
+    /// the line ends up as.
+    fn type_keys(text: &[u8], keys: &[KeyCode]) -> (Vec<u8>, usize, Status) {
+        let mut buffer = [0u8; 16];
+        buffer[..text.len()].copy_from_slice(text);
+        let mut editor = LineEditor::at(Row(0), Col(0), &mut buffer, text.len());
+        let mut status = Status::Editing;
+        for &key in keys {
+            status = editor.handle_keycode(key);
+        }
+        (editor.text().to_vec(), editor.cursor, status)
+    }
+
+    fn chars(text: &[u8]) -> Vec<KeyCode> {
+        text.iter().map(|&b| KeyCode(u16::from(b))).collect()
+    }
+
+    #[test]
+    fn history() {
+        let mut history = History::new();
+        assert!(history.is_empty());
+        assert_eq!(history.get(0), None);
+        history.push(b"one");
+        history.push(b"");
+        history.push(b"two");
+        history.push(b"two");
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.get(0), Some(&b"two"[..]));
+        assert_eq!(history.get(1), Some(&b"one"[..]));
+        assert_eq!(history.get(2), None);
+        // Only the last line counts as a repeat
+        history.push(b"one");
+        assert_eq!(history.len(), 3);
+
+        let mut history = History::new();
+        for idx in 0..HISTORY_LINES as u8 + 3 {
+            history.push(&[b'a' + idx]);
+        }
+        assert_eq!(history.len(), HISTORY_LINES);
+        assert_eq!(history.get(0), Some(&[b'a' + HISTORY_LINES as u8 + 2][..]));
+        assert_eq!(history.get(HISTORY_LINES - 1), Some(&b"d"[..]));
+        assert_eq!(history.get(HISTORY_LINES), None);
+
+        let long = [b'x'; HISTORY_LINE_LEN + 10];
+        history.push(&long);
+        assert_eq!(history.get(0), Some(&long[..HISTORY_LINE_LEN]));
+    }
+
+    #[test]
+    fn inserting() {
+        assert_eq!(
+            type_keys(b"", &chars(b"hello")),
+            (b"hello".to_vec(), 5, Status::Editing)
+        );
+        let mut keys = vec![KeyCode::LEFT, KeyCode::LEFT];
+        keys.extend(chars(b"XY"));
+        assert_eq!(
+            type_keys(b"abcd", &keys),
+            (b"abXYcd".to_vec(), 4, Status::Editing)
+        );
+        keys = vec![
+            KeyCode::HOME,
+            KeyCode(b'<' as u16),
+            KeyCode::END,
+            KeyCode(b'>' as u16),
+        ];
+        assert_eq!(
+            type_keys(b"ab", &keys),
+            (b"<ab>".to_vec(), 4, Status::Editing)
+        );
+        // Ctrl+A, Ctrl+F and Ctrl+B
+        keys = vec![KeyCode(0x01), KeyCode(0x06), KeyCode(0x06), KeyCode(0x02)];
+        keys.extend(chars(b"-"));
+        assert_eq!(
+            type_keys(b"ab", &keys),
+            (b"a-b".to_vec(), 2, Status::Editing)
+        );
+        // The line can't outgrow the buffer, and control characters are
+        // ignored
+        let (text, cursor, _) = type_keys(b"", &chars(b"0123456789\x07abcdefghij"));
+        assert_eq!((&text[..], cursor), (&b"0123456789abcdef"[..], 16));
+    }
+
+    #[test]
+    fn deleting() {
+        let keys = [KeyCode::LEFT, KeyCode(0x08)];
+        assert_eq!(
+            type_keys(b"abc", &keys),
+            (b"ac".to_vec(), 1, Status::Editing)
+        );
+        let keys = [KeyCode::HOME, KeyCode(0x08), KeyCode(0x7F)];
+        assert_eq!(
+            type_keys(b"abc", &keys),
+            (b"bc".to_vec(), 0, Status::Editing)
+        );
+        let keys = [KeyCode(0x7F)];
+        assert_eq!(
+            type_keys(b"abc", &keys),
+            (b"abc".to_vec(), 3, Status::Editing)
+        );
+        // Ctrl+K
+        let keys = [KeyCode::LEFT, KeyCode::LEFT, KeyCode(0x0B)];
+        assert_eq!(
+            type_keys(b"abcd", &keys),
+            (b"ab".to_vec(), 2, Status::Editing)
+        );
+        // Ctrl+U
+        let keys = [KeyCode::LEFT, KeyCode(0x15)];
+        assert_eq!(
+            type_keys(b"abcd", &keys),
+            (b"".to_vec(), 0, Status::Editing)
+        );
+    }
+
+    #[test]
+    fn finishing() {
+        let keys = [KeyCode::HOME, KeyCode(0x0D)];
+        assert_eq!(type_keys(b"abc", &keys), (b"abc".to_vec(), 3, Status::Done));
+        assert_eq!(type_keys(b"abc", &[KeyCode(0x0A)]).2, Status::Done);
+        assert_eq!(type_keys(b"abc", &[KeyCode(0x1B)]).2, Status::Cancelled);
+    }
+
+    #[test]
+    fn history_stepping() {
+        let mut history = History::new();
+        history.push(b"first");
+        history.push(b"second");
+        let mut buffer = [0u8; 16];
+        let mut editor = LineEditor::at(Row(0), Col(0), &mut buffer, 0);
+        editor.set_history(&mut history);
+        editor.handle_keycode(KeyCode(u16::from(b'x')));
+        // Down does nothing until we've gone up
+        editor.handle_keycode(KeyCode::DOWN);
+        assert_eq!(editor.text(), b"x");
+        editor.handle_keycode(KeyCode::UP);
+        assert_eq!(editor.text(), b"second");
+        editor.handle_keycode(KeyCode::UP);
+        assert_eq!(editor.text(), b"first");
+        // There's nothing older, so we stay put
+        editor.handle_keycode(KeyCode::UP);
+        assert_eq!(editor.text(), b"first");
+        editor.handle_keycode(KeyCode::DOWN);
+        assert_eq!(editor.text(), b"second");
+        editor.handle_keycode(KeyCode::DOWN);
+        assert_eq!(editor.text(), b"");
+        editor.handle_keycode(KeyCode::UP);
+        editor.handle_keycode(KeyCode(u16::from(b'!')));
+        assert_eq!(editor.handle_keycode(KeyCode(0x0D)), Status::Done);
+        assert_eq!(history.get(0), Some(&b"second!"[..]));
+        assert_eq!(history.len(), 3);
+    }
+
+    fn decode(bytes: &[u8]) -> Vec<KeyCode> {
+        let mut decoder = KeyDecoder::new();
+        let mut keys: Vec<KeyCode> = bytes.iter().filter_map(|&b| decoder.feed(b)).collect();
+        while let Some(key) = decoder.idle() {
+            keys.push(key);
+        }
+        keys
+    }
+
+    #[test]
+    fn decoding() {
+        assert_eq!(decode(b"a\r"), chars(b"a\r"));
+        assert_eq!(
+            decode(b"\x1B[A\x1B[B\x1B[C\x1B[D\x1B[H\x1B[F"),
+            [
+                KeyCode::UP,
+                KeyCode::DOWN,
+                KeyCode::RIGHT,
+                KeyCode::LEFT,
+                KeyCode::HOME,
+                KeyCode::END
+            ]
+        );
+        assert_eq!(
+            decode(b"\x1B[2~\x1B[3~\x1B[5~\x1B[6~\x1B[1~\x1B[4~"),
+            [
+                KeyCode::INSERT,
+                KeyCode(0x7F),
+                KeyCode::PAGE_UP,
+                KeyCode::PAGE_DOWN,
+                KeyCode::HOME,
+                KeyCode::END
+            ]
+        );
+        let f = |n: u16| KeyCode(KeyCode::F1.0 + n - 1);
+        assert_eq!(
+            decode(b"\x1BOP\x1BOQ\x1BOR\x1BOS\x1B[15~\x1B[17~\x1B[21~\x1B[23~\x1B[24~"),
+            [f(1), f(2), f(3), f(4), f(5), f(6), f(10), f(11), f(12)]
+        );
+        assert_eq!(decode(b"\x1BOH\x1BOF"), [KeyCode::HOME, KeyCode::END]);
+        // Sequences we don't know are dropped
+        assert_eq!(decode(b"\x1B[99~a\x1B[Za\x1BOxa"), chars(b"aaa"));
+    }
+
+    #[test]
+    fn lone_escapes() {
+        let mut decoder = KeyDecoder::new();
+        assert_eq!(decoder.feed(0x1B), None);
+        assert_eq!(decoder.idle(), Some(KeyCode(0x1B)));
+        assert_eq!(decoder.idle(), None);
+
+        // The key after an Escape comes out on the next call
+        assert_eq!(decoder.feed(0x1B), None);
+        assert_eq!(decoder.feed(b'x'), Some(KeyCode(0x1B)));
+        assert_eq!(decoder.feed(b'y'), Some(KeyCode(u16::from(b'x'))));
+        assert_eq!(decoder.idle(), Some(KeyCode(u16::from(b'y'))));
+        assert_eq!(decoder.idle(), None);
+
+        assert_eq!(
+            decode(b"\x1B\x1B\x1B[A"),
+            [KeyCode(0x1B), KeyCode(0x1B), KeyCode::UP]
+        );
+        assert_eq!(decode(b"\x1Bx\x1B"), chars(b"\x1Bx\x1B"));
+        assert_eq!(
+            decode(b"\x1Bx\x1B[B"),
+            [KeyCode(0x1B), KeyCode(0x78), KeyCode::DOWN]
+        );
+    }
+}
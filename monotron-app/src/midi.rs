@@ -0,0 +1,856 @@
+//! Plays Standard MIDI Files (`.mid`) on the synthesiser.
+//!
+//! The file is streamed from the SD card with `Host::read` as it plays, so
+//! only a few bytes per track are held in memory. Format 0 and format 1
+//! files are supported. As there's no way to seek, each track in a format 1
+//! file is read through its own handle, so a file with N tracks keeps N
+//! handles open while it plays. It also means opening the file is slow for
+//! big format 1 files: each track's handle has to read through everything
+//! before that track to get to it, so opening an N track file reads about N
+//! times as many bytes as the file holds.
+//!
+//! The synthesiser only has three channels, so notes from all sixteen MIDI
+//! channels share them. A new note takes a free channel if there is one, or
+//! else the one which has been playing the longest. Percussion (MIDI channel
+//! 10) is played as short bursts of `Waveform::Noise`. Each MIDI channel's
+//! program (instrument) picks the waveform its notes are played with.
+//!
+//! ```rust,ignore
+//! let mut player = midi::Player::open("tune.mid")?;
+//! player.play();
+//! while !player.is_finished() {
+//!     Host::wfvbi();
+//!     player.tick();
+//! }
+//! ```
+
+use super::{Channel, Frequency, Host, Note, Waveform};
+use monotron_api::{ApiResult, Handle, OpenMode};
+
+/// The most tracks a file can have.
+pub const MAX_TRACKS: usize = 16;
+
+/// How many bytes of each track we hold in memory.
+const BUFFER_LEN: usize = 16;
+
+/// The MIDI channel (counting from 0) which plays percussion.
+const PERCUSSION: u8 = 9;
+
+/// A frame is 1/60 s, which is 50,000 / 3 microseconds.
+const MICROSECONDS_PER_FRAME_X3: u64 = 50_000;
+
+/// Microseconds per quarter note until the file says otherwise (i.e. 120
+/// quarter notes per minute).
+const DEFAULT_TEMPO: u32 = 500_000;
+
+const CHANNELS: [Channel; 3] = [Channel::Channel0, Channel::Channel1, Channel::Channel2];
+
+/// Why a file couldn't be played.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The file couldn't be opened or read
+    Io(monotron_api::Error),
+    /// The file isn't a Standard MIDI File
+    NotMidi,
+    /// The file is format 2, or uses SMPTE timing
+    Unsupported,
+    /// The file has more than `MAX_TRACKS` tracks
+    TooManyTracks,
+    /// The file ends in the middle of something
+    Truncated,
+}
+
+/// Reads a file a few bytes at a time. The file is closed when this is
+/// dropped.
+struct Reader {
+    handle: Handle,
+    buffer: [u8; BUFFER_LEN],
+    pos: usize,
+    len: usize,
+    /// How many more bytes we may read (i.e. what's left of the chunk).
+    remaining: u32,
+}
+
+impl Reader {
+    fn open(filename: &str) -> Result<Reader, Error> {
+        match Host::open(
+            filename,
+            OpenMode::ReadOnly {
+                non_blocking: false,
+            },
+        ) {
+            ApiResult::Ok(handle) => Ok(Reader {
+                handle,
+                buffer: [0; BUFFER_LEN],
+                pos: 0,
+                len: 0,
+                remaining: u32::MAX,
+            }),
+            ApiResult::Error(e) => Err(Error::Io(e)),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        if self.remaining == 0 {
+            return Err(Error::Truncated);
+        }
+        if self.pos == self.len {
+            let want = BUFFER_LEN.min(self.remaining as usize);
+            match Host::read(self.handle, &mut self.buffer[..want]) {
+                ApiResult::Ok(0) => return Err(Error::Truncated),
+                ApiResult::Ok(len) => {
+                    self.pos = 0;
+                    self.len = len;
+                }
+                ApiResult::Error(e) => return Err(Error::Io(e)),
+            }
+        }
+        let byte = self.buffer[self.pos];
+        self.pos += 1;
+        self.remaining -= 1;
+        Ok(byte)
+    }
+
+    /// A big-endian 16-bit number.
+    fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from(self.byte()?) << 8 | u16::from(self.byte()?))
+    }
+
+    /// A big-endian 32-bit number.
+    fn u32(&mut self) -> Result<u32, Error> {
+        let mut value = 0;
+        for _ in 0..4 {
+            value = value << 8 | u32::from(self.byte()?);
+        }
+        Ok(value)
+    }
+
+    /// A variable-length number: seven bits per byte, most significant
+    /// first, with the top bit set on every byte but the last.
+    fn vlq(&mut self) -> Result<u32, Error> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.byte()?;
+            value = value << 7 | u32::from(byte & 0x7F);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error::NotMidi)
+    }
+
+    fn skip(&mut self, count: u32) -> Result<(), Error> {
+        for _ in 0..count {
+            self.byte()?;
+        }
+        Ok(())
+    }
+
+    /// Read a chunk header, returning its type and length.
+    fn chunk(&mut self) -> Result<([u8; 4], u32), Error> {
+        let mut id = [0u8; 4];
+        for byte in id.iter_mut() {
+            *byte = self.byte()?;
+        }
+        Ok((id, self.u32()?))
+    }
+}
+
+impl Drop for Reader {
+    fn drop(&mut self) {
+        let _ = Host::close(self.handle);
+    }
+}
+
+/// The parts of a MIDI message we care about.
+#[derive(Debug, Copy, Clone)]
+enum Message {
+    NoteOn {
+        channel: u8,
+        key: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        channel: u8,
+        key: u8,
+    },
+    Controller {
+        channel: u8,
+        number: u8,
+        value: u8,
+    },
+    Program {
+        channel: u8,
+        program: u8,
+    },
+    /// Microseconds per quarter note
+    Tempo(u32),
+    EndOfTrack,
+    Other,
+}
+
+/// Reads the events from one track.
+struct Track {
+    reader: Reader,
+    running_status: u8,
+    /// When the next event is due, in MIDI ticks from the start.
+    due: u64,
+    ended: bool,
+}
+
+impl Track {
+    /// Open `filename` and find the first track at or after `offset` bytes
+    /// in. `offset` is moved on to the chunk after it.
+    fn open(filename: &str, offset: &mut u32) -> Result<Track, Error> {
+        let mut reader = Reader::open(filename)?;
+        reader.skip(*offset)?;
+        loop {
+            let (id, len) = reader.chunk()?;
+            *offset = offset.saturating_add(8).saturating_add(len);
+            if &id == b"MTrk" {
+                reader.remaining = len;
+                break;
+            }
+            // Chunks we don't know about are to be skipped
+            reader.skip(len)?;
+        }
+        let mut track = Track {
+            reader,
+            running_status: 0,
+            due: 0,
+            ended: false,
+        };
+        if track.reader.remaining == 0 {
+            track.ended = true;
+        } else {
+            track.due = u64::from(track.reader.vlq()?);
+        }
+        Ok(track)
+    }
+
+    /// Read the event which is due, and find out when the one after it is.
+    fn next(&mut self) -> Result<Message, Error> {
+        let message = self.message()?;
+        match message {
+            Message::EndOfTrack => self.ended = true,
+            _ if self.reader.remaining == 0 => self.ended = true,
+            _ => self.due += u64::from(self.reader.vlq()?),
+        }
+        Ok(message)
+    }
+
+    fn message(&mut self) -> Result<Message, Error> {
+        let byte = self.reader.byte()?;
+        // Messages can leave out the status byte if it's the same as the last
+        // one's, in which case this is the first data byte.
+        let (status, first) = if byte & 0x80 != 0 {
+            (byte, None)
+        } else if self.running_status != 0 {
+            (self.running_status, Some(byte))
+        } else {
+            return Err(Error::NotMidi);
+        };
+        match status {
+            0xFF => {
+                self.running_status = 0;
+                let kind = self.reader.byte()?;
+                let len = self.reader.vlq()?;
+                match kind {
+                    0x51 if len == 3 => {
+                        let mut tempo = 0;
+                        for _ in 0..3 {
+                            tempo = tempo << 8 | u32::from(self.reader.byte()?);
+                        }
+                        Ok(Message::Tempo(tempo))
+                    }
+                    0x2F => Ok(Message::EndOfTrack),
+                    _ => {
+                        self.reader.skip(len)?;
+                        Ok(Message::Other)
+                    }
+                }
+            }
+            0xF0 | 0xF7 => {
+                self.running_status = 0;
+                let len = self.reader.vlq()?;
+                self.reader.skip(len)?;
+                Ok(Message::Other)
+            }
+            0xF1..=0xFE => Err(Error::NotMidi),
+            _ => {
+                self.running_status = status;
+                let first = match first {
+                    Some(byte) => byte,
+                    None => self.reader.byte()?,
+                };
+                let channel = status & 0x0F;
+                match status & 0xF0 {
+                    0xC0 => Ok(Message::Program {
+                        channel,
+                        program: first,
+                    }),
+                    // Channel pressure
+                    0xD0 => Ok(Message::Other),
+                    kind => {
+                        let second = self.reader.byte()?;
+                        Ok(match kind {
+                            0x80 => Message::NoteOff {
+                                channel,
+                                key: first,
+                            },
+                            0x90 if second == 0 => Message::NoteOff {
+                                channel,
+                                key: first,
+                            },
+                            0x90 => Message::NoteOn {
+                                channel,
+                                key: first,
+                                velocity: second,
+                            },
+                            0xB0 => Message::Controller {
+                                channel,
+                                number: first,
+                                value: second,
+                            },
+                            // Key pressure and pitch bend
+                            _ => Message::Other,
+                        })
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// What each MIDI channel's notes sound like.
+#[derive(Debug, Copy, Clone)]
+struct Instrument {
+    waveform: Waveform,
+    volume: u8,
+}
+
+/// What one synthesiser channel is playing.
+#[derive(Debug, Copy, Clone)]
+struct Voice {
+    /// The MIDI channel and key, if anything.
+    note: Option<(u8, u8)>,
+    /// How many more frames a percussion hit lasts, or 0 for a note which
+    /// lasts until it's released.
+    frames_left: u8,
+    /// The frame the note started on, so we can find the oldest.
+    started: u32,
+}
+
+/// Plays a MIDI file.
+pub struct Player {
+    tracks: [Option<Track>; MAX_TRACKS],
+    /// MIDI ticks per quarter note.
+    division: u64,
+    /// Microseconds per quarter note.
+    tempo: u32,
+    /// MIDI ticks from the start.
+    now: u64,
+    /// Time which hasn't made a whole MIDI tick yet, in units of a third of
+    /// a microsecond multiplied by `division`. Keeping it means we never
+    /// drift.
+    spare: u64,
+    instruments: [Instrument; 16],
+    voices: [Voice; 3],
+    frame: u32,
+    volume: u8,
+    playing: bool,
+}
+
+impl Player {
+    /// Open a MIDI file, ready to play from the start. See the module
+    /// documentation for why this can take a while.
+    pub fn open(filename: &str) -> Result<Player, Error> {
+        let mut reader = Reader::open(filename)?;
+        // Anything too short to have a header isn't a MIDI file
+        let (id, len) = reader.chunk().map_err(|e| match e {
+            Error::Truncated => Error::NotMidi,
+            e => e,
+        })?;
+        if &id != b"MThd" || len < 6 {
+            return Err(Error::NotMidi);
+        }
+        let format = reader.u16()?;
+        let num_tracks = reader.u16()? as usize;
+        let division = reader.u16()?;
+        drop(reader);
+        if format > 1 || division & 0x8000 != 0 {
+            return Err(Error::Unsupported);
+        }
+        if num_tracks > MAX_TRACKS {
+            return Err(Error::TooManyTracks);
+        }
+        let mut tracks: [Option<Track>; MAX_TRACKS] = Default::default();
+        let mut offset = len.checked_add(8).ok_or(Error::NotMidi)?;
+        for slot in tracks.iter_mut().take(num_tracks) {
+            let track = Track::open(filename, &mut offset)?;
+            if !track.ended {
+                *slot = Some(track);
+            }
+        }
+        Ok(Player {
+            tracks,
+            division: u64::from(division.max(1)),
+            tempo: DEFAULT_TEMPO,
+            now: 0,
+            spare: 0,
+            instruments: [Instrument {
+                waveform: Waveform::Square,
+                volume: 100,
+            }; 16],
+            voices: [Voice {
+                note: None,
+                frames_left: 0,
+                started: 0,
+            }; 3],
+            frame: 0,
+            volume: 255,
+            playing: false,
+        })
+    }
+
+    /// Start playing, or carry on from where `pause` left off.
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    /// Stop playing, but remember where we'd got to. Notes which were
+    /// playing aren't picked up again.
+    pub fn pause(&mut self) {
+        self.playing = false;
+        for idx in 0..self.voices.len() {
+            self.release(idx);
+        }
+    }
+
+    /// True if we're playing (even if the file has finished).
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// True if every track has finished.
+    pub fn is_finished(&self) -> bool {
+        self.tracks.iter().all(Option::is_none)
+    }
+
+    /// Change the overall volume. The default is 255.
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume = volume;
+    }
+
+    /// Move the file on by one frame. Call this once per `Host::wfvbi`.
+    pub fn tick(&mut self) {
+        if !self.playing {
+            return;
+        }
+        self.frame = self.frame.wrapping_add(1);
+        self.end_percussion();
+        self.spare += MICROSECONDS_PER_FRAME_X3 * self.division;
+        while let Some(due) = self.tracks.iter().flatten().map(|t| t.due).min() {
+            let per_tick = u64::from(self.tempo) * 3;
+            let needed = (due - self.now) * per_tick;
+            if self.spare < needed {
+                let ticks = self.spare / per_tick;
+                self.now += ticks;
+                self.spare -= ticks * per_tick;
+                return;
+            }
+            self.spare -= needed;
+            self.now = due;
+            self.dispatch();
+        }
+        for idx in 0..self.voices.len() {
+            self.release(idx);
+        }
+        self.spare = 0;
+    }
+
+    /// Handle every event which is due now.
+    fn dispatch(&mut self) {
+        for idx in 0..MAX_TRACKS {
+            loop {
+                let track = match self.tracks[idx].as_mut() {
+                    Some(track) if track.due == self.now => track,
+                    _ => break,
+                };
+                // A broken track just stops
+                let result = track.next();
+                if track.ended || result.is_err() {
+                    self.tracks[idx] = None;
+                }
+                match result {
+                    Ok(message) => self.handle(message),
+                    Err(_) => break,
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, message: Message) {
+        match message {
+            Message::NoteOn {
+                channel: PERCUSSION,
+                key,
+                velocity,
+            } => {
+                let (frequency, frames) = percussion(key);
+                let volume = self.note_volume(PERCUSSION, velocity);
+                let idx = self.allocate(PERCUSSION, key);
+                self.voices[idx].frames_left = frames;
+                Host::play(frequency, CHANNELS[idx], Waveform::Noise, volume);
+            }
+            Message::NoteOn {
+                channel,
+                key,
+                velocity,
+            } => {
+                let volume = self.note_volume(channel, velocity);
+                let waveform = self.instruments[channel as usize].waveform;
+                let idx = self.allocate(channel, key);
+                Host::play(key_frequency(key), CHANNELS[idx], waveform, volume);
+            }
+            // Percussion stops by itself
+            Message::NoteOff { channel, key } if channel != PERCUSSION => {
+                if let Some(idx) = self
+                    .voices
+                    .iter()
+                    .position(|v| v.note == Some((channel, key)))
+                {
+                    self.release(idx);
+                }
+            }
+            Message::Controller {
+                channel,
+                number,
+                value,
+            } => match number {
+                // Channel volume
+                7 => self.instruments[channel as usize].volume = value,
+                // All sound off, and all notes off
+                120 | 123 => {
+                    for idx in 0..self.voices.len() {
+                        if let Some((c, _)) = self.voices[idx].note {
+                            if c == channel {
+                                self.release(idx);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Message::Program { channel, program } => {
+                self.instruments[channel as usize].waveform = program_waveform(program);
+            }
+            Message::Tempo(tempo) if tempo > 0 => self.tempo = tempo,
+            _ => {}
+        }
+    }
+
+    /// Pick a synthesiser channel for a new note: the one already playing
+    /// that key, or a free one, or the one which has been playing the
+    /// longest.
+    fn allocate(&mut self, channel: u8, key: u8) -> usize {
+        let frame = self.frame;
+        let idx = self
+            .voices
+            .iter()
+            .position(|v| v.note == Some((channel, key)))
+            .or_else(|| self.voices.iter().position(|v| v.note.is_none()))
+            .unwrap_or_else(|| {
+                let mut oldest = 0;
+                for (idx, voice) in self.voices.iter().enumerate() {
+                    let age = frame.wrapping_sub(voice.started);
+                    if age > frame.wrapping_sub(self.voices[oldest].started) {
+                        oldest = idx;
+                    }
+                }
+                oldest
+            });
+        self.voices[idx] = Voice {
+            note: Some((channel, key)),
+            frames_left: 0,
+            started: frame,
+        };
+        idx
+    }
+
+    fn release(&mut self, idx: usize) {
+        if self.voices[idx].note.take().is_some() {
+            self.voices[idx].frames_left = 0;
+            Host::play(Note::Rest, CHANNELS[idx], Waveform::Square, 0);
+        }
+    }
+
+    /// Count down the percussion hits, and stop the ones which are done.
+    fn end_percussion(&mut self) {
+        for idx in 0..self.voices.len() {
+            if self.voices[idx].frames_left > 0 {
+                self.voices[idx].frames_left -= 1;
+                if self.voices[idx].frames_left == 0 {
+                    self.release(idx);
+                }
+            }
+        }
+    }
+
+    /// Mix a note's velocity with its channel's volume and ours.
+    fn note_volume(&self, channel: u8, velocity: u8) -> u8 {
+        let volume = u32::from(velocity)
+            * u32::from(self.instruments[channel as usize].volume)
+            * u32::from(self.volume)
+            / (127 * 127);
+        volume.min(255) as u8
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        self.pause();
+    }
+}
+
+/// The frequency of a MIDI key, moved up or down by octaves into the range
+/// we have notes for.
+fn key_frequency(key: u8) -> Frequency {
+    let mut key = key;
+    while key < 12 {
+        key += 12;
+    }
+    while key > 119 {
+        key -= 12;
+    }
+    Note::from_midi(key).map_or(Note::Rest.frequency(), Note::frequency)
+}
+
+/// What a General MIDI percussion key sounds like: the frequency to play
+/// the noise at, and how many frames it lasts.
+fn percussion(key: u8) -> (Frequency, u8) {
+    match key {
+        // Bass drums
+        35 | 36 => (Frequency::from_hz(60), 4),
+        // Closed and pedal hi-hats
+        42 | 44 => (Frequency::from_hz(2000), 2),
+        // Open hi-hat
+        46 => (Frequency::from_hz(2000), 8),
+        // Cymbals
+        49 | 51 | 52 | 53 | 55 | 57 | 59 => (Frequency::from_hz(1200), 12),
+        // Snares, toms and the rest, pitched by their key
+        _ => (key_frequency(key.saturating_add(24)), 4),
+    }
+}
+
+/// Pick a waveform for a General MIDI program (instrument).
+fn program_waveform(program: u8) -> Waveform {
+    match program {
+        // Organs, and pipes like the flute
+        16..=23 | 72..=79 => Waveform::Sine,
+        // Guitars, basses, strings and brass
+        24..=63 => Waveform::Sawtooth,
+        _ => Waveform::Square,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A directory which stands in for the SD card, for the test which made
+    /// it. It's deleted when dropped.
+    struct SdCard(PathBuf);
+
+    impl SdCard {
+        fn new(name: &str) -> SdCard {
+            let root =
+                std::env::temp_dir().join(format!("monotron-midi-{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(&root).unwrap();
+            crate::filesystem::TEST_SDCARD.with(|card| *card.borrow_mut() = Some(root.clone()));
+            SdCard(root)
+        }
+
+        /// Put `contents` on the card as `filename`.
+        fn write(&self, filename: &str, contents: &[u8]) {
+            std::fs::write(self.0.join(filename), contents).unwrap();
+        }
+    }
+
+    impl Drop for SdCard {
+        fn drop(&mut self) {
+            crate::filesystem::TEST_SDCARD.with(|card| *card.borrow_mut() = None);
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        chunk.extend_from_slice(body);
+        chunk
+    }
+
+    fn header(format: u16, num_tracks: u16, division: u16) -> Vec<u8> {
+        let mut body = format.to_be_bytes().to_vec();
+        body.extend_from_slice(&num_tracks.to_be_bytes());
+        body.extend_from_slice(&division.to_be_bytes());
+        chunk(b"MThd", &body)
+    }
+
+    #[test]
+    fn vlq() {
+        let card = SdCard::new("vlq");
+        card.write(
+            "vlq.bin",
+            &[
+                0x00, 0x7F, 0x81, 0x00, 0xC0, 0x00, 0xFF, 0x7F, 0x81, 0x80, 0x80, 0x00, 0xFF, 0xFF,
+                0xFF, 0x7F, 0x80, 0x80, 0x80, 0x80, 0x00,
+            ],
+        );
+        let mut reader = Reader::open("vlq.bin").unwrap();
+        for &expected in &[0, 0x7F, 0x80, 0x2000, 0x3FFF, 0x20_0000, 0x0FFF_FFFF] {
+            assert_eq!(reader.vlq(), Ok(expected));
+        }
+        // No more than four bytes are allowed
+        assert_eq!(reader.vlq(), Err(Error::NotMidi));
+
+        card.write("vlq-short.bin", &[0x81, 0x80]);
+        let mut reader = Reader::open("vlq-short.bin").unwrap();
+        assert_eq!(reader.vlq(), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn running_status() {
+        let card = SdCard::new("running-status");
+        let mut file = header(0, 1, 96);
+        file.extend(chunk(
+            b"MTrk",
+            &[
+                0x00, 0x90, 0x3C, 0x40, // Note on
+                0x10, 0x3E, 0x50, // Running status note on
+                0x10, 0x3C, 0x00, // Running status note on with no velocity
+                0x00, 0xC1, 0x05, // Program change
+                0x20, 0x07, // Running status program change
+                0x00, 0xFF, 0x01, 0x01, b'x', // Text, which ends running status
+                0x00, 0x3C, 0x00,
+            ],
+        ));
+        card.write("running.mid", &file);
+        let mut offset = 14;
+        let mut track = Track::open("running.mid", &mut offset).unwrap();
+        assert_eq!(offset as usize, file.len());
+        assert_eq!(track.due, 0);
+        assert!(matches!(
+            track.next(),
+            Ok(Message::NoteOn {
+                channel: 0,
+                key: 0x3C,
+                velocity: 0x40
+            })
+        ));
+        assert_eq!(track.due, 0x10);
+        assert!(matches!(
+            track.next(),
+            Ok(Message::NoteOn {
+                channel: 0,
+                key: 0x3E,
+                velocity: 0x50
+            })
+        ));
+        assert!(matches!(
+            track.next(),
+            Ok(Message::NoteOff {
+                channel: 0,
+                key: 0x3C
+            })
+        ));
+        assert_eq!(track.due, 0x20);
+        assert!(matches!(
+            track.next(),
+            Ok(Message::Program {
+                channel: 1,
+                program: 5
+            })
+        ));
+        assert!(matches!(
+            track.next(),
+            Ok(Message::Program {
+                channel: 1,
+                program: 7
+            })
+        ));
+        assert_eq!(track.due, 0x40);
+        assert!(matches!(track.next(), Ok(Message::Other)));
+        assert!(matches!(track.next(), Err(Error::NotMidi)));
+    }
+
+    #[test]
+    fn tracks() {
+        let card = SdCard::new("tracks");
+        let mut file = header(1, 2, 96);
+        file.extend(chunk(b"XFIH", &[1, 2, 3]));
+        file.extend(chunk(
+            b"MTrk",
+            &[
+                0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, 0x00, 0xFF, 0x2F, 0x00,
+            ],
+        ));
+        let second = file.len();
+        file.extend(chunk(b"MTrk", &[0x60, 0x80, 0x3C, 0x00]));
+        card.write("tracks.mid", &file);
+
+        let mut offset = 14;
+        let mut track = Track::open("tracks.mid", &mut offset).unwrap();
+        assert_eq!(offset as usize, second);
+        assert!(matches!(track.next(), Ok(Message::Tempo(500_000))));
+        assert!(!track.ended);
+        assert!(matches!(track.next(), Ok(Message::EndOfTrack)));
+        assert!(track.ended);
+
+        // The chunk running out ends the track too
+        let mut track = Track::open("tracks.mid", &mut offset).unwrap();
+        assert_eq!(track.due, 0x60);
+        assert!(matches!(track.next(), Ok(Message::NoteOff { .. })));
+        assert!(track.ended);
+        assert!(Track::open("tracks.mid", &mut offset).is_err());
+
+        let mut player = Player::open("tracks.mid").unwrap();
+        assert!(!player.is_finished());
+        player.play();
+        for _ in 0..60 {
+            player.tick();
+        }
+        assert!(player.is_finished());
+    }
+
+    #[test]
+    fn bad_headers() {
+        let card = SdCard::new("bad-headers");
+        card.write("empty.mid", &[]);
+        assert_eq!(Player::open("empty.mid").err(), Some(Error::NotMidi));
+        card.write("riff.mid", &chunk(b"RIFF", &[0; 6]));
+        assert_eq!(Player::open("riff.mid").err(), Some(Error::NotMidi));
+        card.write("short.mid", &chunk(b"MThd", &[0; 4]));
+        assert_eq!(Player::open("short.mid").err(), Some(Error::NotMidi));
+        // A header length which would overflow the offset of the first track
+        let mut file = b"MThd\xFF\xFF\xFF\xFF".to_vec();
+        file.extend_from_slice(&[0, 0, 0, 1, 0, 96]);
+        card.write("huge.mid", &file);
+        assert_eq!(Player::open("huge.mid").err(), Some(Error::NotMidi));
+
+        card.write("format2.mid", &header(2, 1, 96));
+        assert_eq!(Player::open("format2.mid").err(), Some(Error::Unsupported));
+        card.write("smpte.mid", &header(0, 1, 0xE728));
+        assert_eq!(Player::open("smpte.mid").err(), Some(Error::Unsupported));
+        card.write("many.mid", &header(1, MAX_TRACKS as u16 + 1, 96));
+        assert_eq!(Player::open("many.mid").err(), Some(Error::TooManyTracks));
+        card.write("missing.mid", &header(0, 1, 96));
+        assert_eq!(Player::open("missing.mid").err(), Some(Error::Truncated));
+        assert_eq!(
+            Player::open("nothere.mid").err(),
+            Some(Error::Io(monotron_api::Error::FileNotFound))
+        );
+    }
+}
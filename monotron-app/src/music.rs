@@ -0,0 +1,474 @@
+//! Plays tunes on the synthesiser.
+//!
+//! A tune is up to three `Track`s, one per `Channel`, each a list of notes
+//! and rests with a `Length`. Put them in a `Sequencer`, set the tempo, and
+//! call `Sequencer::tick` once per frame (i.e. after every `Host::wfvbi`).
+//! Each track plays its notes with an `instrument::Instrument`, which is a
+//! plain square wave unless you give it another.
+//!
+//! ```rust,ignore
+//! static MELODY: [Event; 3] = [
+//!     Event::note(Note::C5, Length::QUARTER),
+//!     Event::note(Note::E5, Length::QUARTER.dotted()),
+//!     Event::rest(Length::EIGHTH),
+//! ];
+//!
+//! let mut sequencer = Sequencer::new(120);
+//! sequencer.set_track(Track::new(Channel::Channel0, &MELODY));
+//! sequencer.play();
+//! loop {
+//!     Host::wfvbi();
+//!     sequencer.tick();
+//! }
+//! ```
+
+use super::instrument::{Instrument, Voice};
+use super::{Channel, Frequency, Note, Waveform};
+
+/// How many times `tick` is called each minute.
+const FRAMES_PER_MINUTE: u64 = 60 * 60;
+
+/// The length of a note or rest, in 192ths of a whole note. That's fine
+/// enough for dotted notes and triplets down to a thirty-second note.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct Length(pub u16);
+
+#[allow(missing_docs)]
+impl Length {
+    pub const WHOLE: Length = Length(192);
+    pub const HALF: Length = Length(96);
+    pub const QUARTER: Length = Length(48);
+    pub const EIGHTH: Length = Length(24);
+    pub const SIXTEENTH: Length = Length(12);
+    pub const THIRTY_SECOND: Length = Length(6);
+
+    /// Half as long again.
+    pub const fn dotted(self) -> Length {
+        Length(self.0 + self.0 / 2)
+    }
+
+    /// Two thirds as long, so three fit in the time of two.
+    pub const fn triplet(self) -> Length {
+        Length(self.0 * 2 / 3)
+    }
+
+    /// This length plus another one, as if they were tied together.
+    pub const fn tied(self, other: Length) -> Length {
+        Length(self.0 + other.0)
+    }
+}
+
+/// A note, or a rest, in a `Track`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub struct Event {
+    /// What to play. 0 Hz is a rest.
+    pub frequency: Frequency,
+    /// How long until the next event
+    pub length: Length,
+}
+
+impl Event {
+    /// Play a note.
+    pub const fn note(note: Note, length: Length) -> Event {
+        Event {
+            frequency: note.frequency(),
+            length,
+        }
+    }
+
+    /// Play nothing.
+    pub const fn rest(length: Length) -> Event {
+        Event {
+            frequency: Frequency::from_centi_hz(0),
+            length,
+        }
+    }
+
+    /// True if this is a rest.
+    pub fn is_rest(&self) -> bool {
+        self.frequency.as_centi_hz() == 0
+    }
+}
+
+/// A list of events to play, one after the other, on one channel.
+#[derive(Debug, Clone)]
+pub struct Track<'a> {
+    events: &'a [Event],
+    voice: Voice,
+    volume: u8,
+    looping: bool,
+    /// How many frames of silence to leave at the end of each note.
+    gap: u8,
+    /// The next event to play.
+    next: usize,
+    /// When the next event starts, in 192ths of a whole note.
+    due: u64,
+    /// When to silence the current note, in `Sequencer::clock` units.
+    note_off: Option<u64>,
+    finished: bool,
+}
+
+impl<'a> Track<'a> {
+    /// Make a track which plays `events` on `channel`, with a square wave at
+    /// half volume, over and over again.
+    pub const fn new(channel: Channel, events: &'a [Event]) -> Track<'a> {
+        Track {
+            events,
+            voice: Voice::new(channel, Instrument::new(Waveform::Square)),
+            volume: 128,
+            looping: true,
+            gap: 0,
+            next: 0,
+            due: 0,
+            note_off: None,
+            finished: false,
+        }
+    }
+
+    /// Change the waveform the notes are played with.
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        self.voice.set_waveform(waveform);
+    }
+
+    /// Change the instrument the notes are played with (including its
+    /// waveform).
+    pub fn set_instrument(&mut self, instrument: Instrument) {
+        self.voice.set_instrument(instrument);
+    }
+
+    /// Change the volume the notes are played at.
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume = volume;
+    }
+
+    /// Go back to the first event at the end of the track, or stop there.
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    /// Release each note `frames` frames early, so notes of the same pitch
+    /// don't run into each other.
+    pub fn set_gap(&mut self, frames: u8) {
+        self.gap = frames;
+    }
+
+    /// The channel the track plays on.
+    pub fn channel(&self) -> Channel {
+        self.voice.channel()
+    }
+
+    /// True if the track has played all its events and isn't looping.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Go back to the first event.
+    fn rewind(&mut self) {
+        self.next = 0;
+        self.due = 0;
+        self.note_off = None;
+        self.finished = false;
+    }
+
+    fn silence(&mut self) {
+        self.voice.silence();
+    }
+
+    /// Play whatever is due at `clock`, which goes up by `per_frame` each
+    /// frame.
+    fn update(&mut self, clock: u64, per_frame: u64) {
+        if self.finished {
+            return;
+        }
+        if let Some(note_off) = self.note_off {
+            if clock >= note_off {
+                self.voice.note_off();
+                self.note_off = None;
+            }
+        }
+        // Don't get stuck on a track which is all zero-length events.
+        for _ in 0..=self.events.len() {
+            if clock < self.due * FRAMES_PER_MINUTE {
+                return;
+            }
+            if self.next >= self.events.len() {
+                if self.looping && !self.events.is_empty() {
+                    self.next = 0;
+                } else {
+                    self.voice.note_off();
+                    self.finished = true;
+                    return;
+                }
+            }
+            let event = self.events[self.next];
+            self.next += 1;
+            self.due += u64::from(event.length.0);
+            self.note_off = None;
+            if event.is_rest() {
+                self.voice.note_off();
+            } else {
+                self.voice.note_on(event.frequency, self.volume);
+                let gap = u64::from(self.gap) * per_frame;
+                let end = self.due * FRAMES_PER_MINUTE;
+                if gap > 0 && end.saturating_sub(gap) > clock {
+                    self.note_off = Some(end - gap);
+                }
+            }
+        }
+    }
+}
+
+/// Plays up to three tracks together, at a given tempo.
+pub struct Sequencer<'a> {
+    tracks: [Option<Track<'a>>; 3],
+    /// Quarter notes per minute.
+    bpm: u16,
+    /// How far through the tune we are, in 192ths of a whole note
+    /// multiplied by `FRAMES_PER_MINUTE`. It goes up by a whole number each
+    /// frame, so we never drift.
+    clock: u64,
+    playing: bool,
+}
+
+impl<'a> Sequencer<'a> {
+    /// Make a sequencer with no tracks, which plays `bpm` quarter notes a
+    /// minute.
+    pub const fn new(bpm: u16) -> Sequencer<'a> {
+        Sequencer {
+            tracks: [None, None, None],
+            bpm,
+            clock: 0,
+            playing: false,
+        }
+    }
+
+    /// Play a track on its channel, replacing whatever was there. The track
+    /// starts from wherever the others have got to.
+    pub fn set_track(&mut self, track: Track<'a>) {
+        let idx = track.channel() as usize;
+        self.clear_track(track.channel());
+        let mut track = track;
+        track.rewind();
+        track.due = self.clock / FRAMES_PER_MINUTE;
+        self.tracks[idx] = Some(track);
+    }
+
+    /// Stop playing anything on a channel.
+    pub fn clear_track(&mut self, channel: Channel) {
+        if let Some(mut track) = self.tracks[channel as usize].take() {
+            track.silence();
+        }
+    }
+
+    /// Get at the track on a channel, e.g. to change its volume.
+    pub fn track_mut(&mut self, channel: Channel) -> Option<&mut Track<'a>> {
+        self.tracks[channel as usize].as_mut()
+    }
+
+    /// Change the tempo, in quarter notes per minute. It's fine to do this
+    /// while playing.
+    pub fn set_tempo(&mut self, bpm: u16) {
+        self.bpm = bpm;
+    }
+
+    /// The tempo, in quarter notes per minute.
+    pub fn tempo(&self) -> u16 {
+        self.bpm
+    }
+
+    /// Start playing, or carry on from where `pause` left off.
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    /// Stop playing, but remember where we'd got to.
+    pub fn pause(&mut self) {
+        self.playing = false;
+        self.silence();
+    }
+
+    /// Stop playing, and go back to the start.
+    pub fn stop(&mut self) {
+        self.pause();
+        self.clock = 0;
+        for track in self.tracks.iter_mut().flatten() {
+            track.rewind();
+        }
+    }
+
+    /// True if we're playing (even if every track has finished).
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// True if every track has finished.
+    pub fn is_finished(&self) -> bool {
+        self.tracks.iter().flatten().all(|track| track.is_finished())
+    }
+
+    /// Move the tune on by one frame. Call this once per `Host::wfvbi`.
+    pub fn tick(&mut self) {
+        if !self.playing {
+            return;
+        }
+        let per_frame = u64::from(self.bpm) * u64::from(Length::QUARTER.0);
+        for track in self.tracks.iter_mut().flatten() {
+            // Move the last note on before (maybe) starting the next one
+            track.voice.tick();
+            track.update(self.clock, per_frame);
+        }
+        self.clock += per_frame;
+    }
+
+    fn silence(&mut self) {
+        for track in self.tracks.iter_mut().flatten() {
+            track.silence();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static QUARTERS: [Event; 4] = [Event::note(Note::C4, Length::QUARTER); 4];
+
+    fn track<'s, 'a>(sequencer: &'s Sequencer<'a>) -> &'s Track<'a> {
+        sequencer.tracks[0].as_ref().unwrap()
+    }
+
+    fn playing(bpm: u16, events: &[Event], looping: bool) -> Sequencer<'_> {
+        let mut track = Track::new(Channel::Channel0, events);
+        track.set_looping(looping);
+        let mut sequencer = Sequencer::new(bpm);
+        sequencer.set_track(track);
+        sequencer.play();
+        sequencer
+    }
+
+    /// Tick `sequencer` `frames` times, and list the frames on which its
+    /// track started an event.
+    fn starts(sequencer: &mut Sequencer, frames: u32) -> Vec<u32> {
+        let mut starts = Vec::new();
+        for frame in 0..frames {
+            let due = track(sequencer).due;
+            sequencer.tick();
+            if track(sequencer).due != due {
+                starts.push(frame);
+            }
+        }
+        starts
+    }
+
+    #[test]
+    fn quarter_notes_at_120_bpm() {
+        let mut sequencer = playing(120, &QUARTERS, false);
+        assert_eq!(starts(&mut sequencer, 120), [0, 30, 60, 90]);
+        assert!(track(&sequencer).voice.is_sounding());
+        assert!(!sequencer.is_finished());
+        sequencer.tick();
+        assert!(sequencer.is_finished());
+        assert!(!track(&sequencer).voice.is_sounding());
+    }
+
+    #[test]
+    fn no_drift() {
+        // 70 bpm is 51 3/7 frames per quarter note, but a minute of them
+        // still takes exactly 3600 frames
+        let mut sequencer = playing(70, &QUARTERS, true);
+        let starts = starts(&mut sequencer, 3601);
+        assert_eq!(starts.len(), 71);
+        assert_eq!(starts[..5], [0, 52, 103, 155, 206]);
+        assert_eq!(starts[70], 3600);
+    }
+
+    #[test]
+    fn tempo_changes() {
+        let mut sequencer = playing(120, &QUARTERS, false);
+        assert_eq!(starts(&mut sequencer, 15), [0]);
+        // Half of the first note is left, which takes 7.5 frames at 240 bpm
+        sequencer.set_tempo(240);
+        assert_eq!(sequencer.tempo(), 240);
+        assert_eq!(starts(&mut sequencer, 50), [8, 23, 38]);
+        // Going back to 120 bpm half way through the second note puts us
+        // back on the beat, rather than half a frame late
+        let mut sequencer = playing(120, &QUARTERS, false);
+        assert_eq!(starts(&mut sequencer, 15), [0]);
+        sequencer.set_tempo(240);
+        assert_eq!(starts(&mut sequencer, 15), [8]);
+        sequencer.set_tempo(120);
+        assert_eq!(starts(&mut sequencer, 46), [15, 45]);
+    }
+
+    #[test]
+    fn gaps() {
+        let mut sequencer = playing(120, &QUARTERS, false);
+        sequencer.track_mut(Channel::Channel0).unwrap().set_gap(5);
+        let mut sounding = Vec::new();
+        for _ in 0..35 {
+            sequencer.tick();
+            sounding.push(track(&sequencer).voice.is_sounding());
+        }
+        assert!(sounding[..25].iter().all(|&on| on));
+        assert!(sounding[25..30].iter().all(|&on| !on));
+        assert!(sounding[30..].iter().all(|&on| on));
+    }
+
+    #[test]
+    fn rests() {
+        let events = [
+            Event::note(Note::C4, Length::EIGHTH),
+            Event::rest(Length::EIGHTH),
+            Event::note(Note::C4, Length::EIGHTH),
+        ];
+        let mut sequencer = playing(120, &events, false);
+        assert_eq!(starts(&mut sequencer, 15), [0]);
+        assert!(track(&sequencer).voice.is_sounding());
+        assert_eq!(starts(&mut sequencer, 15), [0]);
+        assert!(!track(&sequencer).voice.is_sounding());
+        assert_eq!(starts(&mut sequencer, 15), [0]);
+        assert!(track(&sequencer).voice.is_sounding());
+    }
+
+    #[test]
+    fn looping() {
+        let mut sequencer = playing(120, &QUARTERS[..2], true);
+        assert_eq!(starts(&mut sequencer, 120), [0, 30, 60, 90]);
+        assert!(!sequencer.is_finished());
+        assert_eq!(track(&sequencer).next, 2);
+
+        // Nothing to play, even if it loops
+        let mut sequencer = playing(120, &[], true);
+        sequencer.tick();
+        assert!(sequencer.is_finished());
+
+        // A track with no length mustn't hang
+        let events = [Event::rest(Length(0)); 2];
+        let mut sequencer = playing(120, &events, true);
+        sequencer.tick();
+        assert!(!sequencer.is_finished());
+    }
+
+    #[test]
+    fn stopping_and_joining() {
+        let mut sequencer = playing(120, &QUARTERS, false);
+        starts(&mut sequencer, 45);
+        sequencer.pause();
+        assert!(!track(&sequencer).voice.is_sounding());
+        assert_eq!(starts(&mut sequencer, 100), []);
+        sequencer.play();
+        assert_eq!(starts(&mut sequencer, 16), [15]);
+        sequencer.stop();
+        sequencer.play();
+        assert_eq!(starts(&mut sequencer, 31), [0, 30]);
+
+        // A new track starts on the next frame, at whatever point in the
+        // bar the others have got to
+        let mut sequencer = playing(120, &QUARTERS, false);
+        starts(&mut sequencer, 40);
+        sequencer.set_track(Track::new(Channel::Channel0, &QUARTERS));
+        assert_eq!(starts(&mut sequencer, 31), [0, 30]);
+    }
+}
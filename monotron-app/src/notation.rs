@@ -0,0 +1,914 @@
+//! Turns tunes written as text into `music::Event`s.
+//!
+//! Two notations are understood:
+//!
+//! * MML (Music Macro Language), as used by `PLAY` in lots of BASICs. A
+//!   string holds up to three channels, separated by `;`.
+//! * RTTTL (Ring Tone Text Transfer Language), as used by old mobile phones.
+//!   A string holds one channel.
+//!
+//! The parsers are all `const fn`s, so a tune can be turned into events when
+//! the app is compiled (a mistake in the tune is then a compile error):
+//!
+//! ```rust,ignore
+//! const SONG: &str = "T150 O4 L8 EDCDEEE4; O2 L4 CGCG";
+//! static MELODY: [Event; notation::mml_len(SONG, 0)] = notation::parse_mml(SONG, 0);
+//! static BASS: [Event; notation::mml_len(SONG, 1)] = notation::parse_mml(SONG, 1);
+//! ```
+//!
+//! or when it runs, from a string or a file loaded with `Host::read`:
+//!
+//! ```rust,ignore
+//! let mut events = [Event::rest(Length::QUARTER); 256];
+//! let count = notation::parse_mml_into(&text[..len], 0, &mut events)?;
+//! sequencer.set_tempo(notation::mml_tempo(&text[..len]));
+//! sequencer.set_track(Track::new(Channel::Channel0, &events[..count]));
+//! ```
+//!
+//! In MML, letters can be upper or lower case, and spaces and `|` are
+//! ignored:
+//!
+//! * `A` to `G` play a note, sharpened by `+` or `#`, or flattened by `-`.
+//!   A number after the note sets its length (`4` is a quarter note) and
+//!   each `.` after that makes it half as long again. `&` ties two notes of
+//!   the same pitch together, as in `C2&C8`.
+//! * `R` or `P` rests, with a length like a note.
+//! * `O` sets the octave (`O4` is the one with middle C in), and `>` and `<`
+//!   go up and down one.
+//! * `L` sets the length for notes without one (`L8` means eighth notes).
+//! * `T` sets the tempo, in quarter notes per minute. The first `T` sets the
+//!   tempo for the whole tune (see `mml_tempo`); later ones stretch or
+//!   squash the notes after them.
+//! * `V` (volume) is skipped, as the volume belongs to the `Track`.
+//!
+//! An RTTTL tune looks like `name:d=4,o=5,b=120:8e6,8d6,4c6,p,2c.6`.
+
+use super::music::{Event, Length};
+use super::Note;
+
+/// The tempo of an MML tune without a `T`.
+pub const MML_DEFAULT_TEMPO: u16 = 120;
+
+/// The tempo of an RTTTL tune without a `b=`.
+pub const RTTTL_DEFAULT_TEMPO: u16 = 63;
+
+/// What was wrong with a tune.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A character which doesn't mean anything there
+    UnexpectedChar,
+    /// A number was missing, or out of range
+    BadNumber,
+    /// A note below C0 or above B8
+    NoteOutOfRange,
+    /// An octave below 0 or above 8
+    OctaveOutOfRange,
+    /// There wasn't room for all the events
+    TooManyEvents,
+    /// An RTTTL tune without `name:defaults:` at the start
+    BadHeader,
+    /// Notes tied together with `&` which aren't the same pitch
+    TieMismatch,
+}
+
+impl ErrorKind {
+    /// Describe the error.
+    pub const fn message(self) -> &'static str {
+        match self {
+            ErrorKind::UnexpectedChar => "unexpected character in tune",
+            ErrorKind::BadNumber => "missing or bad number in tune",
+            ErrorKind::NoteOutOfRange => "note out of range in tune",
+            ErrorKind::OctaveOutOfRange => "octave out of range in tune",
+            ErrorKind::TooManyEvents => "too many events in tune",
+            ErrorKind::BadHeader => "bad RTTTL header",
+            ErrorKind::TieMismatch => "tied notes differ in tune",
+        }
+    }
+}
+
+/// What was wrong with a tune, and where.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Error {
+    /// How many bytes into the text the problem is
+    pub offset: usize,
+    /// What the problem is
+    pub kind: ErrorKind,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Format {
+    Mml,
+    Rtttl,
+}
+
+/// Where a parser has got to.
+#[derive(Debug, Copy, Clone)]
+struct State {
+    format: Format,
+    pos: usize,
+    /// Where this channel's text ends.
+    end: usize,
+    octave: u8,
+    /// The length of notes which don't give one.
+    length: u16,
+    tempo: u16,
+    song_tempo: u16,
+}
+
+enum Step {
+    Event(Event, State),
+    End,
+    Error(Error),
+}
+
+const fn error<T>(offset: usize, kind: ErrorKind) -> Result<T, Error> {
+    Err(Error { offset, kind })
+}
+
+/// Read a decimal number, returning it and the position after it.
+const fn number(src: &[u8], pos: usize, end: usize) -> Result<(u16, usize), Error> {
+    let mut pos = pos;
+    let start = pos;
+    let mut value: u32 = 0;
+    while pos < end && src[pos].is_ascii_digit() {
+        value = value * 10 + (src[pos] - b'0') as u32;
+        if value > u16::MAX as u32 {
+            return error(start, ErrorKind::BadNumber);
+        }
+        pos += 1;
+    }
+    if pos == start {
+        error(start, ErrorKind::BadNumber)
+    } else {
+        Ok((value as u16, pos))
+    }
+}
+
+/// The length of a 1/`divisor` note.
+const fn divisor_length(divisor: u16, offset: usize) -> Result<u16, Error> {
+    if divisor == 0 || divisor > Length::WHOLE.0 {
+        error(offset, ErrorKind::BadNumber)
+    } else {
+        // Rounded, for things like quintuplets which don't divide exactly
+        Ok((Length::WHOLE.0 + divisor / 2) / divisor)
+    }
+}
+
+/// Add on half as much again for each `.` at `pos`.
+const fn dots(src: &[u8], pos: usize, end: usize, length: u16) -> (u16, usize) {
+    let mut pos = pos;
+    let mut total = length;
+    let mut extra = length / 2;
+    while pos < end && src[pos] == b'.' {
+        total = total.saturating_add(extra);
+        extra /= 2;
+        pos += 1;
+    }
+    (total, pos)
+}
+
+/// Read an optional length (a divisor and some dots), returning `default`
+/// (plus any dots) if there's no number.
+const fn note_length(
+    src: &[u8],
+    pos: usize,
+    end: usize,
+    default: u16,
+) -> Result<(u16, usize), Error> {
+    if pos < end && src[pos].is_ascii_digit() {
+        match number(src, pos, end) {
+            Ok((divisor, after)) => match divisor_length(divisor, pos) {
+                Ok(length) => Ok(dots(src, after, end, length)),
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        }
+    } else {
+        Ok(dots(src, pos, end, default))
+    }
+}
+
+/// The semitones above C for a note letter.
+const fn semitone(letter: u8) -> Option<i16> {
+    match letter.to_ascii_uppercase() {
+        b'C' => Some(0),
+        b'D' => Some(2),
+        b'E' => Some(4),
+        b'F' => Some(5),
+        b'G' => Some(7),
+        b'A' => Some(9),
+        // B is called H in some countries
+        b'B' | b'H' => Some(11),
+        _ => None,
+    }
+}
+
+/// The note `semitone` semitones above C in `octave`.
+const fn make_note(octave: u8, semitone: i16) -> Option<Note> {
+    // MIDI note 12 is C0
+    let key = 12 + octave as i16 * 12 + semitone;
+    if key < 0 || key > u8::MAX as i16 {
+        None
+    } else {
+        Note::from_midi(key as u8)
+    }
+}
+
+/// Read the pitch of the MML note (or rest) whose letter is at `pos`, and
+/// any sharps and flats after it, returning it and the position after it.
+const fn mml_pitch(src: &[u8], pos: usize, end: usize, octave: u8) -> Result<(Note, usize), Error> {
+    let mut after = pos + 1;
+    let mut semi = match semitone(src[pos]) {
+        Some(semi) => semi,
+        None => return Ok((Note::Rest, after)),
+    };
+    while after < end {
+        match src[after] {
+            b'+' | b'#' => semi += 1,
+            b'-' => semi -= 1,
+            _ => break,
+        }
+        after += 1;
+    }
+    match make_note(octave, semi) {
+        Some(note) => Ok((note, after)),
+        None => error(pos, ErrorKind::NoteOutOfRange),
+    }
+}
+
+const fn skip_spaces(src: &[u8], pos: usize, end: usize) -> usize {
+    let mut pos = pos;
+    while pos < end && src[pos].is_ascii_whitespace() {
+        pos += 1;
+    }
+    pos
+}
+
+/// Work out how long a note is at the current tempo, in terms of the song's
+/// tempo.
+const fn at_tempo(length: u16, state: &State) -> Length {
+    let scaled = length as u32 * state.song_tempo as u32 / state.tempo as u32;
+    if scaled > u16::MAX as u32 {
+        Length(u16::MAX)
+    } else {
+        Length(scaled as u16)
+    }
+}
+
+/// Find the start and end of an MML channel, if there is one.
+const fn mml_channel(src: &[u8], channel: usize) -> Option<(usize, usize)> {
+    let mut pos = 0;
+    let mut current = 0;
+    let mut start = 0;
+    while pos < src.len() {
+        if src[pos] == b';' {
+            if current == channel {
+                return Some((start, pos));
+            }
+            current += 1;
+            start = pos + 1;
+        }
+        pos += 1;
+    }
+    if current == channel {
+        Some((start, pos))
+    } else {
+        None
+    }
+}
+
+/// The tempo of an MML tune - the first `T` in it, or `MML_DEFAULT_TEMPO`.
+pub const fn mml_tempo(src: &[u8]) -> u16 {
+    let mut pos = 0;
+    while pos < src.len() {
+        if src[pos].eq_ignore_ascii_case(&b'T') {
+            return match number(src, pos + 1, src.len()) {
+                Ok((tempo, _)) if tempo > 0 => tempo,
+                _ => MML_DEFAULT_TEMPO,
+            };
+        }
+        pos += 1;
+    }
+    MML_DEFAULT_TEMPO
+}
+
+const fn mml_start(src: &[u8], channel: usize) -> Option<State> {
+    match mml_channel(src, channel) {
+        Some((start, end)) => {
+            let tempo = mml_tempo(src);
+            Some(State {
+                format: Format::Mml,
+                pos: start,
+                end,
+                octave: 4,
+                length: Length::QUARTER.0,
+                tempo,
+                song_tempo: tempo,
+            })
+        }
+        None => None,
+    }
+}
+
+const fn mml_next(src: &[u8], state: State) -> Step {
+    let mut s = state;
+    while s.pos < s.end {
+        let here = s.pos;
+        let c = src[here].to_ascii_uppercase();
+        s.pos += 1;
+        match c {
+            b' ' | b'\t' | b'\r' | b'\n' | b'|' => {}
+            b'O' => match number(src, s.pos, s.end) {
+                Ok((octave, pos)) if octave <= 8 => {
+                    s.octave = octave as u8;
+                    s.pos = pos;
+                }
+                Ok(_) => {
+                    return Step::Error(Error {
+                        offset: here,
+                        kind: ErrorKind::OctaveOutOfRange,
+                    })
+                }
+                Err(e) => return Step::Error(e),
+            },
+            b'>' | b'<' => {
+                if (c == b'>' && s.octave == 8) || (c == b'<' && s.octave == 0) {
+                    return Step::Error(Error {
+                        offset: here,
+                        kind: ErrorKind::OctaveOutOfRange,
+                    });
+                }
+                s.octave = if c == b'>' {
+                    s.octave + 1
+                } else {
+                    s.octave - 1
+                };
+            }
+            b'L' => match number(src, s.pos, s.end) {
+                Ok((divisor, pos)) => match divisor_length(divisor, s.pos) {
+                    Ok(length) => {
+                        let (length, pos) = dots(src, pos, s.end, length);
+                        s.length = length;
+                        s.pos = pos;
+                    }
+                    Err(e) => return Step::Error(e),
+                },
+                Err(e) => return Step::Error(e),
+            },
+            b'T' => match number(src, s.pos, s.end) {
+                Ok((tempo, pos)) if tempo > 0 => {
+                    s.tempo = tempo;
+                    s.pos = pos;
+                }
+                Ok(_) => {
+                    return Step::Error(Error {
+                        offset: here,
+                        kind: ErrorKind::BadNumber,
+                    })
+                }
+                Err(e) => return Step::Error(e),
+            },
+            b'V' => match number(src, s.pos, s.end) {
+                Ok((_volume, pos)) => s.pos = pos,
+                Err(e) => return Step::Error(e),
+            },
+            b'A'..=b'G' | b'R' | b'P' => {
+                let note = match mml_pitch(src, here, s.end, s.octave) {
+                    Ok((note, pos)) => {
+                        s.pos = pos;
+                        note
+                    }
+                    Err(e) => return Step::Error(e),
+                };
+                let mut length = match note_length(src, s.pos, s.end, s.length) {
+                    Ok((length, pos)) => {
+                        s.pos = pos;
+                        length
+                    }
+                    Err(e) => return Step::Error(e),
+                };
+                // Add on the lengths of any notes tied to this one
+                loop {
+                    let mut pos = skip_spaces(src, s.pos, s.end);
+                    if pos >= s.end || src[pos] != b'&' {
+                        break;
+                    }
+                    pos = skip_spaces(src, pos + 1, s.end);
+                    let tied = if pos < s.end {
+                        src[pos].to_ascii_uppercase()
+                    } else {
+                        0
+                    };
+                    if !matches!(tied, b'A'..=b'G' | b'R' | b'P') {
+                        return Step::Error(Error {
+                            offset: pos,
+                            kind: ErrorKind::UnexpectedChar,
+                        });
+                    }
+                    pos = match mml_pitch(src, pos, s.end, s.octave) {
+                        Ok((tied_note, after)) if tied_note as u8 == note as u8 => after,
+                        Ok(_) => {
+                            return Step::Error(Error {
+                                offset: pos,
+                                kind: ErrorKind::TieMismatch,
+                            })
+                        }
+                        Err(e) => return Step::Error(e),
+                    };
+                    match note_length(src, pos, s.end, s.length) {
+                        Ok((extra, after)) => {
+                            length = length.saturating_add(extra);
+                            s.pos = after;
+                        }
+                        Err(e) => return Step::Error(e),
+                    }
+                }
+                let event = Event::note(note, at_tempo(length, &s));
+                return Step::Event(event, s);
+            }
+            _ => {
+                return Step::Error(Error {
+                    offset: here,
+                    kind: ErrorKind::UnexpectedChar,
+                })
+            }
+        }
+    }
+    Step::End
+}
+
+/// Parse the `name:defaults:` at the start of an RTTTL tune.
+const fn rtttl_start(src: &[u8]) -> Result<State, Error> {
+    let mut pos = 0;
+    while pos < src.len() && src[pos] != b':' {
+        pos += 1;
+    }
+    if pos == src.len() {
+        return error(0, ErrorKind::BadHeader);
+    }
+    pos += 1;
+    let mut state = State {
+        format: Format::Rtttl,
+        pos: 0,
+        end: src.len(),
+        octave: 6,
+        length: Length::QUARTER.0,
+        tempo: RTTTL_DEFAULT_TEMPO,
+        song_tempo: RTTTL_DEFAULT_TEMPO,
+    };
+    loop {
+        pos = skip_spaces(src, pos, src.len());
+        if pos >= src.len() {
+            return error(pos, ErrorKind::BadHeader);
+        }
+        let key = src[pos].to_ascii_lowercase();
+        match key {
+            b':' => break,
+            b',' => {
+                pos += 1;
+                continue;
+            }
+            _ => {}
+        }
+        let equals = skip_spaces(src, pos + 1, src.len());
+        if equals >= src.len() || src[equals] != b'=' {
+            return error(pos, ErrorKind::BadHeader);
+        }
+        let start = skip_spaces(src, equals + 1, src.len());
+        let value = match number(src, start, src.len()) {
+            Ok((value, after)) => {
+                pos = after;
+                value
+            }
+            Err(e) => return Err(e),
+        };
+        match key {
+            b'd' => match divisor_length(value, start) {
+                Ok(length) => state.length = length,
+                Err(e) => return Err(e),
+            },
+            b'o' if value <= 8 => state.octave = value as u8,
+            b'o' => return error(start, ErrorKind::OctaveOutOfRange),
+            b'b' if value > 0 => {
+                state.tempo = value;
+                state.song_tempo = value;
+            }
+            b'b' => return error(start, ErrorKind::BadNumber),
+            // Anything else (like `l=` for looping) is ignored
+            _ => {}
+        }
+    }
+    state.pos = pos + 1;
+    Ok(state)
+}
+
+/// The tempo of an RTTTL tune - its `b=` value, or `RTTTL_DEFAULT_TEMPO`.
+pub const fn rtttl_tempo(src: &[u8]) -> u16 {
+    match rtttl_start(src) {
+        Ok(state) => state.song_tempo,
+        Err(_) => RTTTL_DEFAULT_TEMPO,
+    }
+}
+
+const fn rtttl_next(src: &[u8], state: State) -> Step {
+    let mut s = state;
+    s.pos = skip_spaces(src, s.pos, s.end);
+    while s.pos < s.end && src[s.pos] == b',' {
+        s.pos = skip_spaces(src, s.pos + 1, s.end);
+    }
+    if s.pos >= s.end {
+        return Step::End;
+    }
+    // The duration comes before the note
+    let mut length = s.length;
+    if src[s.pos].is_ascii_digit() {
+        match number(src, s.pos, s.end) {
+            Ok((divisor, after)) => match divisor_length(divisor, s.pos) {
+                Ok(l) => {
+                    length = l;
+                    s.pos = after;
+                }
+                Err(e) => return Step::Error(e),
+            },
+            Err(e) => return Step::Error(e),
+        }
+    }
+    let here = s.pos;
+    if here >= s.end {
+        return Step::Error(Error {
+            offset: here,
+            kind: ErrorKind::UnexpectedChar,
+        });
+    }
+    let letter = src[here].to_ascii_uppercase();
+    s.pos += 1;
+    let mut semi = match semitone(letter) {
+        Some(semi) => Some(semi),
+        None if letter == b'P' => None,
+        None => {
+            return Step::Error(Error {
+                offset: here,
+                kind: ErrorKind::UnexpectedChar,
+            })
+        }
+    };
+    if s.pos < s.end && src[s.pos] == b'#' {
+        if let Some(x) = semi {
+            semi = Some(x + 1);
+        }
+        s.pos += 1;
+    }
+    // The dot can go before or after the octave
+    let (mut length, pos) = dots(src, s.pos, s.end, length);
+    s.pos = pos;
+    let mut octave = s.octave;
+    if s.pos < s.end && src[s.pos].is_ascii_digit() {
+        match number(src, s.pos, s.end) {
+            Ok((o, after)) if o <= 8 => {
+                octave = o as u8;
+                s.pos = after;
+            }
+            Ok(_) => {
+                return Step::Error(Error {
+                    offset: s.pos,
+                    kind: ErrorKind::OctaveOutOfRange,
+                })
+            }
+            Err(e) => return Step::Error(e),
+        }
+        let (dotted, pos) = dots(src, s.pos, s.end, length);
+        length = dotted;
+        s.pos = pos;
+    }
+    let note = match semi {
+        Some(semi) => match make_note(octave, semi) {
+            Some(note) => note,
+            None => {
+                return Step::Error(Error {
+                    offset: here,
+                    kind: ErrorKind::NoteOutOfRange,
+                })
+            }
+        },
+        None => Note::Rest,
+    };
+    Step::Event(Event::note(note, Length(length)), s)
+}
+
+const fn next(src: &[u8], state: State) -> Step {
+    match state.format {
+        Format::Mml => mml_next(src, state),
+        Format::Rtttl => rtttl_next(src, state),
+    }
+}
+
+/// Count the events from `state` onwards.
+const fn count(src: &[u8], state: State) -> Result<usize, Error> {
+    let mut state = state;
+    let mut total = 0;
+    loop {
+        match next(src, state) {
+            Step::Event(_, after) => {
+                total += 1;
+                state = after;
+            }
+            Step::End => return Ok(total),
+            Step::Error(e) => return Err(e),
+        }
+    }
+}
+
+/// Fill an array with the events from `state` onwards, panicking (which is
+/// a compile error in a `const` or `static`) if they don't fit exactly.
+const fn fill<const N: usize>(src: &[u8], state: Option<State>) -> [Event; N] {
+    let mut out = [Event::rest(Length(0)); N];
+    let mut state = match state {
+        Some(state) => state,
+        None if N == 0 => return out,
+        None => panic!("no such channel in tune"),
+    };
+    let mut idx = 0;
+    loop {
+        match next(src, state) {
+            Step::Event(event, after) => {
+                if idx == N {
+                    panic!("{}", ErrorKind::TooManyEvents.message());
+                }
+                out[idx] = event;
+                idx += 1;
+                state = after;
+            }
+            Step::End if idx == N => return out,
+            Step::End => panic!("too few events in tune"),
+            Step::Error(e) => panic!("{}", e.kind.message()),
+        }
+    }
+}
+
+/// Parse events into `out`, returning how many there were.
+fn fill_into(src: &[u8], state: State, out: &mut [Event]) -> Result<usize, Error> {
+    let mut state = state;
+    let mut idx = 0;
+    loop {
+        match next(src, state) {
+            Step::Event(event, after) => {
+                match out.get_mut(idx) {
+                    Some(slot) => *slot = event,
+                    None => return error(state.pos, ErrorKind::TooManyEvents),
+                }
+                idx += 1;
+                state = after;
+            }
+            Step::End => return Ok(idx),
+            Step::Error(e) => return Err(e),
+        }
+    }
+}
+
+/// How many events a channel of an MML tune has. A channel which isn't in
+/// the tune has none. Panics if the tune has a mistake in it.
+pub const fn mml_len(src: &str, channel: usize) -> usize {
+    match mml_start(src.as_bytes(), channel) {
+        Some(state) => match count(src.as_bytes(), state) {
+            Ok(total) => total,
+            Err(e) => panic!("{}", e.kind.message()),
+        },
+        None => 0,
+    }
+}
+
+/// Parse a channel of an MML tune, at compile time. `N` must be
+/// `mml_len(src, channel)`. Panics if it isn't, or if the tune has a
+/// mistake in it.
+pub const fn parse_mml<const N: usize>(src: &str, channel: usize) -> [Event; N] {
+    fill(src.as_bytes(), mml_start(src.as_bytes(), channel))
+}
+
+/// Parse a channel of an MML tune into `out`, returning how many events
+/// there were. A channel which isn't in the tune has none.
+pub fn parse_mml_into(src: &[u8], channel: usize, out: &mut [Event]) -> Result<usize, Error> {
+    match mml_start(src, channel) {
+        Some(state) => fill_into(src, state, out),
+        None => Ok(0),
+    }
+}
+
+/// How many events an RTTTL tune has. Panics if the tune has a mistake in
+/// it.
+pub const fn rtttl_len(src: &str) -> usize {
+    let result = match rtttl_start(src.as_bytes()) {
+        Ok(state) => count(src.as_bytes(), state),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(total) => total,
+        Err(e) => panic!("{}", e.kind.message()),
+    }
+}
+
+/// Parse an RTTTL tune, at compile time. `N` must be `rtttl_len(src)`.
+/// Panics if it isn't, or if the tune has a mistake in it.
+pub const fn parse_rtttl<const N: usize>(src: &str) -> [Event; N] {
+    match rtttl_start(src.as_bytes()) {
+        Ok(state) => fill(src.as_bytes(), Some(state)),
+        Err(e) => panic!("{}", e.kind.message()),
+    }
+}
+
+/// Parse an RTTTL tune into `out`, returning how many events there were.
+pub fn parse_rtttl_into(src: &[u8], out: &mut [Event]) -> Result<usize, Error> {
+    match rtttl_start(src) {
+        Ok(state) => fill_into(src, state, out),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TUNE: &str = "T240 O4 L8 C D4. E; O2 C1";
+    static MELODY: [Event; mml_len(TUNE, 0)] = parse_mml(TUNE, 0);
+    static BASS: [Event; mml_len(TUNE, 1)] = parse_mml(TUNE, 1);
+    static NOTHING: [Event; mml_len(TUNE, 2)] = parse_mml(TUNE, 2);
+
+    const RINGTONE: &str = "test:d=4,o=5,b=180:8e6,p,2c.6";
+    static RINGTONE_EVENTS: [Event; rtttl_len(RINGTONE)] = parse_rtttl(RINGTONE);
+
+    fn mml(src: &str) -> Result<Vec<Event>, Error> {
+        let mut out = [Event::rest(Length(0)); 32];
+        let count = parse_mml_into(src.as_bytes(), 0, &mut out)?;
+        Ok(out[..count].to_vec())
+    }
+
+    fn rtttl(src: &str) -> Result<Vec<Event>, Error> {
+        let mut out = [Event::rest(Length(0)); 32];
+        let count = parse_rtttl_into(src.as_bytes(), &mut out)?;
+        Ok(out[..count].to_vec())
+    }
+
+    fn lengths(events: &[Event]) -> Vec<u16> {
+        events.iter().map(|e| e.length.0).collect()
+    }
+
+    fn error_at(offset: usize, kind: ErrorKind) -> Result<Vec<Event>, Error> {
+        Err(Error { offset, kind })
+    }
+
+    #[test]
+    fn compile_time_matches_run_time() {
+        assert_eq!(
+            MELODY[..],
+            [
+                Event::note(Note::C4, Length::EIGHTH),
+                Event::note(Note::D4, Length::QUARTER.dotted()),
+                Event::note(Note::E4, Length::EIGHTH),
+            ]
+        );
+        assert_eq!(BASS[..], [Event::note(Note::C2, Length::WHOLE)]);
+        assert!(NOTHING.is_empty());
+        assert_eq!(mml(TUNE).unwrap(), MELODY[..]);
+        assert_eq!(mml_tempo(TUNE.as_bytes()), 240);
+
+        assert_eq!(
+            RINGTONE_EVENTS[..],
+            [
+                Event::note(Note::E6, Length::EIGHTH),
+                Event::rest(Length::QUARTER),
+                Event::note(Note::C6, Length::HALF.dotted()),
+            ]
+        );
+        assert_eq!(rtttl(RINGTONE).unwrap(), RINGTONE_EVENTS[..]);
+        assert_eq!(rtttl_tempo(RINGTONE.as_bytes()), 180);
+    }
+
+    #[test]
+    fn mml_lengths() {
+        let events = mml("C C8 C16 C32 C2 C1 L16 C R P4").unwrap();
+        assert_eq!(lengths(&events), [48, 24, 12, 6, 96, 192, 12, 12, 48]);
+        assert!(events[7].is_rest() && events[8].is_rest());
+        // Quintuplets don't divide exactly, so they're rounded
+        assert_eq!(lengths(&mml("C5").unwrap()), [38]);
+        assert_eq!(mml("C0"), error_at(1, ErrorKind::BadNumber));
+        assert_eq!(mml("C193"), error_at(1, ErrorKind::BadNumber));
+        assert_eq!(mml("L0 C"), error_at(1, ErrorKind::BadNumber));
+    }
+
+    #[test]
+    fn mml_dots() {
+        let events = mml("C4. C4.. C. L8. C R.").unwrap();
+        assert_eq!(lengths(&events), [72, 84, 72, 36, 54]);
+    }
+
+    #[test]
+    fn mml_ties() {
+        let events = mml("C4&C8 R2 & R2 C+4&C#4 c&C").unwrap();
+        assert_eq!(lengths(&events), [72, 192, 96, 96]);
+        assert_eq!(events[0].frequency, Note::C4.frequency());
+        assert_eq!(events[2].frequency, Note::CsDb4.frequency());
+        assert_eq!(mml("C4&D4"), error_at(3, ErrorKind::TieMismatch));
+        assert_eq!(mml("C4&C#4"), error_at(3, ErrorKind::TieMismatch));
+        assert_eq!(mml("C4&R4"), error_at(3, ErrorKind::TieMismatch));
+        assert_eq!(mml("C4&"), error_at(3, ErrorKind::UnexpectedChar));
+        assert_eq!(mml("C4&O5C"), error_at(3, ErrorKind::UnexpectedChar));
+    }
+
+    #[test]
+    fn mml_tempo_changes() {
+        // Lengths are scaled against the first tempo in the tune
+        let src = "C4 T120 C4 t240 C4 T60 C4";
+        assert_eq!(mml_tempo(src.as_bytes()), 120);
+        assert_eq!(lengths(&mml(src).unwrap()), [48, 48, 24, 96]);
+        assert_eq!(mml_tempo(b"C"), MML_DEFAULT_TEMPO);
+        assert_eq!(mml("C T0"), error_at(2, ErrorKind::BadNumber));
+        assert_eq!(mml("C T"), error_at(3, ErrorKind::BadNumber));
+    }
+
+    #[test]
+    fn mml_octaves() {
+        let events = mml("A O2 A > A >> A < A O0 C O8 B").unwrap();
+        let expected = [
+            Note::A4,
+            Note::A2,
+            Note::A3,
+            Note::A5,
+            Note::A4,
+            Note::C0,
+            Note::B8,
+        ];
+        for (event, note) in events.iter().zip(expected.iter()) {
+            assert_eq!(event.frequency, note.frequency());
+        }
+        assert_eq!(events.len(), expected.len());
+        assert_eq!(mml("C O9"), error_at(2, ErrorKind::OctaveOutOfRange));
+        assert_eq!(mml("O8 >"), error_at(3, ErrorKind::OctaveOutOfRange));
+        assert_eq!(mml("O0 <"), error_at(3, ErrorKind::OctaveOutOfRange));
+        assert_eq!(mml("O0 C-"), error_at(3, ErrorKind::NoteOutOfRange));
+        assert_eq!(mml("O8 B+"), error_at(3, ErrorKind::NoteOutOfRange));
+    }
+
+    #[test]
+    fn mml_errors() {
+        assert_eq!(mml("C4 X"), error_at(3, ErrorKind::UnexpectedChar));
+        assert_eq!(mml("C V"), error_at(3, ErrorKind::BadNumber));
+        assert_eq!(mml("C 99999999"), error_at(2, ErrorKind::UnexpectedChar));
+        // Volume is skipped, and bars are ignored
+        assert_eq!(mml("V15 C | C").unwrap().len(), 2);
+        let mut out = [Event::rest(Length(0)); 1];
+        assert_eq!(
+            parse_mml_into(b"C C", 0, &mut out).map_err(|e| e.kind),
+            Err(ErrorKind::TooManyEvents)
+        );
+        assert_eq!(parse_mml_into(b"C", 1, &mut out), Ok(0));
+    }
+
+    #[test]
+    fn rtttl_headers() {
+        let events = rtttl("name:d=8,o=4,b=200:c,4d5").unwrap();
+        assert_eq!(
+            events,
+            [
+                Event::note(Note::C4, Length::EIGHTH),
+                Event::note(Note::D5, Length::QUARTER),
+            ]
+        );
+        assert_eq!(rtttl_tempo(b"name:d=8,o=4,b=200:c"), 200);
+        // Spaces are allowed, and unknown settings are ignored
+        let events = rtttl("name : d = 2 , l = 15 : c").unwrap();
+        assert_eq!(events, [Event::note(Note::C6, Length::HALF)]);
+        // Anything not given gets the defaults
+        let events = rtttl("name::c").unwrap();
+        assert_eq!(events, [Event::note(Note::C6, Length::QUARTER)]);
+        assert_eq!(rtttl_tempo(b"name::c"), RTTTL_DEFAULT_TEMPO);
+
+        assert_eq!(rtttl("no header"), error_at(0, ErrorKind::BadHeader));
+        assert_eq!(rtttl("name:d=8"), error_at(8, ErrorKind::BadHeader));
+        assert_eq!(rtttl("name:q:c"), error_at(5, ErrorKind::BadHeader));
+        assert_eq!(rtttl("name:d=x:c"), error_at(7, ErrorKind::BadNumber));
+        assert_eq!(rtttl("name:d=0:c"), error_at(7, ErrorKind::BadNumber));
+        assert_eq!(
+            rtttl("name:o=9:c"),
+            error_at(7, ErrorKind::OctaveOutOfRange)
+        );
+        assert_eq!(rtttl("name:b=0:c"), error_at(7, ErrorKind::BadNumber));
+    }
+
+    #[test]
+    fn rtttl_notes() {
+        // The dot can go either side of the octave
+        let events = rtttl("x:d=4,o=5:c.6, c6., 16c#, 8p, h").unwrap();
+        assert_eq!(
+            events,
+            [
+                Event::note(Note::C6, Length::QUARTER.dotted()),
+                Event::note(Note::C6, Length::QUARTER.dotted()),
+                Event::note(Note::CsDb5, Length::SIXTEENTH),
+                Event::rest(Length::EIGHTH),
+                Event::note(Note::B5, Length::QUARTER),
+            ]
+        );
+        assert_eq!(rtttl("x::c9"), error_at(4, ErrorKind::OctaveOutOfRange));
+        assert_eq!(rtttl("x::c,x"), error_at(5, ErrorKind::UnexpectedChar));
+        assert_eq!(rtttl("x::4"), error_at(4, ErrorKind::UnexpectedChar));
+    }
+}
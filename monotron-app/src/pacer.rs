@@ -0,0 +1,81 @@
+//! Keeps the emulator's `wfvbi` running at 60 Hz, like the real thing.
+//!
+//! Rather than sleeping for a frame after doing the work (which makes every
+//! frame too long), we work out when each frame is due against a monotonic
+//! clock and sleep until then. If we miss a deadline by a whole frame or
+//! more, those frames are dropped and we start counting again from now,
+//! rather than rushing through several frames to catch up.
+
+use std::time::{Duration, Instant};
+
+const FRAMES_PER_SECOND: u64 = 60;
+
+/// How often we complain about dropped frames.
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct Pacer {
+    /// When we started counting frames, or `None` before the first frame.
+    start: Option<Instant>,
+    /// How many frames have been due since `start`.
+    frames: u64,
+    /// How many frames we've dropped in total.
+    dropped: u64,
+    /// How many frames we've dropped since we last said so.
+    unreported: u64,
+    last_report: Instant,
+}
+
+impl Pacer {
+    pub fn new() -> Pacer {
+        Pacer {
+            start: None,
+            frames: 0,
+            dropped: 0,
+            unreported: 0,
+            last_report: Instant::now(),
+        }
+    }
+
+    /// Sleep until the next frame is due.
+    pub fn wait(&mut self) {
+        let now = Instant::now();
+        let start = match self.start {
+            Some(start) => start,
+            None => {
+                // Don't count the time before the first frame against us
+                self.start = Some(now);
+                return;
+            }
+        };
+        self.frames += 1;
+        let deadline = start + frame_time(self.frames);
+        if now < deadline {
+            std::thread::sleep(deadline - now);
+        } else {
+            let late = now - deadline;
+            let missed = late.as_nanos() as u64 * FRAMES_PER_SECOND / 1_000_000_000;
+            if missed > 0 {
+                self.dropped += missed;
+                self.unreported += missed;
+                self.start = Some(now);
+                self.frames = 0;
+            }
+        }
+        if self.unreported > 0 && self.last_report.elapsed() >= REPORT_INTERVAL {
+            println!("Running slow - dropped {} frame(s)", self.unreported);
+            self.unreported = 0;
+            self.last_report = Instant::now();
+        }
+    }
+
+    /// How many frames we've dropped since we started.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+/// How long the given number of frames lasts. Worked out from scratch each
+/// time, so rounding errors don't add up.
+fn frame_time(frames: u64) -> Duration {
+    Duration::from_nanos(frames * 1_000_000_000 / FRAMES_PER_SECOND)
+}
@@ -0,0 +1,160 @@
+//! Reads input scripts, so the emulator can drive an application without a
+//! keyboard (e.g. when testing in headless mode).
+//!
+//! Each line is `<frame> <command> [<argument>]`, where `<frame>` is the
+//! number of `wfvbi` calls to wait before the input arrives. Blank lines, and
+//! lines starting with `#`, are ignored. The commands are:
+//!
+//! * `key <n>` - the byte `n` (in decimal) arrives for `readc`
+//! * `text <string>` - each byte of the rest of the line arrives for `readc`
+//! * `joystick <n>` - the joystick state becomes `n` (in decimal), until the
+//!   next `joystick` command
+//! * `keydown <code> <modifiers>` - the key with `KeyCode` `code` goes down,
+//!   with the given `Modifiers` bits, for `read_key_event`
+//! * `keyup <code> <modifiers>` - the same, but the key comes back up
+//! * `quit` - print the screen as text, and exit
+//!
+//! A `Recorder` writes scripts in the same format, so a session can be
+//! replayed frame by frame.
+
+use super::{InputEvent, JoystickState, KeyCode, Modifiers};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Load a script from a file. The events come back in frame order.
+pub fn load(path: &Path) -> Result<Vec<(u64, InputEvent)>, String> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut events = Vec::new();
+    for (idx, line) in contents.lines().enumerate() {
+        parse_line(line, &mut events)
+            .map_err(|e| format!("{}:{}: {}", path.display(), idx + 1, e))?;
+    }
+    // Stable, so events on the same frame stay in the order they were written
+    events.sort_by_key(|&(frame, _)| frame);
+    Ok(events)
+}
+
+fn parse_line(line: &str, events: &mut Vec<(u64, InputEvent)>) -> Result<(), String> {
+    let line = line.trim_start();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(());
+    }
+    let mut parts = line.splitn(3, ' ');
+    let frame = parts.next().unwrap_or("");
+    let frame: u64 = frame
+        .parse()
+        .map_err(|_| format!("bad frame number {:?}", frame))?;
+    let command = parts.next().unwrap_or("");
+    let argument = parts.next().unwrap_or("");
+    match command {
+        "key" => {
+            let ch = argument
+                .trim()
+                .parse()
+                .map_err(|_| format!("bad key {:?}", argument))?;
+            events.push((frame, InputEvent::Key(ch)));
+        }
+        "text" => {
+            for ch in argument.bytes() {
+                events.push((frame, InputEvent::Key(ch)));
+            }
+        }
+        "joystick" => {
+            let bits = argument
+                .trim()
+                .parse()
+                .map_err(|_| format!("bad joystick state {:?}", argument))?;
+            events.push((frame, InputEvent::Joystick(JoystickState(bits))));
+        }
+        "keydown" => {
+            let (code, modifiers) = parse_key_event(argument)?;
+            events.push((frame, InputEvent::KeyDown(code, modifiers)));
+        }
+        "keyup" => {
+            let (code, modifiers) = parse_key_event(argument)?;
+            events.push((frame, InputEvent::KeyUp(code, modifiers)));
+        }
+        "quit" => {
+            events.push((frame, InputEvent::Quit));
+        }
+        _ => return Err(format!("unknown command {:?}", command)),
+    }
+    Ok(())
+}
+
+/// Parse the `<code> <modifiers>` after `keydown` or `keyup`.
+fn parse_key_event(argument: &str) -> Result<(KeyCode, Modifiers), String> {
+    let mut parts = argument.split_whitespace();
+    let code = parts
+        .next()
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| format!("bad key code {:?}", argument))?;
+    let modifiers = match parts.next().map(str::parse::<u8>) {
+        Some(Ok(bits)) if bits <= 0b111 => bits,
+        _ => return Err(format!("bad modifiers {:?}", argument)),
+    };
+    if parts.next().is_some() {
+        return Err(format!("bad key event {:?}", argument));
+    }
+    Ok((KeyCode(code), Modifiers(modifiers)))
+}
+
+/// Writes input to a script as it happens.
+pub struct Recorder {
+    // Not buffered, so nothing is lost if the application calls `exit`
+    file: File,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> io::Result<Recorder> {
+        let mut file = File::create(path)?;
+        writeln!(file, "# Monotron input, recorded by monotron-app")?;
+        Ok(Recorder { file })
+    }
+
+    pub fn record(&mut self, frame: u64, event: InputEvent) -> io::Result<()> {
+        match event {
+            InputEvent::Key(ch) => writeln!(self.file, "{} key {}", frame, ch),
+            InputEvent::Joystick(state) => writeln!(self.file, "{} joystick {}", frame, state.0),
+            InputEvent::KeyDown(code, modifiers) => {
+                writeln!(self.file, "{} keydown {} {}", frame, code.0, modifiers.0)
+            }
+            InputEvent::KeyUp(code, modifiers) => {
+                writeln!(self.file, "{} keyup {} {}", frame, code.0, modifiers.0)
+            }
+            InputEvent::Quit => writeln!(self.file, "{} quit", frame),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Vec<(u64, InputEvent)>, String> {
+        let mut events = Vec::new();
+        parse_line(line, &mut events)?;
+        Ok(events)
+    }
+
+    #[test]
+    fn key_events() {
+        let events = parse("30 keydown 256 1").unwrap();
+        assert!(matches!(
+            events[..],
+            [(30, InputEvent::KeyDown(KeyCode::UP, Modifiers(1)))]
+        ));
+        let events = parse("40 keyup 97 0").unwrap();
+        assert!(matches!(
+            events[..],
+            [(40, InputEvent::KeyUp(KeyCode(97), Modifiers(0)))]
+        ));
+        assert!(parse("1 keydown").is_err());
+        assert!(parse("1 keydown 256").is_err());
+        assert!(parse("1 keydown 256 8").is_err());
+        assert!(parse("1 keyup 256 1 2").is_err());
+        assert!(parse("1 keyup up 1").is_err());
+    }
+}
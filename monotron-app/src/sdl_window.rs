@@ -1,16 +1,238 @@
+use super::{
+    capture, clock, pacer, script, Channel, Frequency, InputEvent, KeyCode, KeyEvent, Modifiers,
+    MouseState, Waveform,
+};
 use sdl2;
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::keyboard::Keycode;
 use std::collections::VecDeque;
 use vga_framebuffer;
 
+/// The size of the SDL window, and of the frame we render.
+pub const WIDTH: usize = 800;
+pub const HEIGHT: usize = 600;
+
+/// Where the 48x36 text area sits in the frame. Each cell is 8 Monotron
+/// pixels across and 16 scan-lines down, and each Monotron pixel is two
+/// pixels wide.
+const TEXT_LEFT: i32 = (WIDTH as i32 - 48 * 8 * 2) / 2;
+const TEXT_TOP: i32 = (HEIGHT as i32 - 36 * 16) / 2;
+
+/// The Monotron produces one audio sample per scan-line, and an 800x600
+/// frame has 628 scan-lines at 60 Hz.
+const SAMPLE_RATE: i32 = 628 * 60;
+
+/// Bits in the joystick state byte, as decoded by `JoystickState`.
+const JOYSTICK_UP: u8 = 0b10000;
+const JOYSTICK_DOWN: u8 = 0b01000;
+const JOYSTICK_LEFT: u8 = 0b00100;
+const JOYSTICK_RIGHT: u8 = 0b00010;
+const JOYSTICK_FIRE: u8 = 0b00001;
+
+/// How far an analogue stick has to move before we count it as a direction.
+const AXIS_DEAD_ZONE: i16 = 16384;
+
+/// How many key events we keep for `read_key_event`, so apps which never
+/// call it don't fill up memory.
+const MAX_KEY_EVENTS: usize = 256;
+
+/// Turn a key which doesn't produce text (or whose text we don't want) into
+/// bytes for `readc`. The escape sequences are the xterm/VT220 ones, which
+/// we picked for the emulator as most terminal code already knows them:
+///
+/// * Return - `\r`; Backspace - 8; Tab - 9; Escape - 27; Delete - 127
+/// * Ctrl+A to Ctrl+Z - 1 to 26 (so Ctrl+C is 3)
+/// * Cursor keys - `ESC [ A` (up), `ESC [ B` (down), `ESC [ C` (right) and
+///   `ESC [ D` (left)
+/// * Home, Insert, End, Page Up and Page Down - `ESC [ 1 ~` to `ESC [ 6 ~`
+/// * F1 to F4 - `ESC O P` to `ESC O S`
+/// * F5 to F9 - `ESC [ 15 ~`, `ESC [ 17 ~` to `ESC [ 20 ~`
+///
+/// F10, F11 and F12 are kept for the emulator itself.
+fn translate_key(keycode: Keycode, keymod: sdl2::keyboard::Mod) -> Option<Vec<u8>> {
+    use sdl2::keyboard::Mod;
+    let bytes: &[u8] = match keycode {
+        Keycode::Return | Keycode::KpEnter => b"\r",
+        Keycode::Backspace => b"\x08",
+        Keycode::Tab => b"\t",
+        Keycode::Escape => b"\x1b",
+        Keycode::Delete => b"\x7f",
+        Keycode::Up => b"\x1b[A",
+        Keycode::Down => b"\x1b[B",
+        Keycode::Right => b"\x1b[C",
+        Keycode::Left => b"\x1b[D",
+        Keycode::Home => b"\x1b[1~",
+        Keycode::Insert => b"\x1b[2~",
+        Keycode::End => b"\x1b[4~",
+        Keycode::PageUp => b"\x1b[5~",
+        Keycode::PageDown => b"\x1b[6~",
+        Keycode::F1 => b"\x1bOP",
+        Keycode::F2 => b"\x1bOQ",
+        Keycode::F3 => b"\x1bOR",
+        Keycode::F4 => b"\x1bOS",
+        Keycode::F5 => b"\x1b[15~",
+        Keycode::F6 => b"\x1b[17~",
+        Keycode::F7 => b"\x1b[18~",
+        Keycode::F8 => b"\x1b[19~",
+        Keycode::F9 => b"\x1b[20~",
+        _ => {
+            // Keycodes for letters are their lower-case ASCII values. Leave
+            // Ctrl+Alt alone, as that's AltGr on some keyboards.
+            let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+            let alt = keymod.intersects(Mod::LALTMOD | Mod::RALTMOD);
+            let code = keycode as i32;
+            if ctrl && !alt && code >= i32::from(b'a') && code <= i32::from(b'z') {
+                return Some(vec![code as u8 - b'a' + 1]);
+            }
+            return None;
+        }
+    };
+    Some(bytes.to_vec())
+}
+
+/// Turn an SDL key going down (but not auto-repeating) or up into a
+/// `KeyEvent`.
+fn key_event(event: &sdl2::event::Event) -> Option<KeyEvent> {
+    use sdl2::event::Event;
+    use sdl2::keyboard::Mod;
+    let (keycode, keymod, pressed) = match *event {
+        Event::KeyDown {
+            keycode: Some(keycode),
+            keymod,
+            repeat: false,
+            ..
+        } => (keycode, keymod, true),
+        Event::KeyUp {
+            keycode: Some(keycode),
+            keymod,
+            ..
+        } => (keycode, keymod, false),
+        _ => return None,
+    };
+    let code = match keycode {
+        Keycode::Up => KeyCode::UP,
+        Keycode::Down => KeyCode::DOWN,
+        Keycode::Left => KeyCode::LEFT,
+        Keycode::Right => KeyCode::RIGHT,
+        Keycode::Home => KeyCode::HOME,
+        Keycode::End => KeyCode::END,
+        Keycode::Insert => KeyCode::INSERT,
+        Keycode::PageUp => KeyCode::PAGE_UP,
+        Keycode::PageDown => KeyCode::PAGE_DOWN,
+        Keycode::LShift => KeyCode::LEFT_SHIFT,
+        Keycode::RShift => KeyCode::RIGHT_SHIFT,
+        Keycode::LCtrl => KeyCode::LEFT_CTRL,
+        Keycode::RCtrl => KeyCode::RIGHT_CTRL,
+        Keycode::LAlt => KeyCode::LEFT_ALT,
+        Keycode::RAlt => KeyCode::RIGHT_ALT,
+        Keycode::F1 => KeyCode::F1,
+        Keycode::F2 => KeyCode(KeyCode::F1.0 + 1),
+        Keycode::F3 => KeyCode(KeyCode::F1.0 + 2),
+        Keycode::F4 => KeyCode(KeyCode::F1.0 + 3),
+        Keycode::F5 => KeyCode(KeyCode::F1.0 + 4),
+        Keycode::F6 => KeyCode(KeyCode::F1.0 + 5),
+        Keycode::F7 => KeyCode(KeyCode::F1.0 + 6),
+        Keycode::F8 => KeyCode(KeyCode::F1.0 + 7),
+        Keycode::F9 => KeyCode(KeyCode::F1.0 + 8),
+        Keycode::F10 => KeyCode(KeyCode::F1.0 + 9),
+        Keycode::F11 => KeyCode(KeyCode::F1.0 + 10),
+        Keycode::F12 => KeyCode(KeyCode::F1.0 + 11),
+        Keycode::KpEnter => KeyCode(u16::from(b'\r')),
+        // SDL uses ASCII for the keys which type characters
+        _ if (keycode as i32) > 0 && (keycode as i32) < 0x80 => KeyCode(keycode as u16),
+        _ => return None,
+    };
+    let mut modifiers = 0;
+    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+        modifiers |= 0b001;
+    }
+    if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) {
+        modifiers |= 0b010;
+    }
+    if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) {
+        modifiers |= 0b100;
+    }
+    Some(KeyEvent {
+        code,
+        pressed,
+        modifiers: Modifiers(modifiers),
+    })
+}
+
+/// Which bit of `MouseState::buttons` an SDL mouse button sets.
+fn mouse_button_bit(button: sdl2::mouse::MouseButton) -> u8 {
+    use sdl2::mouse::MouseButton;
+    match button {
+        MouseButton::Left => 0b001,
+        MouseButton::Right => 0b010,
+        MouseButton::Middle => 0b100,
+        _ => 0,
+    }
+}
+
 pub struct Context<'a> {
     pub fb: vga_framebuffer::FrameBuffer<'a, FakeHardware>,
     pub keypresses: VecDeque<u8>,
+    pub key_events: VecDeque<KeyEvent>,
+    /// True if we have no window, and so no keyboard or sound.
+    headless: bool,
+    /// How many times `wfvbi` has been called.
+    frame: u64,
+    /// Input to feed in at the given frame numbers, in frame order.
+    script: VecDeque<(u64, InputEvent)>,
+    /// The joystick state most recently given by the script.
+    joystick_scripted: u8,
+    /// Where we're saving video to, if anywhere.
+    recorder: Option<capture::Recorder>,
+    /// Where we're saving keyboard and joystick input to, if anywhere.
+    input_recorder: Option<script::Recorder>,
+    /// The joystick state we last recorded.
+    joystick_recorded: u8,
+    /// Makes `gettime` repeatable, if set.
+    clock: Option<clock::FakeClock>,
+    /// Keeps us at 60 Hz. Headless runs go as fast as they can, so they
+    /// don't have one.
+    pacer: Option<pacer::Pacer>,
+    audio: Option<sdl2::audio::AudioDevice<Synth>>,
+    game_controller: Option<sdl2::GameControllerSubsystem>,
+    controllers: Vec<GameController>,
+    joystick_keys: JoystickKeys,
+    /// Joystick bits for the keys currently held down.
+    joystick_held: u8,
+    /// Set when a joystick key is pressed, so we can drop the text it makes.
+    swallow_text: bool,
+    /// Where the mouse pointer is in the frame.
+    mouse_x: i32,
+    mouse_y: i32,
+    /// Mouse buttons currently held down, as in `MouseState::buttons`.
+    mouse_buttons: u8,
+}
+
+/// The keys which stand in for the Atari joystick.
+struct JoystickKeys {
+    up: Keycode,
+    down: Keycode,
+    left: Keycode,
+    right: Keycode,
+    fire: Keycode,
 }
 
 pub struct FakeHardware {
+    /// The SDL window, or `None` if we're running headless.
+    pub window: Option<Window>,
+    /// The frame as RGB24, `WIDTH` x `HEIGHT`. Monotron pixels are twice as
+    /// wide as they are high, so each one covers two of these.
+    pub pixels: Vec<u8>,
+    /// Where the next Monotron pixel goes.
+    x: usize,
+    y: usize,
+}
+
+pub struct Window {
     pub event_pump: sdl2::EventPump,
     pub canvas: sdl2::render::Canvas<sdl2::video::Window>,
-    point: sdl2::rect::Point,
+    /// Gets a copy of `FakeHardware::pixels` once per frame.
+    pub texture: sdl2::render::Texture,
 }
 
 // We need to do this to put a Context in a static. But is this a good idea?
@@ -35,57 +257,537 @@ impl vga_framebuffer::Hardware for FakeHardware {
             let red_bit = xrgb.pixel_has_red(bit);
             let green_bit = xrgb.pixel_has_green(bit);
             let blue_bit = xrgb.pixel_has_blue(bit);
-            self.canvas.set_draw_color(sdl2::pixels::Color::RGB(
+            let rgb = [
                 if red_bit { 0xFF } else { 0x00 },
                 if green_bit { 0xFF } else { 0x00 },
                 if blue_bit { 0xFF } else { 0x00 },
-            ));
-            self.canvas
-                .draw_point(self.point.offset(self.point.x, 0))
-                .unwrap();
-            self.canvas
-                .draw_point(self.point.offset(self.point.x + 1, 0))
-                .unwrap();
-            let mut new_point = self.point.offset(1, 0);
-            if new_point.x == 400 {
-                new_point = self.point.offset(-self.point.x, 1);
-                if new_point.y == 600 {
-                    new_point = (0, 0).into();
+            ];
+            let offset = ((self.y * WIDTH) + (self.x * 2)) * 3;
+            self.pixels[offset..offset + 3].copy_from_slice(&rgb);
+            self.pixels[offset + 3..offset + 6].copy_from_slice(&rgb);
+            self.x += 1;
+            if self.x == WIDTH / 2 {
+                self.x = 0;
+                self.y += 1;
+                if self.y == HEIGHT {
+                    self.y = 0;
                 }
             }
-            self.point = new_point;
         }
     }
 }
 
+impl Default for JoystickKeys {
+    /// The numeric keypad, so we don't steal any keys the application might
+    /// want from `readc`.
+    fn default() -> JoystickKeys {
+        JoystickKeys {
+            up: Keycode::Kp8,
+            down: Keycode::Kp2,
+            left: Keycode::Kp4,
+            right: Keycode::Kp6,
+            fire: Keycode::Kp0,
+        }
+    }
+}
+
+impl JoystickKeys {
+    /// Reads `MONOTRON_JOYSTICK_KEYS`, which is a comma-separated list of SDL
+    /// key names in the order up, down, left, right, fire (e.g.
+    /// `Up,Down,Left,Right,Space`).
+    fn from_env() -> JoystickKeys {
+        let names = match std::env::var("MONOTRON_JOYSTICK_KEYS") {
+            Ok(names) => names,
+            Err(_) => return JoystickKeys::default(),
+        };
+        let keys: Vec<Option<Keycode>> = names
+            .split(',')
+            .map(|name| Keycode::from_name(name.trim()))
+            .collect();
+        match keys.as_slice() {
+            [Some(up), Some(down), Some(left), Some(right), Some(fire)] => JoystickKeys {
+                up: *up,
+                down: *down,
+                left: *left,
+                right: *right,
+                fire: *fire,
+            },
+            _ => {
+                println!(
+                    "Bad MONOTRON_JOYSTICK_KEYS {:?} - using the numeric keypad",
+                    names
+                );
+                JoystickKeys::default()
+            }
+        }
+    }
+
+    /// Which joystick bit, if any, this key controls.
+    fn bit_for(&self, keycode: Keycode) -> u8 {
+        if keycode == self.up {
+            JOYSTICK_UP
+        } else if keycode == self.down {
+            JOYSTICK_DOWN
+        } else if keycode == self.left {
+            JOYSTICK_LEFT
+        } else if keycode == self.right {
+            JOYSTICK_RIGHT
+        } else if keycode == self.fire {
+            JOYSTICK_FIRE
+        } else {
+            0
+        }
+    }
+}
+
+/// One channel on the synthesiser.
+#[derive(Debug, Copy, Clone)]
+struct Voice {
+    /// Where we are in the waveform. The top 8 bits index the wave table.
+    phase: u32,
+    /// How far `phase` moves on each sample.
+    step: u32,
+    waveform: Waveform,
+    volume: u8,
+}
+
+/// Emulates the three-channel wave-table synthesiser in the Monotron. It
+/// produces 8-bit unsigned mono samples, just like the real thing.
+pub struct Synth {
+    sample_rate: u32,
+    voices: [Voice; 3],
+    sine: [i8; 256],
+    noise: [i8; 256],
+}
+
+impl Synth {
+    fn new(sample_rate: i32) -> Synth {
+        let mut sine = [0i8; 256];
+        for (idx, sample) in sine.iter_mut().enumerate() {
+            let angle = (idx as f32 / 256.0) * 2.0 * std::f32::consts::PI;
+            *sample = (angle.sin() * 127.0) as i8;
+        }
+        // A 16-bit Galois LFSR gives us a repeatable block of noise
+        let mut noise = [0i8; 256];
+        let mut lfsr: u16 = 0xACE1;
+        for sample in noise.iter_mut() {
+            let lsb = lfsr & 1;
+            lfsr >>= 1;
+            if lsb != 0 {
+                lfsr ^= 0xB400;
+            }
+            *sample = lfsr as i8;
+        }
+        let silent = Voice {
+            phase: 0,
+            step: 0,
+            waveform: Waveform::Square,
+            volume: 0,
+        };
+        Synth {
+            sample_rate: sample_rate as u32,
+            voices: [silent; 3],
+            sine,
+            noise,
+        }
+    }
+
+    /// Change the note on a channel. The phase carries on from where it was,
+    /// so changing note doesn't click.
+    pub fn play(&mut self, frequency: Frequency, channel: Channel, waveform: Waveform, volume: u8) {
+        let voice = &mut self.voices[channel as usize];
+        voice.step =
+            (((frequency.as_centi_hz() as u64) << 32) / (100 * self.sample_rate as u64)) as u32;
+        voice.waveform = waveform;
+        voice.volume = volume;
+    }
+
+    fn next_sample(&mut self) -> u8 {
+        let mut total: i32 = 0;
+        for voice in self.voices.iter_mut() {
+            if voice.step == 0 || voice.volume == 0 {
+                continue;
+            }
+            let idx = (voice.phase >> 24) as u8;
+            let sample = match voice.waveform {
+                Waveform::Square => {
+                    if idx < 128 {
+                        127
+                    } else {
+                        -128
+                    }
+                }
+                Waveform::Sine => self.sine[idx as usize],
+                Waveform::Sawtooth => idx.wrapping_sub(128) as i8,
+                Waveform::Noise => self.noise[idx as usize],
+            };
+            total += (sample as i32 * voice.volume as i32) / 255;
+            voice.phase = voice.phase.wrapping_add(voice.step);
+        }
+        ((total / 3) + 128) as u8
+    }
+}
+
+impl sdl2::audio::AudioCallback for Synth {
+    type Channel = u8;
+
+    fn callback(&mut self, out: &mut [u8]) {
+        for sample in out.iter_mut() {
+            *sample = self.next_sample();
+        }
+    }
+}
+
+/// Headless mode is selected with the `headless` feature, or by setting
+/// `MONOTRON_HEADLESS`.
+fn is_headless() -> bool {
+    cfg!(feature = "headless") || std::env::var_os("MONOTRON_HEADLESS").is_some()
+}
+
 impl<'a> Context<'a> {
     pub fn new() -> Context<'a> {
-        let sdl_context = sdl2::init().unwrap();
-        let video_subsystem = sdl_context.video().unwrap();
-        let window = video_subsystem
-            .window("Monotron", 800, 600)
-            .position_centered()
-            .build()
-            .unwrap();
-        let mut canvas = window.into_canvas().build().unwrap();
-        canvas.set_logical_size(800, 600).unwrap();
-        let event_pump = sdl_context.event_pump().unwrap();
+        let headless = is_headless();
+        let (window, audio, game_controller) = if headless {
+            (None, None, None)
+        } else {
+            let sdl_context = sdl2::init().unwrap();
+            let video_subsystem = sdl_context.video().unwrap();
+            let window = video_subsystem
+                .window("Monotron", WIDTH as u32, HEIGHT as u32)
+                .position_centered()
+                .build()
+                .unwrap();
+            let mut canvas = window.into_canvas().build().unwrap();
+            canvas
+                .set_logical_size(WIDTH as u32, HEIGHT as u32)
+                .unwrap();
+            // We draw each frame into `pixels` and then upload it in one go,
+            // as drawing point by point is far too slow.
+            let texture = canvas
+                .texture_creator()
+                .create_texture_streaming(
+                    sdl2::pixels::PixelFormatEnum::RGB24,
+                    WIDTH as u32,
+                    HEIGHT as u32,
+                )
+                .unwrap();
+            let event_pump = sdl_context.event_pump().unwrap();
+            let audio = Self::open_audio(&sdl_context);
+            // Controllers get opened as their `ControllerDeviceAdded` events arrive
+            let game_controller = match sdl_context.game_controller() {
+                Ok(subsystem) => Some(subsystem),
+                Err(e) => {
+                    println!("Failed to start game controller support ({})", e);
+                    None
+                }
+            };
+            let window = Window {
+                canvas,
+                event_pump,
+                texture,
+            };
+            (Some(window), audio, game_controller)
+        };
+        let script = match std::env::var_os("MONOTRON_INPUT_SCRIPT") {
+            Some(path) => match script::load(std::path::Path::new(&path)) {
+                Ok(script) => script,
+                Err(e) => {
+                    // The error says which file, and where in it
+                    println!("Failed to load MONOTRON_INPUT_SCRIPT ({})", e);
+                    std::process::exit(1)
+                }
+            },
+            None => Vec::new(),
+        };
+        let input_recorder = std::env::var_os("MONOTRON_RECORD_INPUT").map(|path| {
+            let path = std::path::Path::new(&path);
+            match script::Recorder::create(path) {
+                Ok(recorder) => recorder,
+                Err(e) => {
+                    println!("Failed to record input to {} ({})", path.display(), e);
+                    std::process::exit(1)
+                }
+            }
+        });
+        // Apps don't see our arguments, so prefix them to avoid clashes
+        let record_path = std::env::args()
+            .find(|arg| arg.starts_with("--monotron-record="))
+            .map(|arg| arg["--monotron-record=".len()..].to_owned());
         let d = FakeHardware {
-            canvas,
-            event_pump,
-            point: (0, 0).into(),
+            window,
+            pixels: vec![0; WIDTH * HEIGHT * 3],
+            x: 0,
+            y: 0,
         };
         let mut fb = vga_framebuffer::FrameBuffer::new();
         fb.init(d);
-        Context {
+        let mut ctx = Context {
             fb,
             keypresses: VecDeque::new(),
+            key_events: VecDeque::new(),
+            headless,
+            frame: 0,
+            script: script.into_iter().collect(),
+            joystick_scripted: 0,
+            recorder: None,
+            input_recorder,
+            joystick_recorded: 0,
+            clock: clock::FakeClock::from_env(),
+            pacer: if headless {
+                None
+            } else {
+                Some(pacer::Pacer::new())
+            },
+            audio,
+            game_controller,
+            controllers: Vec::new(),
+            joystick_keys: JoystickKeys::from_env(),
+            joystick_held: 0,
+            swallow_text: false,
+            mouse_x: 0,
+            mouse_y: 0,
+            mouse_buttons: 0,
+        };
+        if let Some(path) = record_path {
+            ctx.start_recording(std::path::Path::new(&path));
+        }
+        ctx
+    }
+
+    fn open_audio(sdl_context: &sdl2::Sdl) -> Option<sdl2::audio::AudioDevice<Synth>> {
+        let desired_spec = sdl2::audio::AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: None,
+        };
+        let device = sdl_context.audio().and_then(|audio_subsystem| {
+            audio_subsystem.open_playback(None, &desired_spec, |spec| Synth::new(spec.freq))
+        });
+        match device {
+            Ok(device) => {
+                device.resume();
+                Some(device)
+            }
+            Err(e) => {
+                println!("Failed to open audio device ({}) - sound is disabled", e);
+                None
+            }
         }
     }
 
+    pub fn play(&mut self, frequency: Frequency, channel: Channel, waveform: Waveform, volume: u8) {
+        if let Some(ref mut device) = self.audio {
+            device.lock().play(frequency, channel, waveform, volume);
+        }
+    }
+
+    /// Get the emulated joystick state, combining the joystick keys, any
+    /// game controllers that are plugged in and the input script.
+    pub fn joystick(&self) -> u8 {
+        self.live_joystick() | self.joystick_scripted
+    }
+
+    /// Get the joystick state from the keyboard and game controllers.
+    fn live_joystick(&self) -> u8 {
+        let mut bits = self.joystick_held;
+        for pad in &self.controllers {
+            if pad.button(Button::DPadUp) || pad.axis(Axis::LeftY) < -AXIS_DEAD_ZONE {
+                bits |= JOYSTICK_UP;
+            }
+            if pad.button(Button::DPadDown) || pad.axis(Axis::LeftY) > AXIS_DEAD_ZONE {
+                bits |= JOYSTICK_DOWN;
+            }
+            if pad.button(Button::DPadLeft) || pad.axis(Axis::LeftX) < -AXIS_DEAD_ZONE {
+                bits |= JOYSTICK_LEFT;
+            }
+            if pad.button(Button::DPadRight) || pad.axis(Axis::LeftX) > AXIS_DEAD_ZONE {
+                bits |= JOYSTICK_RIGHT;
+            }
+            if pad.button(Button::A) {
+                bits |= JOYSTICK_FIRE;
+            }
+        }
+        bits
+    }
+
+    /// Add some input to the script, to arrive on the given frame.
+    pub fn script_input(&mut self, frame: u64, event: InputEvent) {
+        let idx = self
+            .script
+            .iter()
+            .position(|&(when, _)| when > frame)
+            .unwrap_or(self.script.len());
+        self.script.insert(idx, (frame, event));
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.frame
+    }
+
+    /// Get the mouse state, or `None` if there's no window for it to be in.
+    pub fn mouse(&self) -> Option<MouseState> {
+        if self.headless {
+            return None;
+        }
+        // Keep to the text area, even if the pointer is in the border
+        let x = ((self.mouse_x - TEXT_LEFT) / 2).clamp(0, 48 * 8 - 1);
+        let y = (self.mouse_y - TEXT_TOP).clamp(0, 36 * 16 - 1);
+        Some(MouseState {
+            row: (y / 16) as u8,
+            col: (x / 8) as u8,
+            x: x as u16,
+            y: y as u16,
+            buttons: self.mouse_buttons,
+        })
+    }
+
+    /// Sleep until the next frame is due.
+    pub fn wait_for_frame(&mut self) {
+        if let Some(ref mut pacer) = self.pacer {
+            pacer.wait();
+        }
+    }
+
+    pub fn dropped_frames(&self) -> u64 {
+        self.pacer.as_ref().map(pacer::Pacer::dropped).unwrap_or(0)
+    }
+
+    /// The time on the fake clock, or `None` if we should use the real time.
+    pub fn fake_time(&self) -> Option<chrono::NaiveDateTime> {
+        self.clock.as_ref().map(|clock| clock.time_at(self.frame))
+    }
+
+    /// True if `readc` would wait forever, because we're headless and the
+    /// script has run out.
+    pub fn input_exhausted(&self) -> bool {
+        self.headless && self.keypresses.is_empty() && self.script.is_empty()
+    }
+
+    /// Read back every cell on screen, as (glyph, attribute), row by row.
+    pub fn screen_cells(&mut self) -> Vec<(u8, u8)> {
+        let mut cells = Vec::new();
+        for row in 0..=vga_framebuffer::TEXT_MAX_ROW {
+            for col in 0..=vga_framebuffer::TEXT_MAX_COL {
+                let p = vga_framebuffer::Position::new(
+                    vga_framebuffer::Row(row as u8),
+                    vga_framebuffer::Col(col as u8),
+                );
+                cells.push(match self.fb.read_glyph_at(p) {
+                    Some((glyph, attr)) => (glyph as u8, attr.as_u8()),
+                    None => (0, 0),
+                });
+            }
+        }
+        cells
+    }
+
+    /// Read back the screen as text, one line per row. Anything that isn't
+    /// printable ASCII comes back as `?`.
+    pub fn screen_text(&mut self) -> String {
+        let mut text = String::new();
+        for (idx, &(glyph, _attr)) in self.screen_cells().iter().enumerate() {
+            text.push(if glyph == b' ' || glyph.is_ascii_graphic() {
+                glyph as char
+            } else {
+                '?'
+            });
+            if idx % (vga_framebuffer::TEXT_MAX_COL + 1) == vga_framebuffer::TEXT_MAX_COL {
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    /// Save some input to the recording, if we're making one.
+    fn record_input(&mut self, event: InputEvent) {
+        if let Some(ref mut recorder) = self.input_recorder {
+            if let Err(e) = recorder.record(self.frame, event) {
+                println!("Failed to record input ({})", e);
+                self.input_recorder = None;
+            }
+        }
+    }
+
+    /// A key has been pressed on the keyboard.
+    fn key_pressed(&mut self, ch: u8) {
+        self.keypresses.push_back(ch);
+        self.record_input(InputEvent::Key(ch));
+    }
+
+    /// A key has gone down or come up, for `read_key_event`.
+    fn key_changed(&mut self, key_event: KeyEvent) {
+        if self.key_events.len() == MAX_KEY_EVENTS {
+            self.key_events.pop_front();
+        }
+        self.key_events.push_back(key_event);
+    }
+
+    /// Feed in any scripted input which is due.
+    fn run_script(&mut self) {
+        while let Some(&(frame, event)) = self.script.front() {
+            if frame > self.frame {
+                break;
+            }
+            self.script.pop_front();
+            match event {
+                InputEvent::Key(ch) => self.keypresses.push_back(ch),
+                InputEvent::Joystick(state) => self.joystick_scripted = state.0,
+                InputEvent::KeyDown(code, modifiers) => self.key_changed(KeyEvent {
+                    code,
+                    pressed: true,
+                    modifiers,
+                }),
+                InputEvent::KeyUp(code, modifiers) => self.key_changed(KeyEvent {
+                    code,
+                    pressed: false,
+                    modifiers,
+                }),
+                InputEvent::Quit => {
+                    self.stop_recording();
+                    print!("{}", self.screen_text());
+                    std::process::exit(0);
+                }
+            }
+        }
+    }
+
+    /// Save the most recently drawn frame to a file.
+    pub fn screenshot(&mut self, path: &std::path::Path) -> std::io::Result<()> {
+        let pixels = &self.fb.borrow_hw_mut().unwrap().pixels;
+        capture::save_screenshot(pixels, path)
+    }
+
+    pub fn start_recording(&mut self, path: &std::path::Path) {
+        self.stop_recording();
+        match capture::Recorder::start(path) {
+            Ok(recorder) => {
+                println!("Recording to {}", path.display());
+                self.recorder = Some(recorder);
+            }
+            Err(e) => println!("Failed to record to {} ({})", path.display(), e),
+        }
+    }
+
+    pub fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            let path = recorder.path().to_owned();
+            match recorder.finish() {
+                Ok(frames) => println!("Saved {} frames to {}", frames, path.display()),
+                Err(e) => println!("Failed to save {} ({})", path.display(), e),
+            }
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
     pub fn toggle_fullscreen(&mut self) {
         use sdl2::video::FullscreenType;
-        let ref mut canvas = self.fb.borrow_hw_mut().unwrap().canvas;
+        let canvas = match self.fb.borrow_hw_mut().unwrap().window {
+            Some(ref mut window) => &mut window.canvas,
+            None => return,
+        };
         canvas.set_draw_color(sdl2::pixels::Color::RGB(0, 0, 0));
         canvas.clear();
         if canvas.window_mut().fullscreen_state() == FullscreenType::Off {
@@ -105,16 +807,63 @@ impl<'a> Context<'a> {
         for _r in 0..628 {
             self.fb.isr_sol();
         }
-        self.fb.borrow_hw_mut().unwrap().canvas.present();
+        if let Some(ref mut recorder) = self.recorder {
+            let pixels = &self.fb.borrow_hw_mut().unwrap().pixels;
+            if let Err(e) = recorder.add_frame(pixels) {
+                println!("Failed to record frame ({})", e);
+                self.stop_recording();
+            }
+        }
+        let hw = self.fb.borrow_hw_mut().unwrap();
+        if let Some(ref mut window) = hw.window {
+            window.texture.update(None, &hw.pixels, WIDTH * 3).unwrap();
+            window.canvas.copy(&window.texture, None, None).unwrap();
+            window.canvas.present();
+        }
     }
 
     pub fn pump(&mut self) {
         use sdl2::event::Event;
-        use sdl2::keyboard::Keycode;
         let mut need_toggle = false;
-        for event in self.fb.borrow_hw_mut().unwrap().event_pump.poll_iter() {
+        let mut need_screenshot = false;
+        let mut need_record_toggle = false;
+        self.frame += 1;
+        self.run_script();
+        let events: Vec<Event> = match self.fb.borrow_hw_mut().unwrap().window {
+            Some(ref mut window) => window.event_pump.poll_iter().collect(),
+            None => Vec::new(),
+        };
+        for event in events {
+            if let Event::KeyDown { .. } = event {
+                self.swallow_text = false;
+            }
+            if let Some(key_event) = key_event(&event) {
+                self.key_changed(key_event);
+                self.record_input(if key_event.pressed {
+                    InputEvent::KeyDown(key_event.code, key_event.modifiers)
+                } else {
+                    InputEvent::KeyUp(key_event.code, key_event.modifiers)
+                });
+            }
             match event {
-                Event::Quit { .. } => std::process::exit(1),
+                Event::Quit { .. } => {
+                    self.record_input(InputEvent::Quit);
+                    self.stop_recording();
+                    std::process::exit(1)
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } if self.joystick_keys.bit_for(keycode) != 0 => {
+                    self.joystick_held |= self.joystick_keys.bit_for(keycode);
+                    self.swallow_text = true;
+                }
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    self.joystick_held &= !self.joystick_keys.bit_for(keycode);
+                }
                 Event::KeyDown {
                     keycode: Some(Keycode::F11),
                     ..
@@ -122,34 +871,98 @@ impl<'a> Context<'a> {
                     need_toggle = true;
                 }
                 Event::KeyDown {
-                    keycode: Some(Keycode::Return),
+                    keycode: Some(Keycode::F12),
                     ..
                 } => {
-                    self.keypresses.push_back(b'\r');
+                    need_screenshot = true;
                 }
                 Event::KeyDown {
-                    keycode: Some(Keycode::Backspace),
+                    keycode: Some(Keycode::F10),
                     ..
                 } => {
-                    self.keypresses.push_back(8);
+                    need_record_toggle = true;
                 }
                 Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
+                    keycode: Some(keycode),
+                    keymod,
                     ..
                 } => {
-                    self.keypresses.push_back(27);
+                    if let Some(bytes) = translate_key(keycode, keymod) {
+                        for b in bytes {
+                            self.key_pressed(b);
+                        }
+                        // Some platforms send text for these too
+                        self.swallow_text = true;
+                    }
+                }
+                Event::TextInput { .. } if self.swallow_text => {
+                    // We've already dealt with this key, so drop its text
+                    self.swallow_text = false;
                 }
                 Event::TextInput { text, .. } => {
                     println!("Got {:?}", text);
                     for b in text.bytes() {
-                        self.keypresses.push_back(b);
+                        self.key_pressed(b);
+                    }
+                }
+                Event::ControllerDeviceAdded { which, .. } => {
+                    if let Some(ref subsystem) = self.game_controller {
+                        match subsystem.open(which) {
+                            Ok(pad) => {
+                                println!("Using {} as a joystick", pad.name());
+                                self.controllers.push(pad);
+                            }
+                            Err(e) => println!("Failed to open controller {} ({})", which, e),
+                        }
                     }
                 }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    self.controllers.retain(|pad| pad.instance_id() != which);
+                }
+                // SDL scales these to our logical size for us, even in
+                // full-screen mode
+                Event::MouseMotion { x, y, .. } => {
+                    self.mouse_x = x;
+                    self.mouse_y = y;
+                }
+                Event::MouseButtonDown {
+                    mouse_btn, x, y, ..
+                } => {
+                    self.mouse_x = x;
+                    self.mouse_y = y;
+                    self.mouse_buttons |= mouse_button_bit(mouse_btn);
+                }
+                Event::MouseButtonUp {
+                    mouse_btn, x, y, ..
+                } => {
+                    self.mouse_x = x;
+                    self.mouse_y = y;
+                    self.mouse_buttons &= !mouse_button_bit(mouse_btn);
+                }
                 _ => {}
             }
         }
+        let joystick = self.live_joystick();
+        if joystick != self.joystick_recorded {
+            self.joystick_recorded = joystick;
+            self.record_input(InputEvent::Joystick(super::JoystickState(joystick)));
+        }
         if need_toggle {
             self.toggle_fullscreen();
         }
+        if need_screenshot {
+            let path = capture::next_free_path("png");
+            match self.screenshot(&path) {
+                Ok(()) => println!("Saved {}", path.display()),
+                Err(e) => println!("Failed to save {} ({})", path.display(), e),
+            }
+        }
+        if need_record_toggle {
+            if self.is_recording() {
+                self.stop_recording();
+            } else {
+                self.start_recording(&capture::next_free_path("gif"));
+            }
+        }
     }
 }
@@ -0,0 +1,279 @@
+//! An off-screen copy of the text screen.
+//!
+//! Draw into a `TextBuffer` as often as you like, then call `flush` just
+//! before `Host::wfvbi`. Only the cells which differ from what the buffer
+//! last sent are written out, with as few cursor moves and colour changes as
+//! we can manage. That's a lot cheaper than redrawing the whole screen with
+//! `putchar` every frame.
+
+use super::{Attr, Col, Colour, Host, Row};
+
+/// The number of text columns.
+pub const NUM_COLS: usize = 48;
+
+/// The number of text rows.
+pub const NUM_ROWS: usize = 36;
+
+/// One character cell.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Cell {
+    /// The 8-bit glyph in the cell
+    pub glyph: u8,
+    /// The colours of the cell
+    pub attr: Attr,
+}
+
+/// A 48x36 grid of cells, plus a copy of what's on screen.
+///
+/// That's two grids of two-byte cells, so a buffer takes up about 6.9 KB of
+/// RAM - a good chunk of the 24 KB a real Monotron gives an application for
+/// its code and data. It's best kept in a `static` rather than on the stack.
+pub struct TextBuffer {
+    /// What we're drawing.
+    back: [[Cell; NUM_COLS]; NUM_ROWS],
+    /// What we think is on screen.
+    front: [[Cell; NUM_COLS]; NUM_ROWS],
+    /// Set if we don't know what's on screen, so must send every cell.
+    front_unknown: bool,
+    row: usize,
+    col: usize,
+    attr: Attr,
+}
+
+impl TextBuffer {
+    /// Make a buffer full of spaces in the given colours. We assume the
+    /// screen looks like that already (e.g. you've just called
+    /// `Host::clear_screen`), so nothing is written until you change
+    /// something. Call `invalidate` if that's not true.
+    pub fn new(attr: Attr) -> TextBuffer {
+        let blank = Cell { glyph: b' ', attr };
+        TextBuffer {
+            back: [[blank; NUM_COLS]; NUM_ROWS],
+            front: [[blank; NUM_COLS]; NUM_ROWS],
+            front_unknown: false,
+            row: 0,
+            col: 0,
+            attr,
+        }
+    }
+
+    /// Forget what's on screen, so the next `flush` writes every cell.
+    pub fn invalidate(&mut self) {
+        self.front_unknown = true;
+    }
+
+    /// Fill the buffer with spaces in the current colours, and move the
+    /// cursor to the top left.
+    pub fn clear(&mut self) {
+        let blank = Cell {
+            glyph: b' ',
+            attr: self.attr,
+        };
+        for row in self.back.iter_mut() {
+            for cell in row.iter_mut() {
+                *cell = blank;
+            }
+        }
+        self.row = 0;
+        self.col = 0;
+    }
+
+    /// Read a cell back. Returns `None` if it's off the screen.
+    pub fn get(&self, row: Row, col: Col) -> Option<Cell> {
+        self.back
+            .get(row.0 as usize)
+            .and_then(|cells| cells.get(col.0 as usize))
+            .cloned()
+    }
+
+    /// Change a cell. Cells off the screen are ignored, as are cells with a
+    /// control character (below 0x20) for a glyph, as the screen would act
+    /// on those rather than draw them.
+    pub fn set(&mut self, row: Row, col: Col, cell: Cell) {
+        if cell.glyph < 0x20 {
+            return;
+        }
+        if let Some(old) = self
+            .back
+            .get_mut(row.0 as usize)
+            .and_then(|cells| cells.get_mut(col.0 as usize))
+        {
+            *old = cell;
+        }
+    }
+
+    /// Move the cursor, which is where `putchar` and `write!` draw.
+    pub fn move_cursor(&mut self, row: Row, col: Col) {
+        self.row = (row.0 as usize).min(NUM_ROWS - 1);
+        self.col = (col.0 as usize).min(NUM_COLS - 1);
+    }
+
+    /// Set the colours `putchar` and `write!` draw in.
+    pub fn set_attr(&mut self, attr: Attr) {
+        self.attr = attr;
+    }
+
+    /// Set the foreground colour `putchar` and `write!` draw in.
+    pub fn set_foreground(&mut self, colour: Colour) {
+        self.attr = Attr::new(colour, self.attr.bg());
+    }
+
+    /// Set the background colour `putchar` and `write!` draw in.
+    pub fn set_background(&mut self, colour: Colour) {
+        self.attr = Attr::new(self.attr.fg(), colour);
+    }
+
+    /// Draw a glyph at the cursor, and move the cursor on. `\r` and `\n`
+    /// move the cursor instead of being drawn, and other control characters
+    /// (below 0x20, including Escape) are ignored. The buffer doesn't scroll,
+    /// so anything after the bottom right cell wraps around to the top left.
+    pub fn putchar(&mut self, glyph: u8) {
+        match glyph {
+            b'\r' => self.col = 0,
+            b'\n' => {
+                self.col = 0;
+                self.next_row();
+            }
+            0x00..=0x1F => {}
+            _ => {
+                self.back[self.row][self.col] = Cell {
+                    glyph,
+                    attr: self.attr,
+                };
+                self.col += 1;
+                if self.col == NUM_COLS {
+                    self.col = 0;
+                    self.next_row();
+                }
+            }
+        }
+    }
+
+    /// Draw some glyphs with `putchar`.
+    pub fn puts(&mut self, str8bit: &[u8]) {
+        for &glyph in str8bit {
+            self.putchar(glyph);
+        }
+    }
+
+    /// Send the cells which have changed to the screen.
+    ///
+    /// The bottom right cell is never sent, as writing to it makes the
+    /// screen scroll. The cursor and colours are left wherever the last
+    /// changed cell needed them.
+    pub fn flush(&mut self) {
+        // Where the screen's cursor is, and what colours it's using, if we
+        // know.
+        let mut cursor = None;
+        let mut attr: Option<Attr> = None;
+        for row in 0..NUM_ROWS {
+            for col in 0..NUM_COLS {
+                let cell = self.back[row][col];
+                let unchanged = !self.front_unknown && cell == self.front[row][col];
+                if unchanged || (row, col) == (NUM_ROWS - 1, NUM_COLS - 1) {
+                    continue;
+                }
+                if cursor != Some((row, col)) {
+                    Host::move_cursor(Row(row as u8), Col(col as u8));
+                }
+                if attr.map(|a| a.fg()) != Some(cell.attr.fg()) {
+                    Host::set_foreground(cell.attr.fg());
+                }
+                if attr.map(|a| a.bg()) != Some(cell.attr.bg()) {
+                    Host::set_background(cell.attr.bg());
+                }
+                attr = Some(cell.attr);
+                Host::putchar(cell.glyph);
+                self.front[row][col] = cell;
+                // The screen wraps at the end of a row, but we'd rather move
+                // the cursor ourselves.
+                cursor = if col + 1 < NUM_COLS {
+                    Some((row, col + 1))
+                } else {
+                    None
+                };
+            }
+        }
+        self.front_unknown = false;
+    }
+
+    fn next_row(&mut self) {
+        self.row = (self.row + 1) % NUM_ROWS;
+    }
+}
+
+impl core::fmt::Write for TextBuffer {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let codepage = Host::codepage();
+        for ch in s.chars() {
+            self.putchar(codepage.encode(ch));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(glyph: u8) -> Cell {
+        Cell {
+            glyph,
+            attr: Attr::new(Colour::White, Colour::Black),
+        }
+    }
+
+    #[test]
+    fn size() {
+        assert_eq!(core::mem::size_of::<Cell>(), 2);
+        let size = core::mem::size_of::<TextBuffer>();
+        assert!(size > 6900 && size < 7000, "{} bytes", size);
+    }
+
+    #[test]
+    fn putchar_wraps() {
+        let mut buffer = TextBuffer::new(Attr::new(Colour::White, Colour::Black));
+        buffer.move_cursor(Row(35), Col(46));
+        buffer.puts(b"ab\x1Bc\r\nd");
+        assert_eq!(buffer.get(Row(35), Col(46)), Some(cell(b'a')));
+        assert_eq!(buffer.get(Row(35), Col(47)), Some(cell(b'b')));
+        // Escape is dropped, and we wrap round to the top left
+        assert_eq!(buffer.get(Row(0), Col(0)), Some(cell(b'c')));
+        assert_eq!(buffer.get(Row(1), Col(0)), Some(cell(b'd')));
+        assert_eq!(buffer.get(Row(36), Col(0)), None);
+    }
+
+    #[test]
+    fn flush() {
+        let mut buffer = TextBuffer::new(Attr::new(Colour::White, Colour::Black));
+        buffer.set(Row(3), Col(4), cell(b'x'));
+        buffer.set(Row(3), Col(5), cell(0x07));
+        assert_eq!(buffer.front[3][4], cell(b' '));
+        buffer.flush();
+        assert_eq!(buffer.front, buffer.back);
+        assert_eq!(buffer.front[3][4], cell(b'x'));
+        assert_eq!(buffer.front[3][5], cell(b' '));
+
+        buffer.invalidate();
+        buffer.clear();
+        buffer.flush();
+        assert!(!buffer.front_unknown);
+        assert_eq!(buffer.front, buffer.back);
+    }
+
+    #[test]
+    fn bottom_right_cell_is_never_sent() {
+        let mut buffer = TextBuffer::new(Attr::new(Colour::White, Colour::Black));
+        buffer.set(Row(35), Col(46), cell(b'y'));
+        buffer.set(Row(35), Col(47), cell(b'z'));
+        buffer.flush();
+        assert_eq!(buffer.front[35][46], cell(b'y'));
+        assert_eq!(buffer.front[35][47], cell(b' '));
+        assert_eq!(buffer.get(Row(35), Col(47)), Some(cell(b'z')));
+
+        // Not even when we've forgotten what's on screen
+        buffer.invalidate();
+        buffer.flush();
+        assert_eq!(buffer.front[35][47], cell(b' '));
+    }
+}
@@ -0,0 +1,970 @@
+//! Windows, menus and other widgets for the text screen.
+//!
+//! Everything is drawn with the box-drawing glyphs from Code Page 850, so
+//! keep `Font::Normal` selected. Pop-ups (`menu` and `message_box`) save the
+//! cells underneath them with `Host::read_char_at` and put them back when
+//! they close. So that doesn't take too much RAM, a pop-up can cover at most
+//! `MAX_POPUP_CELLS` cells: long menus scroll, and long messages are cut off.
+//!
+//! Widgets which take keys implement `Widget`, so a `Form` can move the focus
+//! between them. Tab, Return and the cursor keys move to the next widget, and
+//! the cursor keys move back, unless the widget has a use for them.
+
+use super::line_editor::LineEditor;
+use super::text_buffer::{Cell, NUM_COLS, NUM_ROWS};
+use super::{Attr, Col, Colour, Host, KeyCode, KeyDecoder, Row};
+
+use core::iter::{once, repeat};
+
+/// The most cells a pop-up (`menu` or `message_box`) can cover, as that's
+/// how many a `Backing` can save. Pop-ups which would be bigger are made
+/// shorter.
+pub const MAX_POPUP_CELLS: usize = NUM_COLS * 12;
+
+const TAB: KeyCode = KeyCode(0x09);
+const RETURN: KeyCode = KeyCode(0x0D);
+const ESCAPE: KeyCode = KeyCode(0x1B);
+const SPACE: KeyCode = KeyCode(0x20);
+
+/// A block of cells on screen.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Rect {
+    /// The top row
+    pub row: u8,
+    /// The left column
+    pub col: u8,
+    /// How many columns across
+    pub width: u8,
+    /// How many rows down
+    pub height: u8,
+}
+
+impl Rect {
+    /// Make a rectangle.
+    pub fn new(row: u8, col: u8, width: u8, height: u8) -> Rect {
+        Rect {
+            row,
+            col,
+            width,
+            height,
+        }
+    }
+
+    /// Make a rectangle in the middle of the screen.
+    pub fn centred(width: u8, height: u8) -> Rect {
+        let width = width.min(NUM_COLS as u8);
+        let height = height.min(NUM_ROWS as u8);
+        Rect::new(
+            (NUM_ROWS as u8 - height) / 2,
+            (NUM_COLS as u8 - width) / 2,
+            width,
+            height,
+        )
+    }
+
+    /// The rectangle inside this one's border.
+    pub fn inner(&self) -> Rect {
+        Rect::new(
+            self.row.saturating_add(1),
+            self.col.saturating_add(1),
+            self.width.saturating_sub(2),
+            self.height.saturating_sub(2),
+        )
+    }
+
+    /// Move the rectangle up and left (and shrink it, if it's bigger than
+    /// the screen) so all of it is on screen.
+    pub fn fit_on_screen(&self) -> Rect {
+        let width = self.width.min(NUM_COLS as u8);
+        let height = self.height.min(NUM_ROWS as u8);
+        Rect::new(
+            self.row.min(NUM_ROWS as u8 - height),
+            self.col.min(NUM_COLS as u8 - width),
+            width,
+            height,
+        )
+    }
+}
+
+/// The lines drawn around a `Window`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Border {
+    /// `┌─┐`
+    Single,
+    /// `╔═╗`
+    Double,
+}
+
+impl Border {
+    /// The corners (top left, top right, bottom left, bottom right), the
+    /// horizontal and vertical lines, and the tees a separator joins on to
+    /// (left, then right).
+    fn glyphs(self) -> [u8; 8] {
+        match self {
+            Border::Single => [0xDA, 0xBF, 0xC0, 0xD9, 0xC4, 0xB3, 0xC3, 0xB4],
+            Border::Double => [0xC9, 0xBB, 0xC8, 0xBC, 0xCD, 0xBA, 0xCC, 0xB9],
+        }
+    }
+}
+
+/// The colours and border widgets are drawn with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Theme {
+    /// For borders, text and anything without the focus
+    pub normal: Attr,
+    /// For the selected item and the focused widget
+    pub highlight: Attr,
+    /// For windows
+    pub border: Border,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            normal: Attr::new(Colour::White, Colour::Blue),
+            highlight: Attr::new(Colour::Black, Colour::Cyan),
+            border: Border::Double,
+        }
+    }
+}
+
+impl Theme {
+    /// For the selected item in a widget without the focus.
+    fn inverse(&self) -> Attr {
+        Attr::new(self.normal.bg(), self.normal.fg())
+    }
+}
+
+/// What a widget did with a key.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Response {
+    /// The widget had no use for the key
+    Ignored,
+    /// The widget used the key, but nothing changed
+    Handled,
+    /// The text or the selection changed
+    Changed,
+    /// Return was pressed on an item, or a button was pressed
+    Activated,
+    /// Escape was pressed
+    Cancelled,
+}
+
+/// Something which can be drawn, and which takes keys when it has the focus.
+pub trait Widget {
+    /// Draw the widget, showing whether it has the focus.
+    fn draw(&mut self, focused: bool);
+
+    /// Deal with a key, pressed while the widget has the focus.
+    fn handle_key(&mut self, key: KeyCode) -> Response;
+}
+
+/// Decodes the keys for `read_key`. It's kept between calls, as it can be
+/// holding on to a key which came in behind an Escape.
+static mut DECODER: KeyDecoder = KeyDecoder::new();
+
+/// Wait for a key, calling `Host::wfvbi` while we wait, and decode any
+/// escape sequence it arrives as. A line feed counts as Return.
+pub fn read_key() -> KeyCode {
+    // Apps only have one thread, so nobody else can be using it
+    let decoder = unsafe { &mut *core::ptr::addr_of_mut!(DECODER) };
+    loop {
+        while Host::kbhit() {
+            match decoder.feed(Host::readc()) {
+                Some(KeyCode(0x0A)) => return RETURN,
+                Some(code) => return code,
+                None => {}
+            }
+        }
+        if let Some(code) = decoder.idle() {
+            return code;
+        }
+        Host::wfvbi();
+    }
+}
+
+/// Draw glyphs along a row, stopping at the edge of the screen. The bottom
+/// right cell is left alone, as writing to it makes the screen scroll.
+fn draw_row<I>(row: u8, col: u8, attr: Attr, glyphs: I)
+where
+    I: IntoIterator<Item = u8>,
+{
+    if row as usize >= NUM_ROWS {
+        return;
+    }
+    let end = if row as usize == NUM_ROWS - 1 {
+        NUM_COLS - 1
+    } else {
+        NUM_COLS
+    };
+    Host::move_cursor(Row(row), Col(col));
+    Host::set_attr(attr);
+    for (_col, glyph) in (col as usize..end).zip(glyphs) {
+        Host::putchar(glyph);
+    }
+}
+
+/// Some text, cut off or padded with spaces to `width` cells.
+fn padded(text: &[u8], width: usize) -> impl Iterator<Item = u8> + '_ {
+    text.iter().cloned().chain(repeat(b' ')).take(width)
+}
+
+/// Draw a button as `[ label ]`.
+fn draw_button(row: u8, col: u8, label: &[u8], attr: Attr) {
+    draw_row(
+        row,
+        col,
+        attr,
+        b"[ ".iter().chain(label).chain(b" ]").cloned(),
+    );
+}
+
+/// How many rows a pop-up `width` cells across can have, if it wants
+/// `height`.
+fn popup_height(width: usize, height: usize) -> usize {
+    height.min(MAX_POPUP_CELLS / width.max(1))
+}
+
+/// The cells in a block of the screen, saved so they can be put back later.
+pub struct Backing {
+    rect: Rect,
+    cells: [Cell; MAX_POPUP_CELLS],
+    cursor: (Row, Col),
+}
+
+impl Backing {
+    /// Save the cells in `rect`, and where the cursor is. Only
+    /// `MAX_POPUP_CELLS` cells can be saved, so if `rect` is bigger than
+    /// that, the rows off the bottom aren't saved. `rect()` says what was.
+    pub fn save(rect: Rect) -> Backing {
+        let mut rect = rect.fit_on_screen();
+        rect.height = popup_height(rect.width as usize, rect.height as usize) as u8;
+        let blank = Cell {
+            glyph: b' ',
+            attr: Attr::from_u8(0),
+        };
+        let mut cells = [blank; MAX_POPUP_CELLS];
+        for y in 0..rect.height {
+            for x in 0..rect.width {
+                let (glyph, attr) = Host::read_char_at(Row(rect.row + y), Col(rect.col + x));
+                cells[y as usize * rect.width as usize + x as usize] = Cell { glyph, attr };
+            }
+        }
+        Backing {
+            rect,
+            cells,
+            cursor: Host::get_cursor(),
+        }
+    }
+
+    /// The block of the screen we saved.
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    /// Put the cells back, and move the cursor back to where it was. The
+    /// colours are left as whatever the last cell needed.
+    pub fn restore(&self) {
+        let rect = self.rect;
+        for y in 0..rect.height {
+            let start = y as usize * rect.width as usize;
+            let cells = &self.cells[start..start + rect.width as usize];
+            let mut current = None;
+            Host::move_cursor(Row(rect.row + y), Col(rect.col));
+            for (x, cell) in cells.iter().enumerate() {
+                let (row, col) = ((rect.row + y) as usize, rect.col as usize + x);
+                if (row, col) == (NUM_ROWS - 1, NUM_COLS - 1) {
+                    break;
+                }
+                if current != Some(cell.attr) {
+                    Host::set_attr(cell.attr);
+                    current = Some(cell.attr);
+                }
+                Host::putchar(cell.glyph);
+            }
+        }
+        Host::move_cursor(self.cursor.0, self.cursor.1);
+    }
+}
+
+/// A box with a border, and maybe a title.
+pub struct Window<'a> {
+    rect: Rect,
+    title: Option<&'a [u8]>,
+    theme: Theme,
+}
+
+impl<'a> Window<'a> {
+    /// Make a window. Nothing is drawn until you call `draw`.
+    pub fn new(rect: Rect, title: Option<&'a [u8]>) -> Window<'a> {
+        Window {
+            rect,
+            title,
+            theme: Theme::default(),
+        }
+    }
+
+    /// Change the colours and border.
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    /// The whole window, border included.
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    /// The part of the window inside the border.
+    pub fn inner(&self) -> Rect {
+        self.rect.inner()
+    }
+
+    /// Draw the border and the title, and blank everything inside.
+    pub fn draw(&self) {
+        let rect = self.rect;
+        if rect.width < 2 || rect.height < 2 {
+            return;
+        }
+        let [top_left, top_right, bottom_left, bottom_right, horizontal, vertical, _, _] =
+            self.theme.border.glyphs();
+        let inner_width = rect.width as usize - 2;
+        let attr = self.theme.normal;
+        draw_row(
+            rect.row,
+            rect.col,
+            attr,
+            once(top_left)
+                .chain(repeat(horizontal).take(inner_width))
+                .chain(once(top_right)),
+        );
+        for y in 1..rect.height - 1 {
+            draw_row(
+                rect.row + y,
+                rect.col,
+                attr,
+                once(vertical)
+                    .chain(repeat(b' ').take(inner_width))
+                    .chain(once(vertical)),
+            );
+        }
+        draw_row(
+            rect.row + rect.height - 1,
+            rect.col,
+            attr,
+            once(bottom_left)
+                .chain(repeat(horizontal).take(inner_width))
+                .chain(once(bottom_right)),
+        );
+        if let Some(title) = self.title {
+            if inner_width >= 3 {
+                let title = &title[..title.len().min(inner_width - 2)];
+                let col = rect.col as usize + 1 + (inner_width - title.len() - 2) / 2;
+                draw_row(
+                    rect.row,
+                    col as u8,
+                    attr,
+                    once(b' ').chain(title.iter().cloned()).chain(once(b' ')),
+                );
+            }
+        }
+    }
+
+    /// Write some text inside the window, where (0, 0) is the top left cell
+    /// inside the border. Anything which doesn't fit is cut off.
+    pub fn print(&self, row: u8, col: u8, text: &[u8]) {
+        let inner = self.inner();
+        if row >= inner.height || col >= inner.width {
+            return;
+        }
+        let width = (inner.width - col) as usize;
+        draw_row(
+            inner.row + row,
+            inner.col + col,
+            self.theme.normal,
+            text.iter().cloned().take(width),
+        );
+    }
+
+    /// Draw a line across the window, joined to the border, on the given row
+    /// inside the window.
+    pub fn separator(&self, row: u8) {
+        let inner = self.inner();
+        if row >= inner.height {
+            return;
+        }
+        let [_, _, _, _, horizontal, _, left, right] = self.theme.border.glyphs();
+        draw_row(
+            inner.row + row,
+            self.rect.col,
+            self.theme.normal,
+            once(left)
+                .chain(repeat(horizontal).take(inner.width as usize))
+                .chain(once(right)),
+        );
+    }
+}
+
+/// A scrolling list of items, one of which is selected.
+///
+/// Up, Down, Page Up, Page Down, Home and End move the selection, as does
+/// typing the first letter of an item. Return activates the selected item.
+pub struct ListView<'a, T> {
+    rect: Rect,
+    items: &'a [T],
+    selected: usize,
+    /// The item on the top row.
+    top: usize,
+    theme: Theme,
+}
+
+impl<'a, T> ListView<'a, T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Make a list which fills `rect`, with the first item selected.
+    pub fn new(rect: Rect, items: &'a [T]) -> ListView<'a, T> {
+        ListView {
+            rect,
+            items,
+            selected: 0,
+            top: 0,
+            theme: Theme::default(),
+        }
+    }
+
+    /// Change the colours.
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    /// The selected item, or `None` if the list is empty.
+    pub fn selected(&self) -> Option<usize> {
+        if self.items.is_empty() {
+            None
+        } else {
+            Some(self.selected)
+        }
+    }
+
+    /// Select an item. Items which don't exist are ignored.
+    pub fn set_selected(&mut self, idx: usize) {
+        if idx < self.items.len() {
+            self.selected = idx;
+        }
+    }
+
+    fn select(&mut self, idx: usize) -> Response {
+        let idx = idx.min(self.items.len() - 1);
+        if idx == self.selected {
+            Response::Ignored
+        } else {
+            self.selected = idx;
+            Response::Changed
+        }
+    }
+
+    /// Select the next item which starts with `letter`, going round to the
+    /// top if we have to.
+    fn select_letter(&mut self, letter: u8) -> Response {
+        let len = self.items.len();
+        let found = (1..=len)
+            .map(|offset| (self.selected + offset) % len)
+            .find(|&idx| {
+                self.items[idx]
+                    .as_ref()
+                    .first()
+                    .map(|first| first.eq_ignore_ascii_case(&letter))
+                    .unwrap_or(false)
+            });
+        match found {
+            Some(idx) => match self.select(idx) {
+                Response::Ignored => Response::Handled,
+                response => response,
+            },
+            None => Response::Ignored,
+        }
+    }
+}
+
+impl<'a, T> Widget for ListView<'a, T>
+where
+    T: AsRef<[u8]>,
+{
+    fn draw(&mut self, focused: bool) {
+        let height = self.rect.height as usize;
+        if height == 0 {
+            return;
+        }
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + height {
+            self.top = self.selected + 1 - height;
+        }
+        for y in 0..height {
+            let idx = self.top + y;
+            let attr = if idx != self.selected || self.items.is_empty() {
+                self.theme.normal
+            } else if focused {
+                self.theme.highlight
+            } else {
+                self.theme.inverse()
+            };
+            let text = self.items.get(idx).map(|item| item.as_ref()).unwrap_or(b"");
+            draw_row(
+                self.rect.row + y as u8,
+                self.rect.col,
+                attr,
+                padded(text, self.rect.width as usize),
+            );
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyCode) -> Response {
+        if self.items.is_empty() {
+            return Response::Ignored;
+        }
+        let page = (self.rect.height as usize).max(1);
+        match key {
+            KeyCode::UP => self.select(self.selected.saturating_sub(1)),
+            KeyCode::DOWN => self.select(self.selected + 1),
+            KeyCode::PAGE_UP => self.select(self.selected.saturating_sub(page)),
+            KeyCode::PAGE_DOWN => self.select(self.selected + page),
+            KeyCode::HOME => self.select(0),
+            KeyCode::END => self.select(self.items.len() - 1),
+            RETURN => Response::Activated,
+            KeyCode(letter @ 0x21..=0x7E) => self.select_letter(letter as u8),
+            _ => Response::Ignored,
+        }
+    }
+}
+
+/// A box to type some text into, using a `LineEditor`.
+pub struct TextField<'a> {
+    editor: LineEditor<'a>,
+    theme: Theme,
+}
+
+impl<'a> TextField<'a> {
+    /// Make a field `width` cells across, editing the text in `buffer`, the
+    /// first `len` bytes of which are the starting text.
+    pub fn new(row: u8, col: u8, width: u8, buffer: &'a mut [u8], len: usize) -> TextField<'a> {
+        let mut editor = LineEditor::at(Row(row), Col(col), buffer, len);
+        editor.set_width(width as usize);
+        TextField {
+            editor,
+            theme: Theme::default(),
+        }
+    }
+
+    /// Change the colours.
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    /// Stop the text growing past `max_len` bytes.
+    pub fn set_max_len(&mut self, max_len: usize) {
+        self.editor.set_max_len(max_len);
+    }
+
+    /// The text so far.
+    pub fn text(&self) -> &[u8] {
+        self.editor.text()
+    }
+}
+
+impl<'a> Widget for TextField<'a> {
+    /// Shows the cursor in the field if it has the focus.
+    fn draw(&mut self, focused: bool) {
+        Host::set_attr(if focused {
+            self.theme.highlight
+        } else {
+            self.theme.normal
+        });
+        self.editor.draw();
+        if focused {
+            Host::set_cursor_visible(true);
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyCode) -> Response {
+        match key {
+            TAB | RETURN | ESCAPE | KeyCode::UP | KeyCode::DOWN => Response::Ignored,
+            _ => {
+                self.editor.handle_keycode(key);
+                Response::Changed
+            }
+        }
+    }
+}
+
+/// A button, drawn as `[ label ]`. Return or Space presses it.
+pub struct Button<'a> {
+    row: u8,
+    col: u8,
+    label: &'a [u8],
+    theme: Theme,
+}
+
+impl<'a> Button<'a> {
+    /// Make a button.
+    pub fn new(row: u8, col: u8, label: &'a [u8]) -> Button<'a> {
+        Button {
+            row,
+            col,
+            label,
+            theme: Theme::default(),
+        }
+    }
+
+    /// Change the colours.
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+}
+
+impl<'a> Widget for Button<'a> {
+    fn draw(&mut self, focused: bool) {
+        let attr = if focused {
+            self.theme.highlight
+        } else {
+            self.theme.normal
+        };
+        draw_button(self.row, self.col, self.label, attr);
+    }
+
+    fn handle_key(&mut self, key: KeyCode) -> Response {
+        match key {
+            RETURN | SPACE => Response::Activated,
+            _ => Response::Ignored,
+        }
+    }
+}
+
+/// A set of widgets, one of which has the focus.
+pub struct Form<'a, 'w> {
+    widgets: &'a mut [&'w mut dyn Widget],
+    focus: usize,
+}
+
+impl<'a, 'w> Form<'a, 'w> {
+    /// Make a form, with the focus on the first widget.
+    pub fn new(widgets: &'a mut [&'w mut dyn Widget]) -> Form<'a, 'w> {
+        Form { widgets, focus: 0 }
+    }
+
+    /// Which widget has the focus.
+    pub fn focus(&self) -> usize {
+        self.focus
+    }
+
+    /// Give a widget the focus. Widgets which don't exist are ignored.
+    pub fn set_focus(&mut self, idx: usize) {
+        if idx < self.widgets.len() {
+            self.focus = idx;
+        }
+    }
+
+    /// Draw every widget. The screen's cursor is hidden, unless the focused
+    /// widget shows it.
+    pub fn draw(&mut self) {
+        Host::set_cursor_visible(false);
+        let focus = self.focus;
+        for (idx, widget) in self.widgets.iter_mut().enumerate() {
+            if idx != focus {
+                widget.draw(false);
+            }
+        }
+        if let Some(widget) = self.widgets.get_mut(focus) {
+            widget.draw(true);
+        }
+    }
+
+    /// Give a key to the focused widget. If it doesn't want it, Tab, Return,
+    /// Down and Right move the focus on, Up and Left move it back, and
+    /// Escape cancels the form.
+    pub fn handle_key(&mut self, key: KeyCode) -> Response {
+        let count = self.widgets.len();
+        if count == 0 {
+            return Response::Ignored;
+        }
+        match self.widgets[self.focus].handle_key(key) {
+            Response::Ignored => {}
+            response => return response,
+        }
+        match key {
+            TAB | RETURN | KeyCode::DOWN | KeyCode::RIGHT => {
+                self.focus = (self.focus + 1) % count;
+                Response::Handled
+            }
+            KeyCode::UP | KeyCode::LEFT => {
+                self.focus = (self.focus + count - 1) % count;
+                Response::Handled
+            }
+            ESCAPE => Response::Cancelled,
+            _ => Response::Ignored,
+        }
+    }
+
+    /// Draw the form and hand it keys until a widget is activated, returning
+    /// which one, or until Escape is pressed, returning `None`.
+    pub fn run(&mut self) -> Option<usize> {
+        loop {
+            self.draw();
+            match self.handle_key(read_key()) {
+                Response::Activated => return Some(self.focus),
+                Response::Cancelled => return None,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Pop up a menu with its top left corner at (`row`, `col`), or as near as
+/// will fit. Returns the item chosen, or `None` if Escape was pressed. The
+/// screen is put back afterwards, but the cursor is left hidden.
+pub fn menu<T>(row: u8, col: u8, title: Option<&[u8]>, items: &[T], theme: &Theme) -> Option<usize>
+where
+    T: AsRef<[u8]>,
+{
+    if items.is_empty() {
+        return None;
+    }
+    let item_width = items
+        .iter()
+        .map(|item| item.as_ref().len())
+        .max()
+        .unwrap_or(0);
+    let title_width = title.map(|title| title.len() + 2).unwrap_or(0);
+    // A space either side of the items, inside the border
+    let width = item_width.max(title_width) + 4;
+    let width = width.min(NUM_COLS);
+    let height = popup_height(width, items.len() + 2);
+    let rect = Rect::new(row, col, width as u8, height.min(NUM_ROWS) as u8).fit_on_screen();
+    let backing = Backing::save(rect);
+    let mut window = Window::new(rect, title);
+    window.set_theme(*theme);
+    window.draw();
+    let inner = window.inner();
+    let area = Rect::new(
+        inner.row,
+        inner.col + 1,
+        inner.width.saturating_sub(2),
+        inner.height,
+    );
+    let mut list = ListView::new(area, items);
+    list.set_theme(*theme);
+    Host::set_cursor_visible(false);
+    let result = loop {
+        list.draw(true);
+        match read_key() {
+            ESCAPE => break None,
+            key => {
+                if list.handle_key(key) == Response::Activated {
+                    break list.selected();
+                }
+            }
+        }
+    };
+    backing.restore();
+    result
+}
+
+/// Pop up a box in the middle of the screen showing `text` (split into lines
+/// at each `\n`), with a row of buttons underneath. Returns the button
+/// pressed, or `None` if Escape was pressed. With no buttons, Return closes
+/// the box too. The screen is put back afterwards, but the cursor is left
+/// hidden.
+pub fn message_box<T>(
+    title: Option<&[u8]>,
+    text: &[u8],
+    buttons: &[T],
+    theme: &Theme,
+) -> Option<usize>
+where
+    T: AsRef<[u8]>,
+{
+    let lines = text.split(|&b| b == b'\n');
+    let text_width = lines.clone().map(|line| line.len()).max().unwrap_or(0);
+    let num_lines = lines.clone().count();
+    // Each button is `[ label ]`, with a space between buttons
+    let buttons_width = buttons
+        .iter()
+        .map(|button| button.as_ref().len() + 5)
+        .sum::<usize>()
+        .saturating_sub(1);
+    let title_width = title.map(|title| title.len() + 2).unwrap_or(0);
+    let width = text_width.max(buttons_width).max(title_width) + 4;
+    // Room for a blank line and the buttons, if there are any
+    let button_rows = if buttons.is_empty() { 0 } else { 2 };
+    let width = width.min(NUM_COLS);
+    let height = popup_height(width, num_lines + button_rows + 2);
+    let rect = Rect::centred(width as u8, height.min(NUM_ROWS) as u8);
+    let backing = Backing::save(rect);
+    let mut window = Window::new(rect, title);
+    window.set_theme(*theme);
+    window.draw();
+    let inner = window.inner();
+    // Lines which don't fit are cut off
+    let text_rows = (inner.height as usize).saturating_sub(button_rows);
+    for (y, line) in lines.take(text_rows).enumerate() {
+        window.print(y as u8, 1, line);
+    }
+    let button_row = inner.row + inner.height.saturating_sub(1);
+    let mut focus = 0;
+    Host::set_cursor_visible(false);
+    let result = loop {
+        let mut col = inner.col as usize + (inner.width as usize).saturating_sub(buttons_width) / 2;
+        for (idx, button) in buttons.iter().enumerate() {
+            let attr = if idx == focus {
+                theme.highlight
+            } else {
+                theme.normal
+            };
+            draw_button(button_row, col as u8, button.as_ref(), attr);
+            col += button.as_ref().len() + 5;
+        }
+        match read_key() {
+            ESCAPE => break None,
+            RETURN | SPACE if buttons.is_empty() => break None,
+            RETURN | SPACE => break Some(focus),
+            KeyCode::LEFT | KeyCode::UP if !buttons.is_empty() => {
+                focus = (focus + buttons.len() - 1) % buttons.len();
+            }
+            TAB | KeyCode::RIGHT | KeyCode::DOWN if !buttons.is_empty() => {
+                focus = (focus + 1) % buttons.len();
+            }
+            _ => {}
+        }
+    };
+    backing.restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ITEMS: [&[u8]; 6] = [
+        b"Apple", b"banana", b"Cherry", b"apricot", b"Damson", b"Elder",
+    ];
+
+    #[test]
+    fn rects() {
+        assert_eq!(Rect::new(2, 3, 10, 5).inner(), Rect::new(3, 4, 8, 3));
+        assert_eq!(Rect::new(255, 255, 1, 0).inner(), Rect::new(255, 255, 0, 0));
+        assert_eq!(Rect::centred(10, 6), Rect::new(15, 19, 10, 6));
+        assert_eq!(Rect::centred(200, 200), Rect::new(0, 0, 48, 36));
+    }
+
+    #[test]
+    fn fit_on_screen() {
+        let on_screen = Rect::new(1, 2, 10, 5);
+        assert_eq!(on_screen.fit_on_screen(), on_screen);
+        assert_eq!(
+            Rect::new(34, 45, 10, 5).fit_on_screen(),
+            Rect::new(31, 38, 10, 5)
+        );
+        assert_eq!(
+            Rect::new(255, 255, 255, 255).fit_on_screen(),
+            Rect::new(0, 0, 48, 36)
+        );
+    }
+
+    #[test]
+    fn popup_sizes() {
+        assert_eq!(popup_height(20, 10), 10);
+        assert_eq!(popup_height(48, 36), 12);
+        assert_eq!(popup_height(1, 36), 36);
+        assert_eq!(popup_height(0, 36), 36);
+        assert_eq!(popup_height(MAX_POPUP_CELLS, 5), 1);
+        // Saving a whole screen only saves the top rows
+        let backing = Backing::save(Rect::new(0, 0, 255, 255));
+        assert_eq!(backing.rect(), Rect::new(0, 0, 48, 12));
+        let backing = Backing::save(Rect::new(30, 40, 20, 20));
+        assert_eq!(backing.rect(), Rect::new(16, 28, 20, 20));
+    }
+
+    #[test]
+    fn list_paging() {
+        let mut list = ListView::new(Rect::new(0, 0, 10, 4), &ITEMS);
+        assert_eq!(list.handle_key(KeyCode::UP), Response::Ignored);
+        assert_eq!(list.handle_key(KeyCode::DOWN), Response::Changed);
+        assert_eq!(list.selected(), Some(1));
+        assert_eq!(list.handle_key(KeyCode::PAGE_DOWN), Response::Changed);
+        assert_eq!(list.selected(), Some(5));
+        assert_eq!(list.handle_key(KeyCode::PAGE_DOWN), Response::Ignored);
+        assert_eq!(list.handle_key(KeyCode::DOWN), Response::Ignored);
+        assert_eq!(list.handle_key(KeyCode::PAGE_UP), Response::Changed);
+        assert_eq!(list.selected(), Some(1));
+        assert_eq!(list.handle_key(KeyCode::PAGE_UP), Response::Changed);
+        assert_eq!(list.selected(), Some(0));
+        assert_eq!(list.handle_key(KeyCode::END), Response::Changed);
+        assert_eq!(list.selected(), Some(5));
+        assert_eq!(list.handle_key(KeyCode::HOME), Response::Changed);
+        assert_eq!(list.selected(), Some(0));
+        assert_eq!(list.handle_key(RETURN), Response::Activated);
+    }
+
+    #[test]
+    fn list_letters() {
+        let mut list = ListView::new(Rect::new(0, 0, 10, 4), &ITEMS);
+        // Either case matches, and we go round to the top
+        assert_eq!(list.handle_key(KeyCode(u16::from(b'a'))), Response::Changed);
+        assert_eq!(list.selected(), Some(3));
+        assert_eq!(list.handle_key(KeyCode(u16::from(b'A'))), Response::Changed);
+        assert_eq!(list.selected(), Some(0));
+        assert_eq!(list.handle_key(KeyCode(u16::from(b'e'))), Response::Changed);
+        assert_eq!(list.selected(), Some(5));
+        // The only match is the one already selected
+        assert_eq!(list.handle_key(KeyCode(u16::from(b'E'))), Response::Handled);
+        assert_eq!(list.handle_key(KeyCode(u16::from(b'z'))), Response::Ignored);
+        assert_eq!(list.selected(), Some(5));
+    }
+
+    #[test]
+    fn empty_list() {
+        let items: [&[u8]; 0] = [];
+        let mut list = ListView::new(Rect::new(0, 0, 10, 0), &items);
+        assert_eq!(list.selected(), None);
+        for &key in &[
+            KeyCode::DOWN,
+            KeyCode::END,
+            KeyCode::PAGE_DOWN,
+            RETURN,
+            KeyCode(0x61),
+        ] {
+            assert_eq!(list.handle_key(key), Response::Ignored);
+        }
+        list.set_selected(2);
+        assert_eq!(list.selected(), None);
+    }
+
+    #[test]
+    fn form_focus() {
+        let mut list = ListView::new(Rect::new(0, 0, 10, 4), &ITEMS[..2]);
+        let mut ok = Button::new(5, 0, b"OK");
+        let mut cancel = Button::new(5, 8, b"Cancel");
+        let mut widgets: [&mut dyn Widget; 3] = [&mut list, &mut ok, &mut cancel];
+        let mut form = Form::new(&mut widgets);
+        // The list uses Down until it gets to the end
+        assert_eq!(form.handle_key(KeyCode::DOWN), Response::Changed);
+        assert_eq!(form.focus(), 0);
+        assert_eq!(form.handle_key(KeyCode::DOWN), Response::Handled);
+        assert_eq!(form.focus(), 1);
+        assert_eq!(form.handle_key(TAB), Response::Handled);
+        assert_eq!(form.focus(), 2);
+        assert_eq!(form.handle_key(KeyCode::RIGHT), Response::Handled);
+        assert_eq!(form.focus(), 0);
+        assert_eq!(form.handle_key(KeyCode::LEFT), Response::Handled);
+        assert_eq!(form.focus(), 2);
+        assert_eq!(form.handle_key(RETURN), Response::Activated);
+        assert_eq!(form.handle_key(KeyCode::UP), Response::Handled);
+        assert_eq!(form.focus(), 1);
+        assert_eq!(form.handle_key(ESCAPE), Response::Cancelled);
+        form.set_focus(3);
+        assert_eq!(form.focus(), 1);
+
+        let mut none: [&mut dyn Widget; 0] = [];
+        assert_eq!(Form::new(&mut none).handle_key(TAB), Response::Ignored);
+    }
+}